@@ -13,6 +13,9 @@ fn main() {
         .get(RequestPath::Explicit("/index"), simple_redirect)
         .get(RequestPath::Explicit("/fail_check"), simple_redir_fail);
 
+    // simple redirects don't need a handler, and the query string will be carried over
+    server.redirect("/home", "/index", 302);
+
     server.listen(8080);
 }
 
@@ -29,6 +32,6 @@ pub fn simple_redirect(_req: &Box<Request>, resp: &mut Box<Response>) {
 }
 
 pub fn simple_redir_fail(_req: &Box<Request>, resp: &mut Box<Response>) {
-    //call redirect
-    resp.redirect("/fail");
+    //call redirect, which is temporary and keeps the query string
+    resp.redirect_with("/fail", 307, true);
 }
@@ -0,0 +1,113 @@
+//! The authorization policies, which can be attached to a route or to a whole router, and decide if
+//! the request can be served before the handler is invoked. Unlike the global `AuthFunc`, a policy
+//! can ask the client to authenticate, deny the access, or send the client to the login page, and
+//! pass the authenticated principal on to the handler.
+
+use crate::core::http::{Request, Response, ResponseWriter};
+
+/// The decision made by an `AuthPolicy` on the request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthDecision {
+    /// The request is allowed, with the authenticated principal if known, which can be read from
+    /// `Request::principal` in the handler.
+    Allow(Option<String>),
+    /// The client shall authenticate first, and the response will be a `401 Unauthorized` with the
+    /// challenge as the `WWW-Authenticate` header, e.g. `Basic realm="admin"`.
+    Unauthorized(String),
+    /// The client is known but not allowed, and the response will be a `403 Forbidden`.
+    Forbidden,
+    /// The client shall be sent to the location, e.g. the login page, with a `302 Found`.
+    Redirect(String),
+}
+
+/// `AuthPolicy` is a type alias to the authorization checks, see `Router::authorized` for the policy
+/// of a route, and `Router::policy` for the policy of a router.
+///
+/// # Example
+///
+/// ```
+/// use rusty_express::prelude::*;
+///
+/// let mut server = HttpServer::new();
+/// server.authorized(REST::GET, RequestPath::Explicit("/admin"), admin_only, admin_handler);
+///
+/// pub fn admin_only(req: &Request) -> AuthDecision {
+///     match req.header("x-user") {
+///         Some(ref user) if user == "admin" => AuthDecision::Allow(Some(user.to_owned())),
+///         Some(_) => AuthDecision::Forbidden,
+///         None => AuthDecision::Unauthorized(String::from("Basic realm=\"admin\"")),
+///     }
+/// }
+///
+/// pub fn admin_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+///     resp.send(&format!("Welcome, {}", req.principal().unwrap_or_default()));
+/// }
+/// ```
+pub type AuthPolicy = fn(&Request) -> AuthDecision;
+
+/// Check the request against the policies in order, where the principal given by a later policy
+/// replaces the earlier one. Returns `false` if any policy turns the request down, in which case the
+/// response has been set up accordingly.
+pub(crate) fn check(
+    policies: &[AuthPolicy],
+    req: &mut Box<Request>,
+    resp: &mut Box<Response>,
+) -> bool {
+    for policy in policies.iter() {
+        match policy(req) {
+            AuthDecision::Allow(principal) => {
+                if principal.is_some() {
+                    req.set_principal(principal);
+                }
+            }
+            AuthDecision::Unauthorized(challenge) => {
+                resp.status(401);
+                resp.set_header("WWW-Authenticate", &challenge);
+
+                return false;
+            }
+            AuthDecision::Forbidden => {
+                resp.status(403);
+                return false;
+            }
+            AuthDecision::Redirect(location) => {
+                resp.redirect_with(&location, 302, false);
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod auth_test {
+    use super::*;
+    use crate::core::http::ResponseStates;
+
+    fn allow_alice(_: &Request) -> AuthDecision {
+        AuthDecision::Allow(Some(String::from("alice")))
+    }
+
+    fn challenge(_: &Request) -> AuthDecision {
+        AuthDecision::Unauthorized(String::from("Basic realm=\"admin\""))
+    }
+
+    #[test]
+    fn check_test() {
+        let mut req = Box::new(Request::new());
+        let mut resp = Box::new(Response::new());
+
+        assert!(check(&[allow_alice], &mut req, &mut resp));
+        assert_eq!(req.principal(), Some("alice"));
+
+        // the policies are checked in order, and the first one turning the request down wins
+        let mut req = Box::new(Request::new());
+        assert!(!check(&[challenge, allow_alice], &mut req, &mut resp));
+        assert!(req.principal().is_none());
+        assert_eq!(
+            resp.get_header("www-authenticate").map(String::as_str),
+            Some("Basic realm=\"admin\"")
+        );
+    }
+}
@@ -1,32 +1,25 @@
 #![allow(dead_code)]
 
 use std::cmp;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, Read};
 use std::ops::Div;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::hashbrown::HashMap;
+use crate::core::instance::{self, Instance};
+use crate::core::router::{self, RouteCacheStats};
+use crate::hashbrown::{HashMap, HashSet};
 use crate::num_cpus;
-use crate::parking_lot::RwLock;
+use crate::support::cache::{self, FileCacheStats};
 use crate::support::common::*;
+use crate::support::limits::{Limits, OverflowPolicy};
+use crate::support::mime;
 use native_tls::{Identity, TlsAcceptor};
-use std::mem::MaybeUninit;
 
 //TODO: load config from file, e.g. config.toml?
 
-/*
-lazy_static! {
-    static ref VIEW_ENGINES: RwLock<HashMap<String, Box<ViewEngine>>> = RwLock::new(HashMap::new());
-    static ref METADATA_STORE: RwLock<ConnMetadata> = RwLock::new(ConnMetadata::new());
-}
-*/
-
-static mut VIEW_ENGINES: MaybeUninit<RwLock<HashMap<String, Box<ViewEngine>>>> =
-    MaybeUninit::uninit();
-static mut METADATA_STORE: MaybeUninit<RwLock<ConnMetadata>> = MaybeUninit::uninit();
-
 pub struct ServerConfig {
     pool_size: usize,
     read_timeout: u16,
@@ -35,6 +28,12 @@ pub struct ServerConfig {
     tls_path: &'static str,
     use_session_autoclean: bool,
     session_auto_clean_period: Option<Duration>,
+    file_cache: (usize, usize),
+    route_cache: usize,
+    shutdown_timeout: Duration,
+    max_connections: usize,
+    max_connections_per_ip: usize,
+    overflow_policy: OverflowPolicy,
 }
 
 impl ServerConfig {
@@ -88,6 +87,67 @@ impl ServerConfig {
         self.read_limit
     }
 
+    /// On `ControlMessage::Terminate`, the server stops accepting new connections, and tells the
+    /// clients on the keep-alive connections to close with their next responses. It then waits for
+    /// the requests in flight to complete, up to the timeout, before closing the connections left by
+    /// force and shutting down. Default to 30 seconds, and setting to 0 will close the connections
+    /// right away.
+    #[inline]
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    #[inline]
+    pub fn get_shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// The maximum number of connections served at the same time, and the new connections beyond it
+    /// are handled with the overflow policy, see `set_overflow_policy`. Default to 0, which means
+    /// no limit.
+    #[inline]
+    pub fn set_max_connections(&mut self, max: usize) {
+        self.max_connections = max;
+    }
+
+    #[inline]
+    pub fn get_max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// The maximum number of connections served at the same time for each client IP. Default to 0,
+    /// which means no limit.
+    #[inline]
+    pub fn set_max_connections_per_ip(&mut self, max: usize) {
+        self.max_connections_per_ip = max;
+    }
+
+    #[inline]
+    pub fn get_max_connections_per_ip(&self) -> usize {
+        self.max_connections_per_ip
+    }
+
+    /// What to do with the new connections when the server is at its connection limits, default to
+    /// responding with a `503 Service Unavailable` right away.
+    #[inline]
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    #[inline]
+    pub fn get_overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    #[inline]
+    pub(crate) fn get_connection_limits(&self) -> Limits {
+        Limits {
+            max: self.max_connections,
+            max_per_ip: self.max_connections_per_ip,
+            policy: self.overflow_policy,
+        }
+    }
+
     #[inline]
     pub fn set_session_auto_clean(&mut self, auto_clean: bool) {
         self.use_session_autoclean = auto_clean;
@@ -121,7 +181,13 @@ impl ServerConfig {
 
         // create the acceptor using the provided identity
         let identity = Identity::from_pkcs12(&content, "hunter2").unwrap();
-        let acceptor = Arc::new(TlsAcceptor::new(identity).unwrap());
+        let mut builder = TlsAcceptor::builder(identity);
+
+        // advertise h2 via ALPN, and the client will fallback to http/1.1 if it can't speak h2
+        #[cfg(feature = "http2")]
+        builder.accept_alpn(&["h2", "http/1.1"]);
+
+        let acceptor = Arc::new(builder.build().unwrap());
         self.tls_path = "";
 
         Some(acceptor)
@@ -141,23 +207,146 @@ impl ServerConfig {
         self.session_auto_clean_period = Some(auto_clean_sec);
     }
 
+    /// Cache the static files in memory, up to `max_bytes` in total, and only the files no larger
+    /// than `max_file_size` will be cached. The least recently used files will be evicted first when
+    /// the cache is full, and a cached file will be reloaded if it's been modified since. Setting
+    /// `max_bytes` to 0 will turn off the cache, which is the default.
+    ///
+    /// Note that large files are streamed to the client directly, and hence never cached.
+    #[inline]
+    pub fn use_file_cache(&mut self, max_bytes: usize, max_file_size: usize) {
+        self.file_cache = (max_bytes, max_file_size);
+    }
+
+    #[inline]
+    pub fn get_file_cache(&self) -> (usize, usize) {
+        self.file_cache
+    }
+
+    /// Get the statistics of the static file cache, or `None` if the cache is not in use.
+    pub fn file_cache_stats() -> Option<FileCacheStats> {
+        cache::stats()
+    }
+
+    /// Drop all files from the static file cache, e.g. after deploying new static contents.
+    pub fn clear_file_cache() {
+        cache::clear();
+    }
+
+    /// Cache up to `capacity` route lookups, such that the requests to the hot paths don't have to
+    /// go through all the routes every time. The least recently used routes are evicted first when
+    /// the cache is full, and the cache is dropped whenever the routes are changed. Default to 1024,
+    /// and setting to 0 will turn off the cache.
+    #[inline]
+    pub fn use_route_cache(&mut self, capacity: usize) {
+        self.route_cache = capacity;
+    }
+
+    #[inline]
+    pub fn get_route_cache(&self) -> usize {
+        self.route_cache
+    }
+
+    /// Get the statistics of the route lookup cache, e.g. its hit rate.
+    pub fn route_cache_stats() -> RouteCacheStats {
+        router::cache_stats()
+    }
+
+    pub fn clear_route_cache() {
+        router::clear_cache();
+    }
+
     pub fn use_default_header(header: HashMap<String, String>) {
-        let mut store = Self::metadata().write();
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
         (*store).header = header;
     }
 
     pub fn set_default_header(field: String, value: String, replace: bool) {
-        let mut store = Self::metadata().write();
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
         (*store).header.add(&field[..], value, replace, false);
     }
 
     pub fn set_status_page_generator(status: u16, generator: PageGenerator) {
         if status > 0 {
-            let mut store = Self::metadata().write();
+            let instance = ServerConfig::instance();
+            let mut store = instance.metadata.write();
             (*store).status_page_generators.insert(status, generator);
         }
     }
 
+    /// Allow redirecting to absolute URLs on the given host, e.g. `example.com`, or all of its
+    /// sub-domains if the host is given as `*.example.com`. Redirecting to a host that is not in the
+    /// allowlist will be rejected with a `403 Forbidden` response.
+    pub fn allow_redirect_host(host: &str) {
+        let host = host.trim().to_lowercase();
+        if host.is_empty() {
+            return;
+        }
+
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.redirect_hosts.insert(host);
+    }
+
+    pub fn disallow_redirect_host(host: &str) {
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.redirect_hosts.remove(&host.trim().to_lowercase());
+    }
+
+    /// Map the file extension to the MIME type, which will override the built-in mapping of the
+    /// extension, if any, e.g. `ServerConfig::set_mime_type("md", "text/x-markdown")`.
+    pub fn set_mime_type(ext: &str, mime_type: &str) {
+        let ext = ext.trim().trim_start_matches('.').to_lowercase();
+        if ext.is_empty() || mime_type.is_empty() {
+            return;
+        }
+
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.mime_types.insert(ext, mime_type.to_owned());
+    }
+
+    /// Remove the custom mapping of the file extension, and the built-in one will be used again.
+    pub fn remove_mime_type(ext: &str) {
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store
+            .mime_types
+            .remove(&ext.trim().trim_start_matches('.').to_lowercase());
+    }
+
+    /// Load the mappings from a file in the `mime.types` format, e.g. `/etc/mime.types`, where each
+    /// line is a MIME type followed by its file extensions. Return the number of mappings loaded.
+    pub fn load_mime_types<P: AsRef<Path>>(path: P) -> io::Result<usize> {
+        let mappings = mime::parse(&fs::read_to_string(path)?);
+        let count = mappings.len();
+
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.mime_types.extend(mappings);
+
+        Ok(count)
+    }
+
+    /// The charset to be added to the textual MIME types, e.g. `text/html; charset=utf-8`. Default
+    /// to `utf-8`, and an empty charset will leave the MIME types as is.
+    pub fn set_default_charset(charset: &str) {
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.charset = charset.trim().to_owned();
+    }
+
+    /// Guess the MIME type of the files without an extension from their leading bytes, instead of
+    /// serving them as `text/plain`. Default to `false`.
+    pub fn set_mime_sniffing(enable: bool) {
+        let instance = ServerConfig::instance();
+        let mut store = instance.metadata.write();
+        store.mime_sniffing = enable;
+    }
+
     pub(crate) fn load_server_params(&self) -> (u64, u64, usize) {
         (
             u64::from(self.get_read_timeout()),
@@ -166,25 +355,15 @@ impl ServerConfig {
         )
     }
 
+    /// The instance holding the metadata and the view engines, see `ConnMetadata`.
     #[inline]
-    fn metadata<'a>() -> &'a mut RwLock<ConnMetadata> {
-        unsafe { &mut *METADATA_STORE.as_mut_ptr() }
-    }
-
-    fn view_engines<'a>() -> &'a mut RwLock<HashMap<String, Box<ViewEngine>>> {
-        unsafe { &mut *VIEW_ENGINES.as_mut_ptr() }
+    fn instance() -> Arc<Instance> {
+        instance::current()
     }
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
-        unsafe {
-            VIEW_ENGINES.as_mut_ptr().write(RwLock::new(HashMap::new()));
-            METADATA_STORE
-                .as_mut_ptr()
-                .write(RwLock::new(ConnMetadata::new()));
-        }
-
         let path = option_env!("TLS_PATH").unwrap_or("");
 
         ServerConfig {
@@ -195,6 +374,12 @@ impl Default for ServerConfig {
             tls_path: path,
             use_session_autoclean: false,
             session_auto_clean_period: Some(Duration::from_secs(3600)),
+            file_cache: (0, 0),
+            route_cache: 1024,
+            shutdown_timeout: Duration::from_secs(30),
+            max_connections: 0,
+            max_connections_per_ip: 0,
+            overflow_policy: OverflowPolicy::Reject,
         }
     }
 }
@@ -251,7 +436,8 @@ impl ViewEngineDefinition for ServerConfig {
             return;
         }
 
-        let mut engines = ServerConfig::view_engines().write();
+        let instance = ServerConfig::instance();
+        let mut engines = instance.view_engines.write();
         (*engines).insert(extension.to_owned(), Box::new(engine));
     }
 }
@@ -276,7 +462,7 @@ impl ViewEngineParser for ServerConfig {
 
         match String::from_utf8(source) {
             Ok(mut s) => {
-                if let Some(engine) = ServerConfig::view_engines().read().get(extension) {
+                if let Some(engine) = ServerConfig::instance().view_engines.read().get(extension) {
                     let code = engine(&mut s, context);
                     return (code, Vec::from(s.as_bytes()));
                 }
@@ -293,6 +479,10 @@ pub type PageGenerator = fn() -> String;
 pub struct ConnMetadata {
     header: HashMap<String, String>,
     status_page_generators: HashMap<u16, PageGenerator>,
+    redirect_hosts: HashSet<String>,
+    mime_types: HashMap<String, String>,
+    charset: String,
+    mime_sniffing: bool,
 }
 
 impl ConnMetadata {
@@ -300,12 +490,17 @@ impl ConnMetadata {
         ConnMetadata {
             header: HashMap::new(),
             status_page_generators: HashMap::new(),
+            redirect_hosts: HashSet::new(),
+            mime_types: HashMap::new(),
+            charset: String::from("utf-8"),
+            mime_sniffing: false,
         }
     }
 
     #[inline]
     pub fn get_default_header() -> Option<HashMap<String, String>> {
-        let store = ServerConfig::metadata().read();
+        let instance = ServerConfig::instance();
+        let store = instance.metadata.read();
         if !store.header.is_empty() {
             return Some(store.header.clone());
         }
@@ -315,11 +510,80 @@ impl ConnMetadata {
 
     #[inline]
     pub(crate) fn get_status_pages(status: u16) -> Option<PageGenerator> {
-        let store = ServerConfig::metadata().read();
+        let instance = ServerConfig::instance();
+        let store = instance.metadata.read();
         if store.status_page_generators.is_empty() {
             return None;
         }
 
         store.status_page_generators.get(&status).cloned()
     }
+
+    /// The MIME type of the file extension, from the custom mappings first, then the built-in ones.
+    pub(crate) fn mime_type(ext: &str) -> String {
+        let ext = ext.to_lowercase();
+
+        let instance = ServerConfig::instance();
+        let store = instance.metadata.read();
+        if let Some(mime_type) = store.mime_types.get(&ext) {
+            return mime_type.to_owned();
+        }
+
+        mime::builtin(&ext).unwrap_or(mime::OCTET_STREAM).to_owned()
+    }
+
+    /// The `Content-Type` of the file, with the default charset added to the textual types.
+    pub(crate) fn content_type(path: &Path) -> String {
+        let mime_type = match path.extension() {
+            Some(ext) => ConnMetadata::mime_type(&ext.to_string_lossy()),
+            None if ServerConfig::instance().metadata.read().mime_sniffing => {
+                let mut head = Vec::with_capacity(512);
+
+                match File::open(path) {
+                    Ok(file) => match file.take(512).read_to_end(&mut head) {
+                        Ok(_) => mime::sniff(&head).to_owned(),
+                        Err(_) => String::from(mime::OCTET_STREAM),
+                    },
+                    Err(_) => String::from(mime::OCTET_STREAM),
+                }
+            }
+            None => String::from("text/plain"),
+        };
+
+        ConnMetadata::with_charset(mime_type)
+    }
+
+    pub(crate) fn with_charset(mime_type: String) -> String {
+        let instance = ServerConfig::instance();
+        let store = instance.metadata.read();
+        if store.charset.is_empty() || !mime::is_text(&mime_type) {
+            return mime_type;
+        }
+
+        [&mime_type, "; charset=", &store.charset].join("")
+    }
+
+    pub(crate) fn is_redirect_host_allowed(host: &str) -> bool {
+        let instance = ServerConfig::instance();
+        let store = instance.metadata.read();
+        if store.redirect_hosts.is_empty() {
+            return false;
+        }
+
+        if store.redirect_hosts.contains(host) {
+            return true;
+        }
+
+        // try the wildcard entries, from the closest parent domain upward
+        let mut domain = host;
+        while let Some(pos) = domain.find('.') {
+            domain = &domain[pos + 1..];
+
+            if store.redirect_hosts.contains(&["*.", domain].join("")) {
+                return true;
+            }
+        }
+
+        false
+    }
 }
@@ -11,14 +11,14 @@ use crate::core::config::ConnMetadata;
 use crate::core::http::{
     Request, RequestWriter, Response, ResponseManager, ResponseStates, ResponseWriter,
 };
-use crate::core::router::{Route, RouteHandler, RouteSeeker, REST};
+use crate::core::router::{self, Route, RouteHandler, RouteSeeker, REST};
 use crate::core::stream::Stream;
 use crate::core::syncstore::Reusable;
 use crate::support::{
     common::{MapUpdates, VecExt},
     debug,
     debug::InfoLevel,
-    shared_pool, TaskType,
+    drain, shared_pool, TaskType,
 };
 
 use crate::channel::{self, Receiver, Sender};
@@ -311,7 +311,7 @@ fn serve_connection(
     // prepare the request source string to be parsed
     let mut next_id = base_id;
     if source.is_empty() {
-        return send_err(next_id, outbox, StreamException::EmptyRequest);
+        return send_err(next_id, outbox, None, StreamException::EmptyRequest);
     }
 
     // now parse the request and find the proper request handler
@@ -356,16 +356,26 @@ fn serve_connection(
 
         // Get callback from the next request
         let (mut request, callback) = parse_request_sync(next);
-        to_close = !request.keep_alive();
+        to_close = !request.keep_alive() || drain::is_draining();
 
         // not matching any given router, return null
         if callback.is_none() || request.uri.is_empty() {
-            return send_err(next_id, outbox, StreamException::ServiceUnavailable);
+            return send_err(
+                next_id,
+                outbox,
+                Some(&request),
+                StreamException::ServiceUnavailable,
+            );
         }
 
         // check server authorization on certain path
         if !Route::authorize(&request, &request.uri) {
-            return send_err(next_id, outbox, StreamException::AccessDenied);
+            return send_err(
+                next_id,
+                outbox,
+                Some(&request),
+                StreamException::AccessDenied,
+            );
         }
 
         // setup peer address
@@ -400,18 +410,32 @@ fn serve_connection(
     Ok(next_id)
 }
 
+/// Serve the raw request as if it's read from a connection, and return its response, such that the
+/// handlers can be tested without a socket, see `TestClient`.
+pub(crate) fn serve_raw(source: &str, peer_addr: Option<SocketAddr>) -> Box<Response> {
+    let (tx, rx) = channel::bounded(1);
+    serve_connection(source, 1, tx, peer_addr, false).unwrap_or_default();
+
+    // if the request is incomplete, e.g. with a shorter body than its `Content-Length`, it won't
+    // be served
+    match rx.recv() {
+        Ok(RespSeqBundle(_, response)) => response,
+        Err(_) => build_err_response(map_err_code(StreamException::EmptyRequest)),
+    }
+}
+
 fn send_err(
     base_id: usize,
     outbox: Sender<RespSeqBundle>,
+    request: Option<&Box<Request>>,
     err: StreamException,
 ) -> Result<usize, ErrorKind> {
-    if outbox
-        .send(RespSeqBundle(
-            base_id,
-            build_err_response(map_err_code(err)),
-        ))
-        .is_err()
-    {
+    let resp = match request {
+        Some(req) => build_err_response_for(req, map_err_code(err)),
+        None => build_err_response(map_err_code(err)),
+    };
+
+    if outbox.send(RespSeqBundle(base_id, resp)).is_err() {
         return Err(ErrorKind::ConnectionAborted);
     }
 
@@ -425,8 +449,12 @@ fn process_request(
     outbox: Sender<RespSeqBundle>,
     is_tls: bool,
 ) {
+    let flight = drain::in_flight();
+
     shared_pool::run(
         move || {
+            let _flight = flight;
+
             outbox
                 .send(RespSeqBundle(
                     next_id,
@@ -438,11 +466,15 @@ fn process_request(
     );
 }
 
-fn build_response(
-    request: Box<Request>,
+pub(crate) fn build_response(
+    mut request: Box<Request>,
     mut callback: RouteHandler,
     is_tls: bool,
 ) -> Box<Response> {
+    if let Some(base) = callback.base_uri() {
+        request.set_base_uri(base);
+    }
+
     // generating the response and setup stuff
     let mut response = initialize_response(is_tls);
 
@@ -451,18 +483,27 @@ fn build_response(
         _ => response.can_keep_alive(true),
     };
 
+    // the server is shutting down, so tell the client not to reuse the connection
+    if drain::is_draining() {
+        response.can_keep_alive(false);
+    }
+
     if request.method.eq(&REST::OTHER(String::from("HEAD"))) {
         response.header_only(true);
     }
 
-    // callback function will decide what to be written into the response
-    callback.execute(&request, &mut response);
-    request.release();
+    // callback function will decide what to be written into the response, if authorized
+    if callback.authorize(&mut request, &mut response) {
+        callback.execute(&request, &mut response);
+    }
 
     // update the response based on critical conditions
-    response.redirect_handling();
+    response.redirect_handling(&request.query_string());
+    response.error_format(&request);
     response.validate_and_update();
 
+    request.release();
+
     // done, send response back
     response
 }
@@ -470,11 +511,12 @@ fn build_response(
 fn parse_request_sync(source: &str) -> (Box<Request>, RouteHandler) {
     let mut handler = RouteHandler::default();
     let mut request = Request::obtain();
+    let host = find_host(source);
 
     for (index, info) in source.trim().splitn(2, "\r\n").enumerate() {
         match index {
             0 => {
-                let res = parse_start_line_sync(&info, &mut request);
+                let res = parse_start_line_sync(info, host, &mut request);
 
                 if res.0.is_some() {
                     request.create_param(res.1);
@@ -492,6 +534,7 @@ fn parse_request_sync(source: &str) -> (Box<Request>, RouteHandler) {
 
 fn parse_start_line_sync(
     source: &str,
+    host: &str,
     req: &mut Box<Request>,
 ) -> (RouteHandler, HashMap<String, String>) {
     let mut raw_query = String::new();
@@ -504,16 +547,7 @@ fn parse_start_line_sync(
 
         match index {
             0 => {
-                let base_method = match &info.to_uppercase()[..] {
-                    "GET" => REST::GET,
-                    "PUT" => REST::PUT,
-                    "POST" => REST::POST,
-                    "DELETE" => REST::DELETE,
-                    "OPTIONS" => REST::OPTIONS,
-                    _ => REST::OTHER(info.to_uppercase()),
-                };
-
-                req.method = base_method;
+                req.method = parse_method(info);
             }
             1 => {
                 // path is at most the length of the source string
@@ -533,7 +567,7 @@ fn parse_start_line_sync(
     }
 
     if !req.uri.is_empty() {
-        let res = Route::seek_sync(&req.method, &req.uri);
+        let res = Route::seek_sync(&req.method, host, &req.uri);
 
         // now do more work on non-essential parsing
         if !raw_fragment.is_empty() {
@@ -541,6 +575,7 @@ fn parse_start_line_sync(
         }
 
         if !raw_query.is_empty() {
+            req.set_query_string(&raw_query);
             req.create_query(parse_query(raw_query));
         }
 
@@ -550,6 +585,36 @@ fn parse_start_line_sync(
     (RouteHandler::default(), HashMap::new())
 }
 
+pub(crate) fn parse_method(info: &str) -> REST {
+    match &info.to_uppercase()[..] {
+        "GET" => REST::GET,
+        "PATCH" => REST::PATCH,
+        "PUT" => REST::PUT,
+        "POST" => REST::POST,
+        "DELETE" => REST::DELETE,
+        "OPTIONS" => REST::OPTIONS,
+        _ => REST::OTHER(info.to_uppercase()),
+    }
+}
+
+/// Find the value of the `Host` header in the raw request, such that we can look up the route of the
+/// virtual host before the rest of the headers are parsed.
+fn find_host(source: &str) -> &str {
+    for line in source.lines().skip(1) {
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(pos) = line.find(':') {
+            if line[..pos].trim().eq_ignore_ascii_case("host") {
+                return line[pos + 1..].trim();
+            }
+        }
+    }
+
+    ""
+}
+
 fn parse_remainder_sync(info: &str, req: &mut Box<Request>) {
     let remainder: String = info.to_owned();
     if remainder.is_empty() {
@@ -605,7 +670,7 @@ fn parse_headers(
             match idx {
                 0 => {
                     header_key = &info.trim()[..];
-                    is_cookie = header_key.eq("cookie");
+                    is_cookie = header_key.eq_ignore_ascii_case("cookie");
                 }
                 1 => {
                     if is_cookie {
@@ -622,69 +687,36 @@ fn parse_headers(
     }
 }
 
-fn parse_path(source: &str, path: &mut String, query: &mut String, frag: &mut String) {
-    let uri = source.trim().trim_end_matches('/');
-    if uri.is_empty() {
-        path.push('/');
-        return;
-    }
-
-    // split the uri and parse each part
-    let mut uri_parts: Vec<&str> = uri.rsplitn(2, '/').collect();
+pub(crate) fn parse_path(source: &str, path: &mut String, query: &mut String, frag: &mut String) {
+    let mut uri = source.trim();
 
     // parse fragment out
-    if let Some(pos) = uri_parts[0].find('#') {
-        let (rest, raw_frag) = uri_parts[0].split_at(pos);
-        uri_parts[0] = rest;
-
-        if !raw_frag.is_empty() {
-            frag.push_str(raw_frag);
-        }
+    if let Some(pos) = uri.find('#') {
+        frag.push_str(&uri[pos..]);
+        uri = &uri[..pos];
     }
 
     // parse query out
-    if let Some(pos) = uri_parts[0].find('?') {
-        // split the query parts
-        let (rest, raw_query) = uri_parts[0].split_at(pos);
-        uri_parts[0] = rest;
-
-        // push the leading parts if not empty
-        if !uri_parts[1].is_empty() {
-            if !uri_parts[1].starts_with('/') {
-                path.push('/');
-            }
-
-            path.push_str(uri_parts[1]);
-        };
+    if let Some(pos) = uri.find('?') {
+        query.push_str(uri[pos + 1..].trim());
+        uri = &uri[..pos];
+    }
 
-        // adding the delimiter between the first the rest part
+    if !uri.starts_with('/') {
         path.push('/');
+    }
 
-        // now push the remainder of the split string, could be empty, e.g. path: "/?say=hi&to=mom"
-        if uri_parts[0].is_empty() {
-            path.push_str(uri_parts[0]);
-        }
+    path.push_str(uri);
 
-        query.push_str(raw_query.trim());
-    } else {
-        if !uri.starts_with('/') {
-            path.push('/');
-        }
-
-        let uri_len = uri.len();
-        if uri_len > 1 && uri.ends_with('/') {
-            path.push_str(&uri[..uri_len - 1]);
-        } else {
-            path.push_str(uri)
-        };
-    }
+    // merge the slashes and resolve the dots, and the trailing slash is left to the router
+    router::normalize_path(path);
 }
 
 /// Cookie parser will parse the request header's cookie field into a hash-map, where the
 /// field is the key of the map, which map to a single value of the key from the Cookie
 /// header field. Assuming no duplicate cookie keys, or the first cookie key-value pair
 /// will be stored.
-fn parse_cookie(raw: &str, cookie: &mut HashMap<String, String>) {
+pub(crate) fn parse_cookie(raw: &str, cookie: &mut HashMap<String, String>) {
     if raw.is_empty() {
         return;
     }
@@ -699,7 +731,7 @@ fn parse_cookie(raw: &str, cookie: &mut HashMap<String, String>) {
     }
 }
 
-fn parse_query(query: String) -> HashMap<String, Vec<String>> {
+pub(crate) fn parse_query(query: String) -> HashMap<String, Vec<String>> {
     let mut query_result: HashMap<String, Vec<String>> = HashMap::new();
     for (_, kv_pair) in query.trim().split('&').enumerate() {
         let store: Vec<&str> = kv_pair.trim().splitn(2, '=').collect();
@@ -725,9 +757,19 @@ fn parse_query(query: String) -> HashMap<String, Vec<String>> {
     query_result
 }
 
-fn build_err_response(err_status: u16) -> Box<Response> {
-    let mut resp = Response::obtain(); //Box::new(Response::new());
+pub(crate) fn build_err_response(err_status: u16) -> Box<Response> {
+    fill_err_response(Response::obtain(), err_status)
+}
+
+/// Build the error response with the default error page in the format preferred by the client.
+pub(crate) fn build_err_response_for(request: &Box<Request>, err_status: u16) -> Box<Response> {
+    let mut resp = Response::obtain();
+    resp.error_format(request);
+
+    fill_err_response(resp, err_status)
+}
 
+fn fill_err_response(mut resp: Box<Response>, err_status: u16) -> Box<Response> {
     resp.status(err_status);
     if err_status == 0 {
         return resp;
@@ -737,7 +779,7 @@ fn build_err_response(err_status: u16) -> Box<Response> {
     resp.keep_alive(false);
 
     if resp.get_content_type().is_empty() {
-        resp.set_content_type("text/html");
+        resp.set_content_type(&ConnMetadata::with_charset(String::from("text/html")));
     }
 
     resp
@@ -806,24 +848,33 @@ mod async_handler {
 
     fn send_response(
         stream: Stream,
-        request: Box<Request>,
+        mut request: Box<Request>,
         mut callback: RouteHandler,
         is_tls: bool,
     ) -> ExecCode {
+        let _flight = drain::in_flight();
         let mut response = initialize_response(is_tls);
         match request.header("connection") {
             Some(ref val) if val.eq(&String::from("close")) => response.can_keep_alive(false),
             _ => response.can_keep_alive(true),
         };
 
+        // the server is shutting down, so tell the client not to reuse the connection
+        if drain::is_draining() {
+            response.can_keep_alive(false);
+        }
+
         if request.method.eq(&REST::OTHER(String::from("HEAD"))) {
             response.header_only(true);
         }
 
-        // callback function will decide what to be written into the response
-        callback.execute(&request, &mut response);
+        // callback function will decide what to be written into the response, if authorized
+        if callback.authorize(&mut request, &mut response) {
+            callback.execute(&request, &mut response);
+        }
 
-        response.redirect_handling();
+        response.redirect_handling(&request.query_string());
+        response.error_format(&request);
         response.validate_and_update();
 
         write_to_stream(stream, response)
@@ -939,10 +990,11 @@ mod async_handler {
         let mut res = RouteHandler::default();
         let mut baseline_chan = None;
         let mut remainder_chan = None;
+        let host = find_host(source);
 
         for (index, info) in source.trim().splitn(2, "\r\n").enumerate() {
             match index {
-                0 => baseline_chan = parse_start_line(&info, store),
+                0 => baseline_chan = parse_start_line(info, host, store),
                 1 => {
                     let remainder: String = info.to_owned();
                     if remainder.is_empty() {
@@ -1003,7 +1055,7 @@ mod async_handler {
         res
     }
 
-    fn parse_start_line(source: &str, req: &mut Box<Request>) -> BaseLine {
+    fn parse_start_line(source: &str, host: &str, req: &mut Box<Request>) -> BaseLine {
         let mut raw_query = String::new();
         let mut raw_fragment = String::new();
 
@@ -1014,16 +1066,7 @@ mod async_handler {
 
             match index {
                 0 => {
-                    let base_method = match &info.to_uppercase()[..] {
-                        "GET" => REST::GET,
-                        "PUT" => REST::PUT,
-                        "POST" => REST::POST,
-                        "DELETE" => REST::DELETE,
-                        "OPTIONS" => REST::OPTIONS,
-                        _ => REST::OTHER(info.to_uppercase()),
-                    };
-
-                    req.method = base_method;
+                    req.method = parse_method(info);
                 }
                 1 => {
                     // path is at most the length of the source string
@@ -1045,10 +1088,11 @@ mod async_handler {
         if !req.uri.is_empty() {
             let uri = req.uri.to_owned();
             let req_method = req.method.clone();
+            let host = host.to_owned();
 
             let (tx, rx) = channel::bounded(1);
             shared_pool::run(
-                move || Route::seek(&req_method, &uri, tx),
+                move || Route::seek(&req_method, &host, &uri, tx),
                 TaskType::Request,
             );
 
@@ -1058,6 +1102,7 @@ mod async_handler {
             }
 
             if !raw_query.is_empty() {
+                req.set_query_string(&raw_query);
                 req.create_query(parse_query(raw_query));
             }
 
@@ -1082,3 +1127,38 @@ mod async_handler {
         0
     }
 }
+
+#[cfg(test)]
+mod conn_test {
+    use super::parse_path;
+
+    fn parse(source: &str) -> (String, String, String) {
+        let (mut path, mut query, mut frag) = (String::new(), String::new(), String::new());
+        parse_path(source, &mut path, &mut query, &mut frag);
+        (path, query, frag)
+    }
+
+    #[test]
+    fn parse_path_test() {
+        assert_eq!(parse("/").0, "/");
+        assert_eq!(parse("/api/user/").0, "/api/user");
+
+        let (path, query, _) = parse("/api/user?id=1&name=mom");
+        assert_eq!(path, "/api/user");
+        assert_eq!(query, "id=1&name=mom");
+
+        let (path, query, _) = parse("/?say=hi");
+        assert_eq!(path, "/");
+        assert_eq!(query, "say=hi");
+
+        let (path, query, frag) = parse("/blog/post?page=2#top");
+        assert_eq!(path, "/blog/post");
+        assert_eq!(query, "page=2");
+        assert_eq!(frag, "#top");
+
+        // the path is normalized the same way with or without the query
+        let (path, query, _) = parse("//api/./user/?id=1");
+        assert_eq!(path, "/api/user");
+        assert_eq!(query, "id=1");
+    }
+}
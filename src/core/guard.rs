@@ -0,0 +1,172 @@
+//! The request guards, i.e. the conditions on the request headers, the query or a custom check,
+//! which shall be met besides the method and the path for a route to be picked, such that the same
+//! path can be served by different handlers, e.g. for the JSON or the form submission.
+
+use crate::core::config::ConnMetadata;
+use crate::core::http::Request;
+
+/// `GuardFunc` is a type alias to the custom checks of a request guard, which returns `true` if the
+/// request shall be served by the guarded route.
+pub type GuardFunc = fn(&Request) -> bool;
+
+/// The conditions for a guarded route to be picked, see `Router::guarded`. The conditions are
+/// checked in the order of: the headers, the query params, the custom checks, the `Accept` header
+/// and the `Content-Type` header, and if no guarded route of the path is picked, the response will
+/// be `415 Unsupported Media Type` if the content type is the only one not met, or
+/// `406 Not Acceptable` if it's the `Accept` header, or otherwise `404 Not Found`.
+///
+/// # Example
+///
+/// ```
+/// use rusty_express::prelude::*;
+///
+/// let mut json = RequestGuard::new();
+/// json.set_content_types(&["application/json"]);
+///
+/// let mut form = RequestGuard::new();
+/// form.set_content_types(&["application/x-www-form-urlencoded", "multipart/form-data"]);
+///
+/// let mut server = HttpServer::new();
+/// server
+///     .guarded(REST::POST, RequestPath::Explicit("/users"), json, json_handler)
+///     .guarded(REST::POST, RequestPath::Explicit("/users"), form, form_handler);
+///
+/// pub fn json_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+///     resp.send("created from json");
+/// }
+///
+/// pub fn form_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+///     resp.send("created from form");
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct RequestGuard {
+    headers: Vec<(String, Option<String>)>,
+    queries: Vec<(String, Option<String>)>,
+    predicates: Vec<GuardFunc>,
+    accepts: Vec<String>,
+    content_types: Vec<String>,
+}
+
+impl RequestGuard {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The header must be present, and if the value is given, equal to it case-insensitively.
+    pub fn require_header(&mut self, name: &str, value: Option<&str>) {
+        self.headers
+            .push((name.to_lowercase(), value.map(String::from)));
+    }
+
+    /// The query param must be present, and if the value is given, one of its values must equal it.
+    pub fn require_query(&mut self, name: &str, value: Option<&str>) {
+        self.queries
+            .push((name.to_owned(), value.map(String::from)));
+    }
+
+    /// The custom check on the request, which must return `true`.
+    pub fn add_predicate(&mut self, predicate: GuardFunc) {
+        self.predicates.push(predicate);
+    }
+
+    /// The request must accept one of the media types per its `Accept` header, where the types can
+    /// also be given as the file extensions, e.g. `json`.
+    pub fn set_accepts(&mut self, types: &[&str]) {
+        self.accepts = types.iter().map(|t| (*t).to_owned()).collect();
+    }
+
+    /// The `Content-Type` of the request must be one of the media types, which can be a wildcard,
+    /// e.g. `text/*`, or given as the file extensions, e.g. `json`.
+    pub fn set_content_types(&mut self, types: &[&str]) {
+        self.content_types = types.iter().map(|t| t.to_lowercase()).collect();
+    }
+
+    /// Check the request against the conditions, and return the error status if not all are met.
+    pub(crate) fn check(&self, req: &Request) -> Result<(), u16> {
+        let headers =
+            self.headers
+                .iter()
+                .all(|(name, value)| match (req.header(name), value.as_ref()) {
+                    (Some(actual), Some(expected)) => actual.trim().eq_ignore_ascii_case(expected),
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                });
+
+        let queries =
+            self.queries
+                .iter()
+                .all(|(name, value)| match (req.query(name), value.as_ref()) {
+                    (Some(actual), Some(expected)) => actual.iter().any(|v| v == expected),
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                });
+
+        if !headers || !queries || !self.predicates.iter().all(|check| check(req)) {
+            return Err(404);
+        }
+
+        if !self.accepts.is_empty() {
+            let offers: Vec<&str> = self.accepts.iter().map(|t| t.as_str()).collect();
+            if req.accepts(&offers).is_none() {
+                return Err(406);
+            }
+        }
+
+        if !self.content_types.is_empty() {
+            let actual = req
+                .header("content-type")
+                .map(|raw| raw.split(';').next().unwrap_or("").trim().to_lowercase())
+                .unwrap_or_default();
+
+            // the extensions are mapped at the request time, when the MIME types have been set
+            let found = self.content_types.iter().any(|expected| {
+                if expected.contains('/') {
+                    media_match(expected, &actual)
+                } else {
+                    media_match(
+                        &ConnMetadata::mime_type(expected.trim_start_matches('.')),
+                        &actual,
+                    )
+                }
+            });
+
+            if !found {
+                return Err(415);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn media_match(expected: &str, actual: &str) -> bool {
+    if actual.is_empty() {
+        return false;
+    }
+
+    if expected == "*/*" || expected == actual {
+        return true;
+    }
+
+    match expected.find("/*") {
+        Some(pos) if pos + 2 == expected.len() => {
+            actual.split('/').next() == Some(&expected[..pos])
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod guard_test {
+    use super::media_match;
+
+    #[test]
+    fn media_match_test() {
+        assert!(media_match("application/json", "application/json"));
+        assert!(media_match("text/*", "text/plain"));
+        assert!(media_match("*/*", "image/png"));
+        assert!(!media_match("text/*", "application/json"));
+        assert!(!media_match("application/json", ""));
+    }
+}
@@ -0,0 +1,1359 @@
+use std::collections::VecDeque;
+use std::io::{prelude::*, ErrorKind};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::core::conn;
+use crate::core::http::{Request, RequestWriter, Response, ResponseBody};
+use crate::core::router::{Route, RouteSeeker};
+use crate::core::stream::Stream;
+use crate::core::syncstore::Reusable;
+use crate::hashbrown::HashMap;
+use crate::support::{
+    debug::{self, InfoLevel},
+    drain,
+    hpack::{Decoder, Encoder},
+};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const PEEK_SIZE: usize = 4096;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(8);
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+const PEEK_INTERVAL: Duration = Duration::from_millis(5);
+
+const FRAME_HEADER_SIZE: usize = 9;
+const DEFAULT_FRAME_SIZE: usize = 16_384;
+const MAX_FRAME_SIZE: usize = 16_777_215;
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+const MAX_CONCURRENT_STREAMS: u32 = 128;
+const MAX_HEADER_BLOCK: usize = 65_536;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum ErrorCode {
+    NoError = 0x0,
+    ProtocolError = 0x1,
+    InternalError = 0x2,
+    FlowControlError = 0x3,
+    StreamClosed = 0x5,
+    FrameSizeError = 0x6,
+    RefusedStream = 0x7,
+    CompressionError = 0x9,
+    EnhanceYourCalm = 0xb,
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+type HeaderList = Vec<(String, String)>;
+
+#[derive(Default)]
+struct H2Stream {
+    headers: HeaderList,
+    header_block: Vec<u8>,
+    body: Vec<u8>,
+    send_window: i64,
+    recv_window: i64,
+    unacked: i64, // the body bytes to be credited to the connection window once consumed
+    headers_done: bool,
+    end_stream: bool,
+}
+
+/// Try to serve the stream as an HTTP/2 connection: over TLS, this is decided by the protocol
+/// negotiated via ALPN; over plain TCP, we look for the h2c prior-knowledge preface, or an HTTP/1.1
+/// request asking to upgrade to h2c. If the stream shall be served as HTTP/1.1, it's handed back
+/// to the caller untouched. The `req_limit` is the number of 512B a request can take, as the
+/// HTTP/1.1 connections do.
+pub(crate) fn try_serve(mut stream: Stream, is_tls: bool, req_limit: usize) -> Result<(), Stream> {
+    let upgrade = match negotiate(&mut stream) {
+        Negotiation::Http1 => return Err(stream),
+        Negotiation::Prior => None,
+        Negotiation::Upgrade(head) => match upgrade(&mut stream, &head) {
+            Some(req) => Some(req),
+            None => return Err(stream),
+        },
+    };
+
+    let mut conn = Connection::new(stream, is_tls, req_limit * 512);
+    if let Some((settings, headers)) = upgrade {
+        if let Err(code) = conn.apply_settings(&settings) {
+            conn.go_away(code);
+            return Ok(());
+        }
+
+        conn.accept_upgrade(headers);
+    }
+
+    conn.serve();
+    Ok(())
+}
+
+enum Negotiation {
+    Http1,
+    Prior,
+    Upgrade(Vec<u8>),
+}
+
+fn negotiate(stream: &mut Stream) -> Negotiation {
+    match stream {
+        Stream::Tls(tls) => match tls.negotiated_alpn() {
+            Ok(Some(ref proto)) if proto.as_slice() == b"h2" => Negotiation::Prior,
+            _ => Negotiation::Http1,
+        },
+        Stream::Tcp(tcp) => {
+            // don't let an idle socket hold up the worker, and restore the timeout afterwards
+            let prior = tcp.read_timeout().unwrap_or(None);
+            let timeout = prior.map_or(IDLE_TIMEOUT, |t| t.min(IDLE_TIMEOUT));
+
+            if let Err(e) = tcp.set_read_timeout(Some(timeout)) {
+                debug::print(
+                    &format!("Failed to set the read timeout on the stream: {}", e),
+                    InfoLevel::Warning,
+                );
+            }
+
+            let mut buf = [0u8; PEEK_SIZE];
+            let result = match peek_preface(tcp, &mut buf, timeout) {
+                Some(len) if len >= PREFACE.len() && buf.starts_with(PREFACE) => Negotiation::Prior,
+                // only consider the upgrade if the entire request head has been received
+                Some(len) => match find_head_end(&buf[..len]) {
+                    Some(end) if is_h2c_upgrade(&buf[..end]) => {
+                        Negotiation::Upgrade(buf[..end].to_vec())
+                    }
+                    _ => Negotiation::Http1,
+                },
+                None => Negotiation::Http1,
+            };
+
+            if let Err(e) = tcp.set_read_timeout(prior) {
+                debug::print(
+                    &format!("Failed to restore the read timeout on the stream: {}", e),
+                    InfoLevel::Warning,
+                );
+            }
+
+            result
+        }
+    }
+}
+
+/// Peek into the stream until either the entire h2c preface has arrived, or the received bytes
+/// can't be the preface, and return the number of bytes peeked. Returns `None` if the stream is
+/// closed, or nothing has arrived before the timeout.
+fn peek_preface(tcp: &TcpStream, buf: &mut [u8], timeout: Duration) -> Option<usize> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let len = match tcp.peek(buf) {
+            Ok(0) | Err(_) => return None,
+            Ok(len) => len,
+        };
+
+        let size = len.min(PREFACE.len());
+        if len >= PREFACE.len() || buf[..size] != PREFACE[..size] || Instant::now() >= deadline {
+            return Some(len);
+        }
+
+        // a partial preface, wait for the rest of it to arrive
+        thread::sleep(PEEK_INTERVAL);
+    }
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+fn is_h2c_upgrade(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head).to_lowercase();
+    let mut upgrade = false;
+    let mut settings = false;
+
+    for line in head.split("\r\n").skip(1) {
+        let mut parts = line.splitn(2, ':');
+        let (key, val) = match (parts.next(), parts.next()) {
+            (Some(k), Some(v)) => (k.trim(), v.trim()),
+            _ => continue,
+        };
+
+        match key {
+            "upgrade" => upgrade = val.split(',').any(|p| p.trim() == "h2c"),
+            "http2-settings" => settings = true,
+            "content-length" if val != "0" => return false,
+            "transfer-encoding" => return false,
+            _ => {}
+        }
+    }
+
+    upgrade && settings
+}
+
+/// Consume the HTTP/1.1 upgrade request from the stream, reply with the `101 Switching Protocols`,
+/// and return the client's settings and the request headers, which will be served as stream 1.
+fn upgrade(stream: &mut Stream, head: &[u8]) -> Option<(Vec<u8>, HeaderList)> {
+    let text = String::from_utf8_lossy(head);
+    let mut lines = text.split("\r\n");
+
+    let mut start = lines.next()?.split_whitespace();
+    let (method, path) = (start.next()?, start.next()?);
+
+    let mut headers = vec![
+        (String::from(":method"), method.to_owned()),
+        (String::from(":scheme"), String::from("http")),
+        (String::from(":path"), path.to_owned()),
+    ];
+
+    let mut settings = Vec::new();
+    for line in lines {
+        let mut parts = line.splitn(2, ':');
+        let (key, val) = match (parts.next(), parts.next()) {
+            (Some(k), Some(v)) => (k.trim().to_lowercase(), v.trim()),
+            _ => continue,
+        };
+
+        match &key[..] {
+            "http2-settings" => settings = base64_url_decode(val)?,
+            "connection" | "upgrade" | "keep-alive" | "transfer-encoding" => {}
+            "host" => headers.push((String::from(":authority"), val.to_owned())),
+            _ => headers.push((key, val.to_owned())),
+        }
+    }
+
+    // only consume the request from the stream once we're certain to upgrade
+    let mut consumed = vec![0u8; head.len()];
+    if stream.read_exact(&mut consumed).is_err() {
+        return None;
+    }
+
+    let reply = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+    if stream.write_all(reply).is_err() || stream.flush().is_err() {
+        return None;
+    }
+
+    Some((settings, headers))
+}
+
+fn base64_url_decode(src: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(src.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in src.trim_end_matches('=').bytes() {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            _ => return None,
+        };
+
+        acc = (acc << 6) | u32::from(val);
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    Some(out)
+}
+
+struct Connection {
+    stream: Stream,
+    is_tls: bool,
+    client: Option<SocketAddr>,
+    decoder: Decoder,
+    streams: HashMap<u32, H2Stream>,
+    ready: VecDeque<u32>,
+    last_stream_id: u32,
+    conn_send_window: i64,
+    conn_recv_window: i64,
+    peer_window_size: i64,
+    peer_frame_size: usize,
+    read_limit: usize,
+    goaway: bool,
+    draining: Option<u32>, // the last stream id told to the peer when the server started draining
+}
+
+impl Connection {
+    fn new(stream: Stream, is_tls: bool, read_limit: usize) -> Self {
+        let client = stream.peer_addr().ok();
+
+        // wake up every now and then while waiting for the frames, to see if the server is draining
+        if let Err(e) = stream.set_read_timeout(Some(DRAIN_POLL)) {
+            debug::print(
+                &format!("Failed to set the read timeout on the h2 stream: {}", e),
+                InfoLevel::Warning,
+            );
+        }
+
+        Connection {
+            stream,
+            is_tls,
+            client,
+            decoder: Decoder::new(),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            last_stream_id: 0,
+            conn_send_window: DEFAULT_WINDOW_SIZE,
+            conn_recv_window: DEFAULT_WINDOW_SIZE,
+            peer_window_size: DEFAULT_WINDOW_SIZE,
+            peer_frame_size: DEFAULT_FRAME_SIZE,
+            read_limit,
+            goaway: false,
+            draining: None,
+        }
+    }
+
+    /// Serve stream 1 from the HTTP/1.1 request that asked for the upgrade.
+    fn accept_upgrade(&mut self, headers: HeaderList) {
+        self.last_stream_id = 1;
+        self.streams.insert(
+            1,
+            H2Stream {
+                headers,
+                send_window: self.peer_window_size,
+                headers_done: true,
+                end_stream: true,
+                ..Default::default()
+            },
+        );
+
+        self.ready.push_back(1);
+    }
+
+    fn serve(&mut self) {
+        // the client preface is required for both the prior-knowledge and the upgraded connections
+        let mut preface = [0u8; 24];
+        if self.fill(&mut preface, false).is_err() || &preface[..] != PREFACE {
+            debug::print("Invalid HTTP/2 connection preface", InfoLevel::Warning);
+            self.go_away(ErrorCode::ProtocolError);
+            return;
+        }
+
+        let mut settings = Vec::with_capacity(12);
+        push_setting(
+            &mut settings,
+            SETTINGS_MAX_CONCURRENT_STREAMS,
+            MAX_CONCURRENT_STREAMS,
+        );
+        push_setting(&mut settings, SETTINGS_ENABLE_PUSH, 0);
+
+        if self.write_frame(SETTINGS, 0, 0, &settings).is_err() {
+            return;
+        }
+
+        // the connection window is only replenished as the bodies are consumed, so make room for
+        // a request body over the read limit, which shall be told apart
+        let limit = (self.read_limit as i64 + 1).min(MAX_WINDOW_SIZE);
+        if limit > DEFAULT_WINDOW_SIZE && self.replenish(0, limit - DEFAULT_WINDOW_SIZE).is_err() {
+            return;
+        }
+
+        while !self.goaway {
+            // respond to all completed requests before reading more frames
+            while let Some(id) = self.ready.pop_front() {
+                if let Err(code) = self.respond(id) {
+                    self.go_away(code);
+                    return;
+                }
+            }
+
+            // the streams opened before the server started draining are all served
+            if self.draining.is_some() && self.streams.is_empty() {
+                break;
+            }
+
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(code) => {
+                    self.go_away(code);
+                    return;
+                }
+            };
+
+            if let Err(code) = self.handle_frame(frame) {
+                self.go_away(code);
+                return;
+            }
+        }
+
+        if let Err(e) = self.stream.shutdown(std::net::Shutdown::Both) {
+            debug::print(
+                &format!(
+                    "Encountered errors while shutting down the h2 stream: {}",
+                    e
+                ),
+                InfoLevel::Warning,
+            );
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, ErrorCode> {
+        let mut head = [0u8; FRAME_HEADER_SIZE];
+        self.fill(&mut head, true)?;
+
+        let len = (usize::from(head[0]) << 16) | (usize::from(head[1]) << 8) | usize::from(head[2]);
+        if len > DEFAULT_FRAME_SIZE {
+            return Err(ErrorCode::FrameSizeError);
+        }
+
+        let mut payload = vec![0u8; len];
+        self.fill(&mut payload, false)?;
+
+        Ok(Frame {
+            kind: head[3],
+            flags: head[4],
+            stream_id: read_u32(&head[5..]) & 0x7fff_ffff,
+            payload,
+        })
+    }
+
+    /// Read till the buffer is full, where the peer may be idle for up to `IDLE_TIMEOUT` between the
+    /// reads. While waiting for a new frame, the peer is told to stop opening new streams once the
+    /// server starts draining.
+    fn fill(&mut self, buf: &mut [u8], new_frame: bool) -> Result<(), ErrorCode> {
+        let mut read = 0;
+        let mut since = Instant::now();
+
+        while read < buf.len() {
+            if new_frame && read == 0 && self.draining.is_none() && drain::is_draining() {
+                self.draining = Some(self.last_stream_id);
+                self.write_goaway(ErrorCode::NoError)?;
+            }
+
+            match self.stream.read(&mut buf[read..]) {
+                // peer is gone
+                Ok(0) => return Err(ErrorCode::NoError),
+                Ok(size) => {
+                    read += size;
+                    since = Instant::now();
+                }
+                Err(e) => match e.kind() {
+                    ErrorKind::Interrupted => {}
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock
+                        if since.elapsed() < IDLE_TIMEOUT => {}
+                    // idle for too long: close the connection gracefully
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock => return Err(ErrorCode::NoError),
+                    _ => return Err(ErrorCode::InternalError),
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<(), ErrorCode> {
+        match frame.kind {
+            SETTINGS => {
+                if frame.stream_id != 0 {
+                    return Err(ErrorCode::ProtocolError);
+                }
+
+                if frame.flags & FLAG_ACK == 0 {
+                    if !frame.payload.len().is_multiple_of(6) {
+                        return Err(ErrorCode::FrameSizeError);
+                    }
+
+                    self.apply_settings(&frame.payload)?;
+                    self.write_frame(SETTINGS, FLAG_ACK, 0, &[])?;
+                }
+            }
+            PING => {
+                if frame.payload.len() != 8 {
+                    return Err(ErrorCode::FrameSizeError);
+                }
+
+                if frame.flags & FLAG_ACK == 0 {
+                    self.write_frame(PING, FLAG_ACK, 0, &frame.payload)?;
+                }
+            }
+            WINDOW_UPDATE => {
+                if frame.payload.len() != 4 {
+                    return Err(ErrorCode::FrameSizeError);
+                }
+
+                let inc = i64::from(read_u32(&frame.payload) & 0x7fff_ffff);
+                if frame.stream_id == 0 {
+                    if inc == 0 {
+                        return Err(ErrorCode::ProtocolError);
+                    }
+
+                    self.conn_send_window += inc;
+                    if self.conn_send_window > MAX_WINDOW_SIZE {
+                        return Err(ErrorCode::FlowControlError);
+                    }
+                } else if let Some(s) = self.streams.get_mut(&frame.stream_id) {
+                    if inc == 0 {
+                        return self.reset(frame.stream_id, ErrorCode::ProtocolError);
+                    }
+
+                    s.send_window += inc;
+                    if s.send_window > MAX_WINDOW_SIZE {
+                        return self.reset(frame.stream_id, ErrorCode::FlowControlError);
+                    }
+                }
+            }
+            HEADERS => self.on_headers(frame)?,
+            CONTINUATION => self.on_continuation(frame)?,
+            DATA => self.on_data(frame)?,
+            RST_STREAM => {
+                if let Some(s) = self.streams.remove(&frame.stream_id) {
+                    self.replenish(0, s.unacked)?;
+                }
+
+                self.ready.retain(|id| *id != frame.stream_id);
+            }
+            GOAWAY => self.goaway = true,
+            PUSH_PROMISE => return Err(ErrorCode::ProtocolError),
+            _ => {
+                // the priority hints and unknown frames are ignored
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the peer's settings, and reject the values out of the ranges allowed by RFC 7540,
+    /// section 6.5.2, since they would break the framing or the flow control otherwise.
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<(), ErrorCode> {
+        for setting in payload.chunks(6) {
+            if setting.len() < 6 {
+                break;
+            }
+
+            let id = (u16::from(setting[0]) << 8) | u16::from(setting[1]);
+            let val = read_u32(&setting[2..]);
+
+            match id {
+                SETTINGS_ENABLE_PUSH if val > 1 => return Err(ErrorCode::ProtocolError),
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if i64::from(val) > MAX_WINDOW_SIZE {
+                        return Err(ErrorCode::FlowControlError);
+                    }
+
+                    // the delta applies to all open streams as well
+                    let delta = i64::from(val) - self.peer_window_size;
+                    self.peer_window_size = i64::from(val);
+
+                    for s in self.streams.values_mut() {
+                        s.send_window += delta;
+                        if s.send_window > MAX_WINDOW_SIZE {
+                            return Err(ErrorCode::FlowControlError);
+                        }
+                    }
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    let size = val as usize;
+                    if !(DEFAULT_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&size) {
+                        return Err(ErrorCode::ProtocolError);
+                    }
+
+                    self.peer_frame_size = size;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_headers(&mut self, frame: Frame) -> Result<(), ErrorCode> {
+        let id = frame.stream_id;
+        if id == 0 || id.is_multiple_of(2) {
+            return Err(ErrorCode::ProtocolError);
+        }
+
+        let mut block = strip_padding(&frame)?;
+        if frame.flags & FLAG_PRIORITY != 0 {
+            if block.len() < 5 {
+                return Err(ErrorCode::FrameSizeError);
+            }
+
+            block = &block[5..];
+        }
+
+        let header_limit = self.header_limit();
+        if let Some(s) = self.streams.get_mut(&id) {
+            // trailers of a request that's still receiving its body
+            if s.end_stream {
+                return Err(ErrorCode::StreamClosed);
+            }
+
+            if block.len() > header_limit {
+                return Err(ErrorCode::CompressionError);
+            }
+
+            s.header_block.extend_from_slice(block);
+            s.end_stream = frame.flags & FLAG_END_STREAM != 0;
+        } else {
+            if id <= self.last_stream_id {
+                return Err(ErrorCode::ProtocolError);
+            }
+
+            self.last_stream_id = id;
+
+            // the block can't be skipped without breaking the decoder state for the other streams
+            if block.len() > header_limit {
+                return Err(ErrorCode::CompressionError);
+            }
+
+            if self.streams.len() >= MAX_CONCURRENT_STREAMS as usize {
+                return self.reset(id, ErrorCode::RefusedStream);
+            }
+
+            self.streams.insert(
+                id,
+                H2Stream {
+                    header_block: block.to_vec(),
+                    send_window: self.peer_window_size,
+                    recv_window: DEFAULT_WINDOW_SIZE,
+                    end_stream: frame.flags & FLAG_END_STREAM != 0,
+                    ..Default::default()
+                },
+            );
+        }
+
+        if frame.flags & FLAG_END_HEADERS != 0 {
+            self.finish_headers(id)?;
+        }
+
+        Ok(())
+    }
+
+    fn on_continuation(&mut self, frame: Frame) -> Result<(), ErrorCode> {
+        let header_limit = self.header_limit();
+        match self.streams.get_mut(&frame.stream_id) {
+            Some(s) if !s.header_block.is_empty() => {
+                if s.header_block.len() + frame.payload.len() > header_limit {
+                    return Err(ErrorCode::CompressionError);
+                }
+
+                s.header_block.extend_from_slice(&frame.payload)
+            }
+            _ => return Err(ErrorCode::ProtocolError),
+        }
+
+        if frame.flags & FLAG_END_HEADERS != 0 {
+            self.finish_headers(frame.stream_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish_headers(&mut self, id: u32) -> Result<(), ErrorCode> {
+        let s = match self.streams.get_mut(&id) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        let block = std::mem::take(&mut s.header_block);
+        let headers = self
+            .decoder
+            .decode(&block)
+            .map_err(|_| ErrorCode::CompressionError)?;
+
+        // the block is decoded all the same to keep the decoder state, but the streams opened after
+        // the server started draining are ignored, which the peer shall retry elsewhere
+        if self.draining.is_some_and(|last| id > last) {
+            self.streams.remove(&id);
+            return Ok(());
+        }
+
+        if !s.headers_done {
+            s.headers = headers;
+            s.headers_done = true;
+        }
+
+        if s.end_stream {
+            self.ready.push_back(id);
+        }
+
+        Ok(())
+    }
+
+    /// Buffer the body of the request, which is handed to the handler as a whole. The peer is never
+    /// granted more window than the read limit of the request, and the connection window is only
+    /// replenished once the body is consumed by the handler, or dropped. Without a read limit, the
+    /// windows are replenished right away.
+    fn on_data(&mut self, frame: Frame) -> Result<(), ErrorCode> {
+        let id = frame.stream_id;
+        let len = frame.payload.len() as i64;
+
+        // the whole frame counts against the connection window, even if it's dropped
+        self.conn_recv_window -= len;
+        if self.conn_recv_window < 0 {
+            return Err(ErrorCode::FlowControlError);
+        }
+
+        let content = strip_padding(&frame)?;
+        let limit = self.read_limit;
+
+        let s = match self.streams.get_mut(&id) {
+            Some(s) if s.headers_done && !s.end_stream => s,
+            _ => {
+                self.replenish(0, len)?;
+                return self.reset(id, ErrorCode::StreamClosed);
+            }
+        };
+
+        s.unacked += len;
+        s.recv_window -= len;
+        if s.recv_window < 0 {
+            return self.reset(id, ErrorCode::FlowControlError);
+        }
+
+        if limit > 0 && s.body.len() + content.len() > limit {
+            debug::print(
+                &format!(
+                    "The h2 request body is over the read limit of {} bytes",
+                    limit
+                ),
+                InfoLevel::Warning,
+            );
+
+            return self.reset(id, ErrorCode::EnhanceYourCalm);
+        }
+
+        s.body.extend_from_slice(content);
+        s.end_stream = frame.flags & FLAG_END_STREAM != 0;
+
+        if s.end_stream {
+            self.ready.push_back(id);
+            return Ok(());
+        }
+
+        if limit == 0 {
+            s.unacked = 0;
+            self.replenish(0, len)?;
+            return self.replenish(id, len);
+        }
+
+        // only what's left of the read limit, minus the window still open, is granted, plus a byte
+        // such that the body over the limit can be told apart, rather than stalled
+        let room = limit as i64 + 1 - s.body.len() as i64 - s.recv_window;
+        self.replenish(id, room.min(len))
+    }
+
+    fn respond(&mut self, id: u32) -> Result<(), ErrorCode> {
+        let s = match self.streams.get_mut(&id) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        let headers = std::mem::take(&mut s.headers);
+        let body = std::mem::take(&mut s.body);
+        let consumed = std::mem::take(&mut s.unacked);
+
+        let mut response = dispatch(headers, body, self.client, self.is_tls);
+        let (status, fields, body) = response.take_parts();
+        response.release();
+
+        // the body has been consumed by the handler, so the peer can send more
+        self.replenish(0, consumed)?;
+
+        let mut block_fields = Vec::with_capacity(fields.len() + 1);
+        block_fields.push((String::from(":status"), status.to_string()));
+        block_fields.extend(fields);
+
+        let block = Encoder::encode(&block_fields);
+        let end_stream = if body.is_empty() { FLAG_END_STREAM } else { 0 };
+
+        // split the header block into HEADERS and CONTINUATION frames if too large
+        let mut chunks = block.chunks(self.peer_frame_size).peekable();
+        let mut kind = HEADERS;
+
+        if chunks.peek().is_none() {
+            self.write_frame(HEADERS, FLAG_END_HEADERS | end_stream, id, &[])?;
+        }
+
+        while let Some(chunk) = chunks.next() {
+            let mut flags = if kind == HEADERS { end_stream } else { 0 };
+            if chunks.peek().is_none() {
+                flags |= FLAG_END_HEADERS;
+            }
+
+            self.write_frame(kind, flags, id, chunk)?;
+            kind = CONTINUATION;
+        }
+
+        let len = body.len();
+        match body {
+            _ if len == 0 => {}
+            ResponseBody::Bytes(content) => self.send_data(id, &mut content.as_slice(), len)?,
+            ResponseBody::Shared(content) => self.send_data(id, &mut content.as_slice(), len)?,
+            ResponseBody::File(file, _) => self.send_data(id, &mut &file, len)?,
+        }
+
+        self.streams.remove(&id);
+        Ok(())
+    }
+
+    /// Send the body in DATA frames, which are read from the source one frame at a time, such that
+    /// a large file is never held in memory as a whole.
+    fn send_data(&mut self, id: u32, source: &mut dyn Read, len: u64) -> Result<(), ErrorCode> {
+        let mut frame = Vec::new();
+        let mut sent = 0;
+
+        while sent < len {
+            let stream_window = match self.streams.get(&id) {
+                Some(s) => s.send_window,
+                None => return Ok(()), // the stream has been reset by the peer
+            };
+
+            let window = self.conn_send_window.min(stream_window);
+            if window <= 0 {
+                // wait for the peer to open up the windows
+                let frame = self.read_frame()?;
+                self.handle_frame(frame)?;
+
+                if self.goaway {
+                    return Ok(());
+                }
+
+                continue;
+            }
+
+            let size = (len - sent)
+                .min(self.peer_frame_size as u64)
+                .min(window as u64) as usize;
+
+            let flags = if sent + size as u64 == len {
+                FLAG_END_STREAM
+            } else {
+                0
+            };
+
+            // read the payload right behind the frame header, and write the frame in one go
+            frame.resize(FRAME_HEADER_SIZE + size, 0);
+            frame[..FRAME_HEADER_SIZE].copy_from_slice(&frame_head(DATA, flags, id, size));
+
+            if let Err(e) = source.read_exact(&mut frame[FRAME_HEADER_SIZE..]) {
+                debug::print(
+                    &format!("Failed to read the h2 response body: {}", e),
+                    InfoLevel::Warning,
+                );
+
+                return self.reset(id, ErrorCode::InternalError);
+            }
+
+            self.write_all(&frame)?;
+
+            sent += size as u64;
+            self.conn_send_window -= size as i64;
+            if let Some(s) = self.streams.get_mut(&id) {
+                s.send_window -= size as i64;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self, id: u32, code: ErrorCode) -> Result<(), ErrorCode> {
+        let dropped = self.streams.remove(&id).map_or(0, |s| s.unacked);
+        self.write_frame(RST_STREAM, 0, id, &(code as u32).to_be_bytes())?;
+        self.replenish(0, dropped)
+    }
+
+    /// Open up the receive window of the stream, or the connection's if the id is 0, by `inc`.
+    fn replenish(&mut self, id: u32, inc: i64) -> Result<(), ErrorCode> {
+        if inc <= 0 {
+            return Ok(());
+        }
+
+        if id == 0 {
+            self.conn_recv_window += inc;
+        } else if let Some(s) = self.streams.get_mut(&id) {
+            s.recv_window += inc;
+        }
+
+        self.write_frame(WINDOW_UPDATE, 0, id, &(inc as u32).to_be_bytes())
+    }
+
+    /// The max size of a header block, which is capped by the read limit as well, if any.
+    fn header_limit(&self) -> usize {
+        match self.read_limit {
+            0 => MAX_HEADER_BLOCK,
+            limit => limit.min(MAX_HEADER_BLOCK),
+        }
+    }
+
+    fn go_away(&mut self, code: ErrorCode) {
+        let _ = self.write_goaway(code);
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+
+        self.goaway = true;
+    }
+
+    /// Tell the peer the last stream that's going to be served, if the connection is draining, or
+    /// the last one opened otherwise.
+    fn write_goaway(&mut self, code: ErrorCode) -> Result<(), ErrorCode> {
+        let last = self.draining.unwrap_or(self.last_stream_id);
+
+        let mut payload = Vec::with_capacity(8);
+        payload.extend_from_slice(&last.to_be_bytes());
+        payload.extend_from_slice(&(code as u32).to_be_bytes());
+
+        self.write_frame(GOAWAY, 0, 0, &payload)
+    }
+
+    fn write_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: &[u8],
+    ) -> Result<(), ErrorCode> {
+        let mut buf = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&frame_head(kind, flags, stream_id, payload.len()));
+        buf.extend_from_slice(payload);
+
+        self.write_all(&buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), ErrorCode> {
+        self.stream
+            .write_all(buf)
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| {
+                debug::print(
+                    &format!("Failed to write the h2 frame: {}", e),
+                    InfoLevel::Warning,
+                );
+
+                ErrorCode::InternalError
+            })
+    }
+}
+
+/// Map a complete h2 request onto the `Request` object, and run it through the same routing,
+/// authorization and handler pipeline as the HTTP/1.1 requests.
+fn dispatch(
+    fields: HeaderList,
+    body: Vec<u8>,
+    client: Option<SocketAddr>,
+    is_tls: bool,
+) -> Box<Response> {
+    let mut request = Request::obtain();
+    let mut header = HashMap::new();
+    let mut cookie = HashMap::new();
+    let mut path = String::new();
+
+    for (name, value) in fields.into_iter() {
+        match &name[..] {
+            ":method" => request.method = conn::parse_method(&value),
+            ":path" => path = value,
+            ":authority" => {
+                header.entry(String::from("host")).or_insert(value);
+            }
+            ":scheme" => {}
+            "cookie" => conn::parse_cookie(&value, &mut cookie),
+            _ => {
+                header.insert(name, value);
+            }
+        }
+    }
+
+    let mut raw_query = String::new();
+    let mut raw_fragment = String::new();
+    conn::parse_path(&path, &mut request.uri, &mut raw_query, &mut raw_fragment);
+
+    header.insert(String::from("HTTP_VERSION"), String::from("HTTP/2.0"));
+    request.set_headers(header);
+    request.set_cookies(cookie);
+    request.set_body(String::from_utf8_lossy(&body).into_owned());
+
+    if !raw_fragment.is_empty() {
+        request.set_fragment(raw_fragment);
+    }
+
+    if !raw_query.is_empty() {
+        request.set_query_string(&raw_query);
+        request.create_query(conn::parse_query(raw_query));
+    }
+
+    if let Some(addr) = client {
+        request.set_client(addr);
+    }
+
+    let (handler, params) = Route::seek_sync(&request.method, &request.host_info(), &request.uri);
+    if handler.is_none() {
+        let resp = conn::build_err_response_for(&request, 404);
+        request.release();
+
+        return resp;
+    }
+
+    request.create_param(params);
+
+    if !Route::authorize(&request, &request.uri) {
+        let resp = conn::build_err_response_for(&request, 401);
+        request.release();
+
+        return resp;
+    }
+
+    let _flight = drain::in_flight();
+    conn::build_response(request, handler, is_tls)
+}
+
+fn strip_padding(frame: &Frame) -> Result<&[u8], ErrorCode> {
+    if frame.flags & FLAG_PADDED == 0 {
+        return Ok(&frame.payload);
+    }
+
+    let pad = match frame.payload.first() {
+        Some(&pad) => usize::from(pad),
+        None => return Err(ErrorCode::ProtocolError),
+    };
+
+    if pad + 1 > frame.payload.len() {
+        return Err(ErrorCode::ProtocolError);
+    }
+
+    Ok(&frame.payload[1..frame.payload.len() - pad])
+}
+
+#[inline]
+fn read_u32(src: &[u8]) -> u32 {
+    (u32::from(src[0]) << 24)
+        | (u32::from(src[1]) << 16)
+        | (u32::from(src[2]) << 8)
+        | u32::from(src[3])
+}
+
+#[inline]
+fn frame_head(kind: u8, flags: u8, stream_id: u32, len: usize) -> [u8; FRAME_HEADER_SIZE] {
+    let id = (stream_id & 0x7fff_ffff).to_be_bytes();
+    [
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+        kind,
+        flags,
+        id[0],
+        id[1],
+        id[2],
+        id[3],
+    ]
+}
+
+#[inline]
+fn push_setting(buf: &mut Vec<u8>, id: u16, val: u32) {
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&val.to_be_bytes());
+}
+
+#[cfg(test)]
+mod h2_test {
+    use super::*;
+    use crate::core::instance::{self, Instance};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    fn connect() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (Connection::new(Stream::Tcp(server), false, 0), client)
+    }
+
+    fn setting(id: u16, val: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_setting(&mut buf, id, val);
+        buf
+    }
+
+    fn window_update(stream_id: u32, inc: u32) -> Frame {
+        Frame {
+            kind: WINDOW_UPDATE,
+            flags: 0,
+            stream_id,
+            payload: inc.to_be_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn max_frame_size_test() {
+        let (mut conn, _client) = connect();
+
+        for size in [0, 16_383, 16_777_216] {
+            assert_eq!(
+                conn.apply_settings(&setting(SETTINGS_MAX_FRAME_SIZE, size)),
+                Err(ErrorCode::ProtocolError)
+            );
+        }
+
+        assert_eq!(conn.peer_frame_size, DEFAULT_FRAME_SIZE);
+        assert!(conn
+            .apply_settings(&setting(SETTINGS_MAX_FRAME_SIZE, 16_777_215))
+            .is_ok());
+        assert_eq!(conn.peer_frame_size, MAX_FRAME_SIZE);
+    }
+
+    #[test]
+    fn initial_window_size_test() {
+        let (mut conn, _client) = connect();
+
+        assert_eq!(
+            conn.apply_settings(&setting(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 31)),
+            Err(ErrorCode::FlowControlError)
+        );
+        assert!(conn
+            .apply_settings(&setting(SETTINGS_INITIAL_WINDOW_SIZE, (1 << 31) - 1))
+            .is_ok());
+
+        // the open stream's window can't be pushed over the limit by the new initial size either
+        let (mut conn, _client) = connect();
+        conn.streams.insert(
+            1,
+            H2Stream {
+                send_window: MAX_WINDOW_SIZE,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            conn.apply_settings(&setting(SETTINGS_INITIAL_WINDOW_SIZE, 65_536)),
+            Err(ErrorCode::FlowControlError)
+        );
+    }
+
+    #[test]
+    fn window_update_overflow_test() {
+        let (mut conn, mut client) = connect();
+
+        conn.streams.insert(
+            1,
+            H2Stream {
+                send_window: MAX_WINDOW_SIZE - 10,
+                ..Default::default()
+            },
+        );
+
+        // the stream is reset with FLOW_CONTROL_ERROR, and the connection is kept
+        assert!(conn.handle_frame(window_update(1, 11)).is_ok());
+        assert!(!conn.streams.contains_key(&1));
+
+        let mut frame = [0u8; FRAME_HEADER_SIZE + 4];
+        client.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[3], RST_STREAM);
+        assert_eq!(read_u32(&frame[5..]), 1);
+        assert_eq!(
+            read_u32(&frame[FRAME_HEADER_SIZE..]),
+            ErrorCode::FlowControlError as u32
+        );
+
+        // overflowing the connection window is a connection error
+        assert_eq!(
+            conn.handle_frame(window_update(0, MAX_WINDOW_SIZE as u32)),
+            Err(ErrorCode::FlowControlError)
+        );
+    }
+
+    #[test]
+    fn send_file_data_test() {
+        let path = std::env::temp_dir().join("rusty_express_h2_send_data");
+        std::fs::write(&path, vec![7u8; 40_000]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+
+        let (mut conn, mut client) = connect();
+        conn.streams.insert(
+            1,
+            H2Stream {
+                send_window: DEFAULT_WINDOW_SIZE,
+                ..Default::default()
+            },
+        );
+
+        assert!(conn.send_data(1, &mut &file, 40_000).is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        // the file is sent in the frames no larger than the peer's max frame size
+        let mut sizes = Vec::new();
+        loop {
+            let mut head = [0u8; FRAME_HEADER_SIZE];
+            client.read_exact(&mut head).unwrap();
+
+            let len = (usize::from(head[1]) << 8) | usize::from(head[2]);
+            let mut payload = vec![0u8; len];
+            client.read_exact(&mut payload).unwrap();
+
+            assert_eq!(head[3], DATA);
+            assert!(payload.iter().all(|b| *b == 7));
+            sizes.push(len);
+
+            if head[4] & FLAG_END_STREAM != 0 {
+                break;
+            }
+        }
+
+        assert_eq!(sizes, vec![16_384, 16_384, 7_232]);
+        assert_eq!(conn.conn_send_window, DEFAULT_WINDOW_SIZE - 40_000);
+    }
+
+    fn read_frame_from(client: &mut TcpStream) -> (u8, u32, Vec<u8>) {
+        let mut head = [0u8; FRAME_HEADER_SIZE];
+        client.read_exact(&mut head).unwrap();
+
+        let len = (usize::from(head[1]) << 8) | usize::from(head[2]);
+        let mut payload = vec![0u8; len];
+        client.read_exact(&mut payload).unwrap();
+
+        (head[3], read_u32(&head[5..]), payload)
+    }
+
+    #[test]
+    fn read_limit_test() {
+        let (mut conn, mut client) = connect();
+        conn.read_limit = 100;
+        conn.streams.insert(
+            1,
+            H2Stream {
+                recv_window: DEFAULT_WINDOW_SIZE,
+                headers_done: true,
+                ..Default::default()
+            },
+        );
+
+        let data = |payload: Vec<u8>| Frame {
+            kind: DATA,
+            flags: 0,
+            stream_id: 1,
+            payload,
+        };
+
+        // no window is granted beyond the read limit, and nothing is credited before consumed
+        assert!(conn.handle_frame(data(vec![1; 60])).is_ok());
+        assert_eq!(conn.streams[&1].unacked, 60);
+
+        // the body over the limit is dropped, and the connection window is credited for it
+        assert!(conn.handle_frame(data(vec![1; 60])).is_ok());
+        assert!(!conn.streams.contains_key(&1));
+
+        let (kind, id, payload) = read_frame_from(&mut client);
+        assert_eq!((kind, id), (RST_STREAM, 1));
+        assert_eq!(read_u32(&payload), ErrorCode::EnhanceYourCalm as u32);
+
+        let (kind, id, payload) = read_frame_from(&mut client);
+        assert_eq!((kind, id, read_u32(&payload)), (WINDOW_UPDATE, 0, 120));
+        assert_eq!(conn.conn_recv_window, DEFAULT_WINDOW_SIZE);
+
+        // the header block over the limit breaks the decoder state, so it's a connection error
+        let headers = Frame {
+            kind: HEADERS,
+            flags: 0,
+            stream_id: 3,
+            payload: vec![0; 80],
+        };
+        assert!(conn.handle_frame(headers).is_ok());
+
+        let continuation = Frame {
+            kind: CONTINUATION,
+            flags: FLAG_END_HEADERS,
+            stream_id: 3,
+            payload: vec![0; 80],
+        };
+        assert_eq!(
+            conn.handle_frame(continuation),
+            Err(ErrorCode::CompressionError)
+        );
+    }
+
+    #[test]
+    fn drain_goaway_test() {
+        let (mut conn, mut client) = connect();
+        conn.last_stream_id = 5;
+
+        let instance = Instance::new();
+        let _scope = instance::enter(&instance);
+
+        let tracked = match conn.stream {
+            Stream::Tcp(ref s) => drain::track(s),
+            _ => unreachable!(),
+        };
+
+        // hold a request in flight, such that the connection is only closed at the deadline
+        let flight = drain::in_flight();
+        let bound = Arc::clone(&instance);
+        let draining = thread::spawn(move || {
+            let _scope = instance::enter(&bound);
+            drain::drain(Duration::from_millis(300))
+        });
+
+        // waiting for the frames till the connection is closed at the deadline
+        assert_eq!(conn.read_frame().err(), Some(ErrorCode::NoError));
+        assert_eq!(draining.join().unwrap().dropped, 1);
+
+        // the peer was told of the last stream to be served as soon as the drain started
+        let (kind, id, payload) = read_frame_from(&mut client);
+        assert_eq!((kind, id), (GOAWAY, 0));
+        assert_eq!(read_u32(&payload), 5);
+        assert_eq!(read_u32(&payload[4..]), ErrorCode::NoError as u32);
+
+        drop(flight);
+        drop(tracked);
+    }
+
+    #[test]
+    fn peek_preface_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        // the preface arrives in two parts, which shall be waited for
+        client.write_all(&PREFACE[..10]).unwrap();
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            client.write_all(&PREFACE[10..]).unwrap();
+            client
+        });
+
+        let mut buf = [0u8; PEEK_SIZE];
+        let len = peek_preface(&server, &mut buf, IDLE_TIMEOUT).unwrap();
+        assert!(len >= PREFACE.len() && buf.starts_with(PREFACE));
+
+        sender.join().unwrap();
+
+        // an HTTP/1 request is told apart right away
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        let start = Instant::now();
+        let len = peek_preface(&server, &mut buf, IDLE_TIMEOUT).unwrap();
+        assert!(len > 0 && !buf.starts_with(PREFACE));
+        assert!(start.elapsed() < IDLE_TIMEOUT);
+    }
+}
@@ -1,13 +1,14 @@
 #![allow(dead_code)]
 
+use std::cell::UnsafeCell;
 use std::collections;
 use std::fs::File;
 use std::io::{prelude::*, BufReader, BufWriter};
 use std::mem;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::ptr;
-use std::str;
+use std::str::{self, FromStr};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
@@ -17,11 +18,13 @@ use crate::core::syncstore::{Reusable, StaticStore, SyncPool, TOTAL_ELEM_COUNT};
 use crate::core::{
     config::{ConnMetadata, EngineContext, ServerConfig, ViewEngineParser},
     cookie::*,
+    instance,
     router::REST,
     stream::Stream,
 };
 use crate::hashbrown::{hash_map::Iter, HashMap};
-use crate::support::{common::*, debug, debug::InfoLevel, shared_pool, TaskType};
+use crate::parking_lot::Mutex;
+use crate::support::{cache, common::*, debug, debug::InfoLevel, negotiate, shared_pool, TaskType};
 
 const FOUR_OH_FOUR: &str = include_str!("../default/404.html");
 const FOUR_OH_ONE: &str = include_str!("../default/401.html");
@@ -32,16 +35,48 @@ const RESP_TIMEOUT: Duration = Duration::from_millis(64);
 const LONG_CONN_TIMEOUT: Duration = Duration::from_secs(8);
 const HEADER_END: [u8; 2] = [13, 10];
 
+/// Files larger than this size will be streamed from the file directly, instead of being loaded
+/// into the response body.
+const FILE_STREAM_THRESHOLD: u64 = 64 * 1024;
+
 type BodyChan = (
     Option<Sender<(Vec<u8>, u16)>>,
     Option<Receiver<(Vec<u8>, u16)>>,
 );
 type NotifyChan = Option<(Sender<String>, Receiver<String>)>;
 
-static mut REQ_POOL: StaticStore<SyncPool<Request>> = StaticStore::init();
-static mut RESP_POOL: StaticStore<SyncPool<Response>> = StaticStore::init();
-static mut POOL_CHAN: StaticStore<(channel::Sender<()>, channel::Receiver<()>)> =
-    StaticStore::init();
+/// The request and the response pools of a server instance, which are set up when the server is
+/// launched, see `init_pools`.
+pub(crate) struct PoolState {
+    requests: UnsafeCell<StaticStore<SyncPool<Request>>>,
+    responses: UnsafeCell<StaticStore<SyncPool<Response>>>,
+    chan: Mutex<Option<channel::Sender<()>>>,
+}
+
+// The pools are synchronized by themselves, and they're only set up or dropped when no one else is
+// using them, i.e. before the server is launched, or after it's drained.
+unsafe impl Send for PoolState {}
+unsafe impl Sync for PoolState {}
+
+impl PoolState {
+    pub(crate) fn new() -> Self {
+        PoolState {
+            requests: UnsafeCell::new(StaticStore::init()),
+            responses: UnsafeCell::new(StaticStore::init()),
+            chan: Mutex::new(None),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn requests(&self) -> &mut StaticStore<SyncPool<Request>> {
+        unsafe { &mut *self.requests.get() }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn responses(&self) -> &mut StaticStore<SyncPool<Response>> {
+        unsafe { &mut *self.responses.get() }
+    }
+}
 
 //TODO: pub http version?
 
@@ -63,14 +98,17 @@ impl Default for KeepAliveStatus {
 pub struct Request {
     pub method: REST,
     pub uri: String,
+    base_uri: String,
     params: HashMap<String, String>,
     query: HashMap<String, Vec<String>>,
+    query_string: String,
     header: HashMap<String, String>,
     cookie: HashMap<String, String>,
     fragment: String,
     host: String,
     body: String,
     client_info: Option<SocketAddr>,
+    principal: Option<String>,
 }
 
 impl Request {
@@ -136,6 +174,102 @@ impl Request {
         }
     }
 
+    /// The raw query string of the request uri, without the leading `?`.
+    #[inline]
+    pub fn query_string(&self) -> String {
+        self.query_string.clone()
+    }
+
+    /// Pick the best media type from the `offers` based on the `Accept` header of the request, with
+    /// the q-values and wildcards taken into account. The offers can be full media types, e.g.
+    /// `application/json`, or file extensions, e.g. `json`. If tied, the offer listed first wins,
+    /// and if none of the offers is acceptable, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rusty_express::prelude::*;
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     match req.accepts(&["text/html", "application/json"]) {
+    ///         Some(ref t) if t == "application/json" => resp.send("{\"hello\":\"world\"}"),
+    ///         Some(_) => resp.send("<p>hello world</p>"),
+    ///         None => resp.status(406),
+    ///     }
+    /// }
+    /// ```
+    pub fn accepts(&self, offers: &[&str]) -> Option<String> {
+        let media: Vec<String> = offers
+            .iter()
+            .map(|offer| {
+                if offer.contains('/') {
+                    (*offer).to_owned()
+                } else {
+                    ConnMetadata::mime_type(offer.trim_start_matches('.'))
+                }
+            })
+            .collect();
+
+        let types: Vec<&str> = media.iter().map(|t| &t[..]).collect();
+
+        self.negotiate("accept", offers, &types, negotiate::media_type)
+    }
+
+    /// Pick the best language from the `offers` based on the `Accept-Language` header, e.g. for
+    /// `Accept-Language: en-US, fr;q=0.5`, the offer `en` is preferred over `fr-CA`.
+    pub fn accepts_language(&self, offers: &[&str]) -> Option<String> {
+        self.negotiate("accept-language", offers, offers, negotiate::language)
+    }
+
+    /// Pick the best charset from the `offers` based on the `Accept-Charset` header.
+    pub fn accepts_charset(&self, offers: &[&str]) -> Option<String> {
+        self.negotiate("accept-charset", offers, offers, negotiate::token)
+    }
+
+    /// Pick the best content coding from the `offers` based on the `Accept-Encoding` header. The
+    /// `identity` coding is always acceptable unless it's explicitly refused by the client.
+    pub fn accepts_encoding(&self, offers: &[&str]) -> Option<String> {
+        let result = self.negotiate("accept-encoding", offers, offers, negotiate::token);
+        if result.is_some() {
+            return result;
+        }
+
+        let identity = offers.iter().find(|o| o.eq_ignore_ascii_case("identity"))?;
+
+        match self.header.get("accept-encoding") {
+            Some(raw) if negotiate::quality(raw, identity, negotiate::token) == Some(0.0) => None,
+            _ => Some((*identity).to_owned()),
+        }
+    }
+
+    fn negotiate(
+        &self,
+        field: &str,
+        offers: &[&str],
+        values: &[&str],
+        matcher: fn(&str, &str) -> Option<u8>,
+    ) -> Option<String> {
+        let header = self.header.get(field).map(|raw| &raw[..]);
+
+        negotiate::negotiate(header, values, matcher).map(|index| offers[index].to_owned())
+    }
+
+    /// The path of the router this request is dispatched to, if it's mounted with `Router::mount`,
+    /// e.g. `/api/v1`; otherwise it's empty.
+    #[inline]
+    pub fn base_uri(&self) -> String {
+        self.base_uri.clone()
+    }
+
+    /// The request uri with the mount path stripped, i.e. the part seen by the mounted router, e.g.
+    /// `/users` for the request to `/api/v1/users`.
+    pub fn local_uri(&self) -> String {
+        match self.uri.get(self.base_uri.len()..) {
+            Some(rest) if !rest.is_empty() => rest.to_owned(),
+            _ => String::from("/"),
+        }
+    }
+
     pub fn uri_fragment(&self) -> String {
         self.fragment.clone()
     }
@@ -147,6 +281,12 @@ impl Request {
         }
     }
 
+    /// Parse the param into the given type, e.g. `req.param_as::<u64>("id")` for the route
+    /// `/user/:id<u64>`, which returns `None` if the param is missing or can't be parsed.
+    pub fn param_as<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.params.get(key)?.parse().ok()
+    }
+
     #[inline]
     pub fn param_iter(&self) -> Iter<String, String> {
         self.params.iter()
@@ -162,6 +302,15 @@ impl Request {
         self.host.clone()
     }
 
+    /// The principal authenticated by the `AuthPolicy` of the route, if any.
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    pub(crate) fn set_principal(&mut self, principal: Option<String>) {
+        self.principal = principal;
+    }
+
     #[must_use]
     pub fn form_data(&self) -> collections::HashMap<String, String> {
         let mut data = collections::HashMap::new();
@@ -235,7 +384,7 @@ impl Request {
 
 impl Reusable for Request {
     fn obtain() -> Box<Self> {
-        match unsafe { REQ_POOL.as_mut() } {
+        match instance::current().pools.requests().as_mut() {
             Ok(pool) => pool.get(),
             Err(_) => Default::default(),
         }
@@ -244,7 +393,7 @@ impl Reusable for Request {
     fn release(mut self: Box<Self>) {
         self.reset(false);
 
-        if let Ok(pool) = unsafe { REQ_POOL.as_mut() } {
+        if let Ok(pool) = instance::current().pools.requests().as_mut() {
             pool.put(self);
         }
     }
@@ -255,12 +404,16 @@ impl Reusable for Request {
         if !hard {
             unsafe {
                 self.uri.as_mut_vec().set_len(0);
+                self.base_uri.as_mut_vec().set_len(0);
+                self.query_string.as_mut_vec().set_len(0);
                 self.fragment.as_mut_vec().set_len(0);
                 self.host.as_mut_vec().set_len(0);
                 self.body.as_mut_vec().set_len(0);
             }
         } else {
             self.uri.clear();
+            self.base_uri.clear();
+            self.query_string.clear();
             self.fragment.clear();
             self.host.clear();
             self.body.clear();
@@ -274,6 +427,8 @@ impl Reusable for Request {
         if self.client_info.is_some() {
             self.client_info.take();
         }
+
+        self.principal = None;
     }
 }
 
@@ -281,6 +436,8 @@ pub trait RequestWriter {
     fn write_header(&mut self, key: &str, val: &str, allow_override: bool);
     fn write_query(&mut self, key: &str, val: Vec<String>, allow_override: bool);
     fn create_query(&mut self, query: HashMap<String, Vec<String>>);
+    fn set_query_string(&mut self, query: &str);
+    fn set_base_uri(&mut self, base: &str);
     fn set_cookie(&mut self, key: &str, val: &str, allow_override: bool);
     fn create_cookie(&mut self, cookie: HashMap<String, String>);
     fn set_param(&mut self, key: &str, val: &str);
@@ -304,6 +461,14 @@ impl RequestWriter for Request {
         self.query = query;
     }
 
+    fn set_query_string(&mut self, query: &str) {
+        self.query_string = query.trim_start_matches('?').to_owned();
+    }
+
+    fn set_base_uri(&mut self, base: &str) {
+        self.base_uri = base.to_owned();
+    }
+
     fn set_cookie(&mut self, key: &str, val: &str, allow_override: bool) {
         self.cookie.add(key, val.to_owned(), allow_override, true);
     }
@@ -341,6 +506,28 @@ impl RequestWriter for Request {
     }
 }
 
+/// The response body handed over to the HTTP/2 connection, which frames it on its own, or to the
+/// test client, see `Response::take_parts`.
+pub(crate) enum ResponseBody {
+    Bytes(Vec<u8>),
+    Shared(Arc<Vec<u8>>),
+    File(File, u64),
+}
+
+impl ResponseBody {
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            ResponseBody::Bytes(content) => content.len() as u64,
+            ResponseBody::Shared(content) => content.len() as u64,
+            ResponseBody::File(_, len) => *len,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 #[derive(Default)]
 pub struct Response {
     status: u16,
@@ -351,7 +538,12 @@ pub struct Response {
     cookie: HashMap<String, Cookie>,
     header_only: bool,
     redirect: String,
+    redirect_status: u16,
+    redirect_query: bool,
+    json_error: bool,
     body: Vec<u8>,
+    body_cached: Option<Arc<Vec<u8>>>,
+    body_file: Option<(File, u64)>,
     body_chan: BodyChan,
     notifier: NotifyChan,
     subscriber: NotifyChan,
@@ -366,18 +558,62 @@ impl Response {
         self.header = header;
     }
 
-    pub(crate) fn redirect_handling(&mut self) {
+    pub(crate) fn redirect_handling(&mut self, query: &str) {
         // if a redirect response, set up as so.
         let mut redirect = self.get_redirect_path();
+        if redirect.is_empty() {
+            return;
+        }
+
+        if redirect.contains(['\r', '\n']) {
+            // the line breaks would end the `Location` header and inject whatever follows
+            debug::print(
+                "Redirect to a location with line breaks is rejected",
+                InfoLevel::Warning,
+            );
+
+            self.status(403);
+            self.body_cached = None;
+            self.body.clear();
+            return;
+        }
+
+        if let Some(host) = redirect_host(&redirect) {
+            // absolute url, only allowed if the host is in the allowlist
+            if !ConnMetadata::is_redirect_host_allowed(&host) {
+                debug::print(
+                    &format!("Redirect to a disallowed host is rejected: {}", host),
+                    InfoLevel::Warning,
+                );
 
-        if !redirect.is_empty() {
-            if !redirect.starts_with('/') {
-                redirect.insert(0, '/');
+                self.status(403);
+                self.body_cached = None;
+                self.body.clear();
+                return;
             }
+        } else if !redirect.starts_with('/') {
+            redirect.insert(0, '/');
+        }
 
-            self.header("Location", &redirect, true);
-            self.status(301);
+        if self.redirect_query && !query.is_empty() {
+            // the query shall go before the fragment, if there's any
+            let pos = redirect.find('#').unwrap_or(redirect.len());
+            let delimiter = if redirect[..pos].contains('?') {
+                '&'
+            } else {
+                '?'
+            };
+
+            redirect.insert_str(pos, query);
+            redirect.insert(pos, delimiter);
         }
+
+        self.header("Location", &redirect, true);
+        self.status(if self.redirect_status > 0 {
+            self.redirect_status
+        } else {
+            301
+        });
     }
 
     fn write_resp_header(&mut self, buffer: &mut BufWriter<&mut Stream>) {
@@ -423,11 +659,11 @@ impl Response {
             header.append_line_break();
         } else {
             // Only generate content length header attribute if not using async and no content-length set explicitly
-            if self.is_header_only() || self.body.is_empty() {
+            if self.is_header_only() || self.body_len() == 0 {
                 header.reserve(19);
                 header.extend_from_slice(b"Content-Length: 0\r\n");
             } else {
-                let size = self.body.len().to_string();
+                let size = self.body_len().to_string();
 
                 header.reserve(18 + size.len());
                 header.extend_from_slice(b"Content-Length: ");
@@ -461,21 +697,142 @@ impl Response {
         }
     }
 
-    fn set_ext_mime_header(&mut self, path: &PathBuf) {
-        let mime_type = if let Some(ext) = path.extension() {
-            let file_extension = ext.to_string_lossy();
-            default_mime_type_with_ext(&file_extension)
+    /// Decompose the response into the status code, the header fields and the body, such that it
+    /// can be framed by the HTTP/2 connection, or inspected by the test client. Connection-specific
+    /// headers are dropped, since they are not allowed in HTTP/2.
+    pub(crate) fn take_parts(&mut self) -> (u16, Vec<(String, String)>, ResponseBody) {
+        let status = match self.status {
+            0 if self.has_contents() => 200,
+            0 => 404,
+            _ => self.status,
+        };
+
+        let mut fields = Vec::with_capacity(self.header.len() + self.cookie.len() + 4);
+        fields.push((String::from("server"), ["Rusty-Express/", VERSION].join("")));
+
+        if !self.header.contains_key("date") {
+            let dt = Utc::now().format("%a, %e %b %Y %T GMT").to_string();
+            fields.push((String::from("date"), dt));
+        }
+
+        for (field, value) in self.header.iter() {
+            let name = field.to_lowercase();
+            match &name[..] {
+                "connection" | "keep-alive" | "transfer-encoding" | "upgrade" => continue,
+                _ => fields.push((name, value.to_owned())),
+            }
+        }
+
+        if !self.content_type.is_empty() {
+            fields.push((String::from("content-type"), self.content_type.to_owned()));
+        }
+
+        for (_, cookie) in self.cookie.iter() {
+            if cookie.is_valid() {
+                fields.push((String::from("set-cookie"), cookie.to_string()));
+            }
+        }
+
+        let body = if self.is_header_only() {
+            ResponseBody::Bytes(Vec::new())
+        } else if let Some((file, len)) = self.body_file.take() {
+            // the h2 connection will read the file in frame-sized chunks
+            ResponseBody::File(file, len)
+        } else if let Some(content) = self.body_cached.take() {
+            ResponseBody::Shared(content)
         } else {
-            String::from("text/plain")
+            ResponseBody::Bytes(self.body.swap_reset())
+        };
+
+        let length = match self.content_length.as_ref() {
+            Some(length) => length.to_owned(),
+            None => body.len().to_string(),
         };
 
-        self.set_content_type(&mime_type);
+        fields.push((String::from("content-length"), length));
+
+        (status, fields, body)
+    }
+
+    /// Load the file from the static file cache, if it's cached and nothing else has been written
+    /// to the body.
+    fn send_cached_file(&mut self, path: &PathBuf) -> bool {
+        if !self.body_is_free() {
+            return false;
+        }
+
+        match cache::lookup(path) {
+            Some(content) => {
+                // the cached content is shared, not copied, until more is written to the body
+                self.body_cached = Some(content);
+
+                if self.content_type.is_empty() {
+                    self.set_ext_mime_header(path);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set up the file to be streamed as the response body, if it's large enough and nothing else
+    /// has been written to the body. Return `None` if the file shall be loaded into the body instead.
+    fn stream_file(&mut self, path: &PathBuf) -> Option<u16> {
+        if !self.body_is_free() {
+            return None;
+        }
+
+        let file = File::open(path).ok()?;
+        let len = file.metadata().ok().filter(|m| m.is_file())?.len();
+
+        if len <= FILE_STREAM_THRESHOLD {
+            return None;
+        }
+
+        if self.content_type.is_empty() {
+            self.set_ext_mime_header(path);
+        }
+
+        self.content_length = Some(len.to_string());
+        self.body_file = Some((file, len));
+
+        Some(200)
+    }
+
+    fn set_ext_mime_header(&mut self, path: &PathBuf) {
+        self.set_content_type(&ConnMetadata::content_type(path));
+    }
+
+    /// If nothing has been written to the body yet.
+    fn body_is_free(&self) -> bool {
+        self.body.is_empty()
+            && self.body_cached.is_none()
+            && self.body_chan.0.is_none()
+            && self.body_file.is_none()
+    }
+
+    fn body_len(&self) -> usize {
+        match self.body_cached.as_ref() {
+            Some(content) => content.len(),
+            None => self.body.len(),
+        }
+    }
+
+    /// The body to write more contents to, where the shared cached content, if any, has to be
+    /// copied over first.
+    fn body_mut(&mut self) -> &mut Vec<u8> {
+        if let Some(content) = self.body_cached.take() {
+            self.body.extend_from_slice(&content);
+        }
+
+        &mut self.body
     }
 }
 
 impl Reusable for Response {
     fn obtain() -> Box<Self> {
-        match unsafe { RESP_POOL.as_mut() } {
+        match instance::current().pools.responses().as_mut() {
             Ok(pool) => pool.get(),
             Err(_) => Default::default(),
         }
@@ -484,13 +841,16 @@ impl Reusable for Response {
     fn release(mut self: Box<Self>) {
         self.reset(false);
 
-        if let Ok(pool) = unsafe { RESP_POOL.as_mut() } {
+        if let Ok(pool) = instance::current().pools.responses().as_mut() {
             pool.put(self);
         }
     }
 
     fn reset(&mut self, hard: bool) {
         self.status = 0;
+        self.redirect_status = 0;
+        self.redirect_query = false;
+        self.json_error = false;
         self.keep_alive = KeepAliveStatus::NotSet;
 
         if !hard {
@@ -509,6 +869,11 @@ impl Reusable for Response {
             self.content_length.take();
         }
 
+        if self.body_file.is_some() {
+            self.body_file.take();
+        }
+
+        self.body_cached = None;
         self.header_only = false;
         self.header.clear();
         self.cookie.clear();
@@ -562,7 +927,11 @@ impl ResponseStates for Response {
 
     #[inline]
     fn has_contents(&self) -> bool {
-        (self.is_header_only() || !self.body.is_empty() || self.body_chan.0.is_some())
+        self.is_header_only()
+            || !self.body.is_empty()
+            || self.body_cached.is_some()
+            || self.body_file.is_some()
+            || self.body_chan.0.is_some()
     }
 
     #[inline]
@@ -616,6 +985,8 @@ pub trait ResponseWriter {
     fn keep_alive(&mut self, to_keep: bool);
     fn set_content_type(&mut self, content_type: &str);
     fn redirect(&mut self, path: &str);
+    fn redirect_with(&mut self, location: &str, status: u16, keep_query: bool);
+    fn format(&mut self, req: &Request, choices: &[(&str, &str)]);
 }
 
 impl ResponseWriter for Response {
@@ -627,7 +998,7 @@ impl ResponseWriter for Response {
         self.status = match status {
             100..=101 => status,
             200..=206 => status,
-            300..=308 if status != 307 && status != 308 => status,
+            300..=305 | 307 | 308 => status,
             400..=417 if status != 402 => status,
             426 | 428 | 429 | 431 | 451 => status,
             500..=505 | 511 => status,
@@ -791,8 +1162,9 @@ impl ResponseWriter for Response {
         }
 
         if !content.is_empty() {
-            self.body.reserve(content.len());
-            self.body.extend_from_slice(content.as_bytes());
+            let body = self.body_mut();
+            body.reserve(content.len());
+            body.extend_from_slice(content.as_bytes());
         }
     }
 
@@ -897,7 +1269,23 @@ impl ResponseWriter for Response {
             return 200;
         }
 
-        let status = open_file(&path, &mut self.body);
+        if self.send_cached_file(&path) {
+            return 200;
+        }
+
+        // large files will be streamed to the client directly from the file
+        if let Some(status) = self.stream_file(&path) {
+            return status;
+        }
+
+        let was_empty = self.body_is_free();
+        let status = open_file(&path, self.body_mut());
+
+        if status == 200 && was_empty && cache::is_enabled() {
+            // hand the file content over to the cache, and share it from there
+            let content = mem::take(&mut self.body);
+            self.body_cached = Some(cache::store(&path, content));
+        }
 
         if status != 200 && status != 0 {
             // if not opening the file correctly, reset the body for error page
@@ -924,15 +1312,24 @@ impl ResponseWriter for Response {
             return;
         }
 
+        // set header's mime extension field
+        self.set_ext_mime_header(&path);
+
+        if self.send_cached_file(&path) {
+            return;
+        }
+
+        // large files will be streamed to the client directly from the file
+        if self.stream_file(&path).is_some() {
+            return;
+        }
+
         // lazy init the tx-rx pair.
         if self.body_chan.0.is_none() {
             let (tx, rx) = channel::bounded(4);
             self.body_chan = (Some(tx), Some(rx));
         }
 
-        // set header's mime extension field
-        self.set_ext_mime_header(&path);
-
         // actually load the file to the response body
         if let Some(chan) = self.body_chan.0.as_ref() {
             open_file_async(path, chan.clone());
@@ -974,6 +1371,7 @@ impl ResponseWriter for Response {
             let (status, final_content) = ServerConfig::template_parser(&ext[..], content, context);
 
             if status == 0 || status == 200 {
+                self.body_cached = None;
                 self.body = final_content;
                 if self.content_type.is_empty() {
                     // if read the file good and not set the mime yet, set the mime
@@ -1040,15 +1438,88 @@ impl ResponseWriter for Response {
         }
     }
 
-    /// Can only redirect to internal path, no outsource path, sorry for the hackers (FYI, you can
-    /// still hack the redirection link via Javascript)!
+    /// Redirect the client to the given location with `301 Moved Permanently`. Note that browsers
+    /// will cache a permanent redirect, use `redirect_with` if the redirect is temporary, e.g. in
+    /// a login flow.
+    ///
+    /// Only internal paths are allowed, unless the host of an absolute URL has been added to the
+    /// allowlist with `ServerConfig::allow_redirect_host`, otherwise the response will be a `403`.
     fn redirect(&mut self, path: &str) {
-        self.redirect = path.to_owned();
+        self.redirect_with(path, 301, false);
+    }
+
+    /// Redirect the client to the given location with the given status code, which shall be one of
+    /// 301, 302, 303, 307 or 308, otherwise `302 Found` will be used. If `keep_query` is true, the
+    /// query string of the request will be appended to the new location.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rusty_express::prelude::*;
+    ///
+    /// pub fn login_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     // temporary redirect, and keep the `?next=...` query for after signing in
+    ///     resp.redirect_with("/account/login", 303, true);
+    /// }
+    /// ```
+    fn redirect_with(&mut self, location: &str, status: u16, keep_query: bool) {
+        self.redirect = location.to_owned();
+        self.redirect_query = keep_query;
+        self.redirect_status = match status {
+            301 | 302 | 303 | 307 | 308 => status,
+            _ => {
+                debug::print(
+                    &format!("Invalid redirect status: {}, using 302 instead", status),
+                    InfoLevel::Warning,
+                );
+
+                302
+            }
+        };
+    }
+
+    /// Send the representation that best matches the `Accept` header of the request, where each
+    /// choice is a tuple of the media type and the content of the representation. The content type
+    /// and the `Vary` header will be set accordingly, and if none of the choices is acceptable to the
+    /// client, the response will be a `406 Not Acceptable`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rusty_express::prelude::*;
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.format(
+    ///         req,
+    ///         &[
+    ///             ("text/html", "<p>hello world</p>"),
+    ///             ("application/json", "{\"hello\":\"world\"}"),
+    ///         ],
+    ///     );
+    /// }
+    /// ```
+    fn format(&mut self, req: &Request, choices: &[(&str, &str)]) {
+        self.header("Vary", "Accept", false);
+
+        let types: Vec<&str> = choices.iter().map(|choice| choice.0).collect();
+        let picked = match req.accepts(&types) {
+            Some(t) => t,
+            None => {
+                self.status(406);
+                return;
+            }
+        };
+
+        if let Some(choice) = choices.iter().find(|choice| choice.0 == picked) {
+            self.set_content_type(choice.0);
+            self.send(choice.1);
+        }
     }
 }
 
 pub(crate) trait ResponseManager {
     fn header_only(&mut self, header_only: bool);
+    fn error_format(&mut self, request: &Request);
     fn validate_and_update(&mut self);
     fn write_header(&mut self, buffer: &mut BufWriter<&mut Stream>) -> bool;
     fn write_body(&self, buffer: &mut BufWriter<&mut Stream>) -> bool;
@@ -1061,6 +1532,19 @@ impl ResponseManager for Response {
         self.header_only = header_only;
     }
 
+    /// Decide if the default error pages shall be sent in JSON, which is only done if the client
+    /// prefers `application/json` over `text/html`.
+    fn error_format(&mut self, request: &Request) {
+        if self.has_contents() {
+            return;
+        }
+
+        self.json_error = request
+            .accepts(&["text/html", "application/json"])
+            .filter(|t| t == "application/json")
+            .is_some();
+    }
+
     fn validate_and_update(&mut self) {
         if self.status != 0 && (self.status < 200 || self.status == 204 || self.status == 304) {
             self.header_only(true);
@@ -1079,12 +1563,14 @@ impl ResponseManager for Response {
                     if received.1 == 200 {
                         // read the content
                         if !received.0.is_empty() {
-                            self.body.reserve(received.0.len());
-                            self.body.extend_from_slice(&received.0);
+                            let body = self.body_mut();
+                            body.reserve(received.0.len());
+                            body.extend_from_slice(&received.0);
                         }
                     } else {
                         // faulty, clear the content
                         self.status = 500;
+                        self.body_cached = None;
                         self.body.clear();
                         break;
                     }
@@ -1098,6 +1584,17 @@ impl ResponseManager for Response {
         }
 
         // if not setting the header only and not having a body, it's a failure
+        if self.json_error {
+            let status = if self.status == 0 { 404 } else { self.status };
+
+            // custom status pages always take precedence
+            if ConnMetadata::get_status_pages(status).is_none() {
+                self.content_type = String::from("application/json");
+                self.body = json_error_page(status).into_bytes();
+                return;
+            }
+        }
+
         match self.status {
             0 | 404 => {
                 if let Some(page_generator) = ConnMetadata::get_status_pages(404) {
@@ -1137,7 +1634,13 @@ impl ResponseManager for Response {
     }
 
     fn write_body(&self, buffer: &mut BufWriter<&mut Stream>) -> bool {
-        if self.has_contents() {
+        if let Some((file, len)) = self.body_file.as_ref() {
+            return send_file_body(file, *len, buffer);
+        }
+
+        if let Some(content) = self.body_cached.as_ref() {
+            write_to_buff(buffer, content);
+        } else if self.has_contents() {
             // the content length should have been set in the header, see function resp_header
             write_to_buff(buffer, &self.body);
         } else {
@@ -1149,7 +1652,19 @@ impl ResponseManager for Response {
     }
 
     fn keep_long_conn(&mut self, stream_clone: Stream, buffer: &mut BufWriter<&mut Stream>) {
-        if self.has_contents() {
+        if let Some((file, len)) = self.body_file.as_ref() {
+            // the file is sent as one trunk
+            write_trunk_size(*len as usize, buffer);
+
+            if !send_file_body(file, *len, buffer) {
+                return;
+            }
+
+            write_line_break(buffer);
+            flush_buffer(buffer);
+        } else if let Some(content) = self.body_cached.as_ref() {
+            stream_trunk(content, buffer);
+        } else if self.has_contents() {
             // the content length should have been set in the header, see function resp_header
             stream_trunk(&self.body, buffer);
         }
@@ -1185,13 +1700,17 @@ impl ResponseManager for Response {
 }
 
 pub(crate) fn init_pools() {
-    unsafe {
-        REQ_POOL.set(SyncPool::new());
-        RESP_POOL.set(SyncPool::new());
-        POOL_CHAN.set(channel::bounded(0))
-    }
+    let instance = instance::current();
+    let (tx, rx) = channel::bounded(0);
+
+    instance.pools.requests().set(SyncPool::new());
+    instance.pools.responses().set(SyncPool::new());
+    instance.pools.chan.lock().replace(tx);
 
-    thread::spawn(|| {
+    // the pools are refilled as long as the server instance is around
+    let instance = Arc::downgrade(&instance);
+
+    thread::spawn(move || {
         let cap = TOTAL_ELEM_COUNT / 5;
         let mut count = 0;
 
@@ -1199,24 +1718,26 @@ pub(crate) fn init_pools() {
             thread::sleep(Duration::from_secs(1));
             count += 1;
 
-            if let Ok(chan) = unsafe { POOL_CHAN.as_ref() } {
-                match chan.1.try_recv() {
-                    Ok(_) | Err(TryRecvError::Disconnected) => return,
-                    _ => {}
-                }
-            } else {
-                // shouldn't happen, but we shall quit now
-                return;
+            match rx.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => return,
+                _ => {}
             }
 
             if count % 30 == 0 {
-                if let Ok(pool) = unsafe { REQ_POOL.as_mut() } {
+                let instance = match instance.upgrade() {
+                    Some(instance) => instance,
+                    None => return,
+                };
+
+                let _scope = instance::enter(&instance);
+
+                if let Ok(pool) = instance.pools.requests().as_mut() {
                     if pool.len() < cap {
                         pool.refill(cap);
                     }
                 }
 
-                if let Ok(pool) = unsafe { RESP_POOL.as_mut() } {
+                if let Ok(pool) = instance.pools.responses().as_mut() {
                     if pool.len() < cap {
                         pool.refill(cap);
                     }
@@ -1228,22 +1749,19 @@ pub(crate) fn init_pools() {
     });
 }
 
-pub(crate) fn drop_statics() {
-    unsafe {
-        if let Ok(chan) = POOL_CHAN.as_ref() {
-            // zero-sized channel will block until the message is read, which shall happen evey second.
-            chan.0.send(()).unwrap_or_default();
-        }
-
-        // take the pools out
-        let _ = POOL_CHAN.take();
-        let mut req_pool = REQ_POOL.take();
-        let mut resp_pool = RESP_POOL.take();
+pub(crate) fn drop_pools() {
+    let instance = instance::current();
+    let pools = &instance.pools;
 
-        // drop them in place
-        ptr::drop_in_place(&mut req_pool as *mut Option<SyncPool<Request>>);
-        ptr::drop_in_place(&mut resp_pool as *mut Option<SyncPool<Response>>);
+    let chan = pools.chan.lock().take();
+    if let Some(chan) = chan {
+        // zero-sized channel will block until the message is read, which shall happen evey second.
+        chan.send(()).unwrap_or_default();
     }
+
+    // take the pools out and drop them, once and for all
+    drop(pools.requests().take());
+    drop(pools.responses().take());
 }
 
 fn broadcast_new_communications(sender: Sender<String>, mut stream_clone: Stream) {
@@ -1287,14 +1805,43 @@ fn broadcast_new_communications(sender: Sender<String>, mut stream_clone: Stream
 }
 
 fn stream_trunk(content: &[u8], buffer: &mut BufWriter<&mut Stream>) {
-    // the content length should have been set in the header, see function resp_header
-    write_to_buff(buffer, content.len().to_string().as_bytes());
-    write_line_break(buffer);
+    write_trunk_size(content.len(), buffer);
     write_to_buff(buffer, content);
     write_line_break(buffer);
     flush_buffer(buffer);
 }
 
+/// The size of a chunk must be in hexadecimal, see RFC 7230, section 4.1.
+fn write_trunk_size(len: usize, buffer: &mut BufWriter<&mut Stream>) {
+    write_to_buff(buffer, format!("{:X}", len).as_bytes());
+    write_line_break(buffer);
+}
+
+fn send_file_body(file: &File, len: u64, buffer: &mut BufWriter<&mut Stream>) -> bool {
+    // make sure the header and everything before the file are written to the stream first
+    if buffer.flush().is_err() {
+        return false;
+    }
+
+    match buffer.get_mut().send_file(file, len) {
+        Ok(sent) if sent == len => true,
+        Ok(sent) => {
+            debug::print(
+                &format!("File is truncated when sending: {} of {} bytes", sent, len),
+                InfoLevel::Warning,
+            );
+            false
+        }
+        Err(e) => {
+            debug::print(
+                &format!("Unable to send the file to the stream: {}", e),
+                InfoLevel::Warning,
+            );
+            false
+        }
+    }
+}
+
 fn stream_default_body(status: u16, buffer: &mut BufWriter<&mut Stream>) {
     match status {
         //explicit error status
@@ -1335,6 +1882,47 @@ fn get_file_path(path: &str) -> Option<PathBuf> {
     Some(file_path.to_path_buf())
 }
 
+/// Extract the host from an absolute (or scheme-relative) redirect location, or `None` if the
+/// location is an internal path.
+fn redirect_host(location: &str) -> Option<String> {
+    let lower = location.trim().to_lowercase();
+
+    let rest = if let Some(rest) = lower.strip_prefix("http://") {
+        rest
+    } else if let Some(rest) = lower.strip_prefix("https://") {
+        rest
+    } else if lower.chars().take(2).all(|c| c == '/' || c == '\\') && lower.len() > 1 {
+        // browsers also take `/\` or `\\` as the scheme-relative prefix
+        &lower[2..]
+    } else {
+        return match lower.find("://") {
+            // other schemes, e.g. `ftp://`, are never allowed
+            Some(pos) if lower[..pos].chars().all(|c| c.is_ascii_alphanumeric()) => {
+                Some(String::new())
+            }
+            _ => None,
+        };
+    };
+
+    let authority = match rest.find(['/', '\\', '?', '#']) {
+        Some(pos) => &rest[..pos],
+        None => rest,
+    };
+
+    // strip the user info and the port
+    let host = match authority.rfind('@') {
+        Some(pos) => &authority[pos + 1..],
+        None => authority,
+    };
+
+    let host = match host.rfind(':') {
+        Some(pos) if !host.ends_with(']') => &host[..pos],
+        _ => host,
+    };
+
+    Some(host.to_owned())
+}
+
 fn open_file(file_path: &PathBuf, buf: &mut Vec<u8>) -> u16 {
     // try open the file
     if let Ok(file) = File::open(file_path) {
@@ -1361,12 +1949,16 @@ fn open_file_async(file_path: PathBuf, tx: Sender<(Vec<u8>, u16)>) {
     shared_pool::run(
         move || {
             // try open the file
-            if let Ok(file) = File::open(file_path) {
+            if let Ok(file) = File::open(&file_path) {
                 let mut buf_reader = BufReader::new(file);
                 let mut buf = Vec::with_capacity(1024);
 
                 match buf_reader.read_to_end(&mut buf) {
                     Ok(len) => {
+                        // the body is sent over the channel, so it's only copied if it's cached
+                        let buf = Arc::try_unwrap(cache::store(&file_path, buf))
+                            .unwrap_or_else(|shared| shared.to_vec());
+
                         if tx.send((buf, 200)).is_err() {
                             debug::print(
                                 "Unable to write the file to the stream",
@@ -1387,7 +1979,25 @@ fn open_file_async(file_path: PathBuf, tx: Sender<(Vec<u8>, u16)>) {
 }
 
 fn get_status(status: u16) -> Vec<u8> {
-    let status = match status {
+    let status = status_text(status);
+
+    let mut result = Vec::with_capacity(11 + status.len());
+    result.extend_from_slice(b"HTTP/1.1 ");
+    result.extend_from_slice(status.as_bytes());
+    result.append_line_break();
+
+    result
+}
+
+fn json_error_page(status: u16) -> String {
+    let text = status_text(status);
+    let reason = text.split_once(' ').map_or(text, |(_, reason)| reason);
+
+    format!("{{\"status\":{},\"message\":\"{}\"}}", status, reason)
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
         100 => "100 Continue",
         101 => "101 Switching Protocols",
         200 => "200 OK",
@@ -1434,64 +2044,6 @@ fn get_status(status: u16) -> Vec<u8> {
         505 => "505 HTTP Version Not Supported",
         511 => "511 Network Authentication Required",
         _ => "403 Forbidden",
-    };
-
-    let mut result = Vec::with_capacity(11 + status.len());
-    result.extend_from_slice(b"HTTP/1.1 ");
-    result.extend_from_slice(status.as_bytes());
-    result.append_line_break();
-
-    result
-}
-
-fn default_mime_type_with_ext(ext: &str) -> String {
-    match ext {
-        "abw" => String::from("application/x-abiword"),
-        "arc" | "bin" => String::from("application/octet-stream"),
-        "avi" => String::from("video/x-msvideo"),
-        "azw" => String::from("application/vnd.amazon.ebook"),
-        "bz" => String::from("application/x-bzip"),
-        "bz2" => String::from("application/x-bzip2"),
-        "css" | "scss" | "sass" | "less" => String::from("text/css"),
-        "doc" => String::from("application/msword"),
-        "docx" => {
-            String::from("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
-        }
-        "eot" => String::from("application/vnd.ms-fontobject"),
-        "epub" => String::from("application/epub+zip"),
-        "js" | "jsx" => String::from("application/javascript"),
-        "ts" => String::from("application/typescript"),
-        "ico" => String::from("image/x-icon"),
-        "ics" => String::from("text/calendar"),
-        "jar" => String::from("application/java-archive"),
-        "mpkg" => String::from("application/vnd.apple.installer+xml"),
-        "odp" => String::from("application/vnd.oasis.opendocument.presentation"),
-        "ods" => String::from("application/vnd.oasis.opendocument.spreadsheet"),
-        "odt" => String::from("application/vnd.oasis.opendocument.text"),
-        "ppt" => String::from("application/vnd.ms-powerpoint"),
-        "pptx" => String::from(
-            "application/vnd.openxmlformats-officedocument.presentationml.presentation",
-        ),
-        "rar" => String::from("application/x-rar-compressed"),
-        "swf" => String::from("application/x-shockwave-flash"),
-        "vsd" => String::from("application/vnd.visio"),
-        "wasm" => String::from("application/wasm"),
-        "weba" => String::from("audio/webm"),
-        "xhtml" => String::from("application/xhtml+xml"),
-        "xul" => String::from("application/vnd.mozilla.xul+xml"),
-        "7z" => String::from("application/x-7z-compressed"),
-        "svg" => String::from("image/svg+xml"),
-        "csh" | "sh" | "tar" | "wav" => ["application/x-", ext].join(""),
-        "csv" | "html" | "htm" => ["text/", ext].join(""),
-        "jpeg" | "jpg" | "gif" | "png" | "bmp" | "webp" | "tiff" | "tif" => {
-            ["image/", ext].join("")
-        }
-        "otf" | "ttf" | "woff" | "woff2" => ["font/", ext].join(""),
-        "midi" | "mp3" | "aac" | "mid" | "oga" => ["audio/", ext].join(""),
-        "webm" | "mp4" | "ogg" | "mpeg" | "ogv" => ["video/", ext].join(""),
-        "xml" | "pdf" | "json" | "ogx" | "rtf" | "zip" => ["application/", ext].join(""),
-        _ if !ext.is_empty() => ["application/", ext].join(""),
-        _ => String::from("text/plain"),
     }
 }
 
@@ -1565,3 +2117,61 @@ fn write_header_cookie(cookie: HashMap<String, Cookie>, tx: Sender<Vec<u8>>) {
         );
     });
 }
+
+#[cfg(test)]
+mod http_test {
+    use super::{redirect_host, stream_trunk, Response, ResponseStates, ResponseWriter};
+    use crate::core::stream::Stream;
+    use std::io::{BufWriter, Read};
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn redirect_host_test() {
+        assert_eq!(redirect_host("/index"), None);
+        assert_eq!(redirect_host("index?next=http://evil.com"), None);
+        assert_eq!(
+            redirect_host("https://Example.com/a?b=c"),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            redirect_host("http://example.com:8080"),
+            Some("example.com".into())
+        );
+        assert_eq!(redirect_host("//example.com/a"), Some("example.com".into()));
+        assert_eq!(redirect_host("/\\evil.com"), Some("evil.com".into()));
+        assert_eq!(
+            redirect_host("http://example.com@evil.com/"),
+            Some("evil.com".into())
+        );
+        assert_eq!(redirect_host("ftp://example.com"), Some(String::new()));
+    }
+
+    #[test]
+    fn redirect_line_break_test() {
+        let mut resp = Response::new();
+        resp.redirect_with("/login\r\nSet-Cookie: session=evil", 302, false);
+        resp.redirect_handling("");
+
+        assert_eq!(resp.status, 403);
+        assert!(resp.get_header("location").is_none());
+    }
+
+    #[test]
+    fn stream_trunk_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut stream = Stream::Tcp(listener.accept().unwrap().0);
+
+        {
+            let mut buffer = BufWriter::new(&mut stream);
+            stream_trunk(&[b'a'; 26], &mut buffer);
+            stream_trunk(&[], &mut buffer);
+        }
+
+        drop(stream);
+
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        assert_eq!(received, format!("1A\r\n{}\r\n0\r\n\r\n", "a".repeat(26)));
+    }
+}
@@ -0,0 +1,139 @@
+//! The states owned by each server, i.e. its router, object pools, worker pools, config metadata
+//! and connections, such that multiple servers can run in the same process independently.
+//!
+//! The states are looked up from the instance bound to the current thread: the threads serving a
+//! server are bound to its instance, and so are the calls made through the `HttpServer` APIs. The
+//! static APIs called from any other thread, e.g. `Route::use_router` in `main`, are applied to the
+//! instance of the server created last.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::core::config::{ConnMetadata, ViewEngine};
+use crate::core::http::PoolState;
+use crate::core::router::RouterState;
+use crate::hashbrown::HashMap;
+use crate::parking_lot::{Mutex, RwLock};
+use crate::support::cache::FileCache;
+use crate::support::drain::DrainState;
+use crate::support::limits::LimitState;
+use crate::support::shared_pool::Pool;
+
+thread_local! {
+    static BOUND: RefCell<Option<Arc<Instance>>> = const { RefCell::new(None) };
+}
+
+lazy_static! {
+    static ref LATEST: RwLock<Option<Arc<Instance>>> = RwLock::new(None);
+}
+
+pub(crate) struct Instance {
+    pub(crate) router: RouterState,
+    pub(crate) metadata: RwLock<ConnMetadata>,
+    pub(crate) view_engines: RwLock<HashMap<String, Box<ViewEngine>>>,
+    pub(crate) pools: PoolState,
+    pub(crate) workers: Mutex<Option<Pool>>,
+    pub(crate) file_cache: Mutex<Option<FileCache>>,
+    pub(crate) drain: DrainState,
+    pub(crate) limits: LimitState,
+
+    /// If the instance is owned by a server already.
+    claimed: AtomicBool,
+}
+
+impl Instance {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Instance {
+            router: RouterState::new(),
+            metadata: RwLock::new(ConnMetadata::new()),
+            view_engines: RwLock::new(HashMap::new()),
+            pools: PoolState::new(),
+            workers: Mutex::new(None),
+            file_cache: Mutex::new(None),
+            drain: DrainState::new(),
+            limits: LimitState::new(),
+            claimed: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Keep the instance bound to the current thread till the scope is dropped, when the instance
+/// bound before, if any, is restored.
+pub(crate) struct Scope(Option<Arc<Instance>>);
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        BOUND.with(|bound| *bound.borrow_mut() = previous);
+    }
+}
+
+/// Bind the instance to the current thread, see `Scope`.
+pub(crate) fn enter(instance: &Arc<Instance>) -> Scope {
+    let previous = BOUND.with(|bound| bound.borrow_mut().replace(Arc::clone(instance)));
+    Scope(previous)
+}
+
+/// The instance bound to the current thread, or the one of the server created last if the thread
+/// is not bound to any.
+pub(crate) fn current() -> Arc<Instance> {
+    if let Some(instance) = bound() {
+        return instance;
+    }
+
+    if let Some(instance) = LATEST.read().as_ref() {
+        return Arc::clone(instance);
+    }
+
+    let mut latest = LATEST.write();
+    Arc::clone(latest.get_or_insert_with(Instance::new))
+}
+
+/// The instance bound to the current thread, if any, such that it can be bound to the threads
+/// taking over the work.
+pub(crate) fn bound() -> Option<Arc<Instance>> {
+    BOUND.with(|bound| bound.borrow().clone())
+}
+
+/// Obtain the instance for a new server. The states set up with the static APIs before the first
+/// server is created are kept for it, otherwise each server starts with the states of its own.
+pub(crate) fn claim() -> Arc<Instance> {
+    let mut latest = LATEST.write();
+
+    if let Some(instance) = latest.as_ref() {
+        if !instance.claimed.swap(true, Ordering::AcqRel) {
+            return Arc::clone(instance);
+        }
+    }
+
+    let instance = Instance::new();
+    instance.claimed.store(true, Ordering::Release);
+    latest.replace(Arc::clone(&instance));
+
+    instance
+}
+
+#[cfg(test)]
+mod instance_test {
+    use super::*;
+
+    #[test]
+    fn scope_test() {
+        let (first, second) = (Instance::new(), Instance::new());
+
+        {
+            let _outer = enter(&first);
+            assert!(Arc::ptr_eq(&current(), &first));
+
+            {
+                let _inner = enter(&second);
+                assert!(Arc::ptr_eq(&current(), &second));
+            }
+
+            assert!(Arc::ptr_eq(&current(), &first));
+        }
+
+        assert!(bound().is_none());
+    }
+}
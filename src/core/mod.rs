@@ -1,10 +1,17 @@
+pub mod auth;
 pub mod config;
 pub(crate) mod conn;
 pub mod context;
 pub mod cookie;
+pub mod guard;
+#[cfg(feature = "http2")]
+pub(crate) mod h2;
 pub mod http;
+pub(crate) mod instance;
+pub mod resource;
 pub mod router;
 pub mod server;
 pub mod states;
 pub(crate) mod stream;
 pub(crate) mod syncstore;
+pub mod testing;
@@ -0,0 +1,251 @@
+//! The RESTful resource controllers, which map the CRUD actions of a resource to the routes, such
+//! that a resource can be added with `Router::resource` instead of a route per action.
+
+#![allow(clippy::borrowed_box)]
+
+use crate::core::router::{Callback, REST};
+
+/// The actions of a resource, each of which is only routed if it's provided, and the nested
+/// resources are routed under a member of this resource. For a resource at `/users`:
+///
+/// | Action    | Method         | Path          |
+/// |-----------|----------------|---------------|
+/// | `index`   | `GET`          | `/users`      |
+/// | `create`  | `POST`         | `/users`      |
+/// | `show`    | `GET`          | `/users/:id`  |
+/// | `update`  | `PUT`, `PATCH` | `/users/:id`  |
+/// | `destroy` | `DELETE`       | `/users/:id`  |
+///
+/// A nested resource at `/posts` is then routed at `/users/:user_id/posts`, where the id of the
+/// parent is named after the singular of its last path segment, see `ResourceController::nested`.
+///
+/// # Example
+///
+/// ```
+/// use rusty_express::prelude::*;
+///
+/// struct Users;
+///
+/// impl ResourceController for Users {
+///     fn index(&self) -> Option<Callback> {
+///         Some(list_users)
+///     }
+///
+///     fn show(&self) -> Option<Callback> {
+///         Some(show_user)
+///     }
+/// }
+///
+/// let mut server = HttpServer::new();
+/// server.resource("/users", &Users);
+///
+/// pub fn list_users(req: &Box<Request>, resp: &mut Box<Response>) {
+///     resp.send("All users");
+/// }
+///
+/// pub fn show_user(req: &Box<Request>, resp: &mut Box<Response>) {
+///     resp.send(&format!("User {}", req.param("id").unwrap_or_default()));
+/// }
+/// ```
+pub trait ResourceController {
+    fn index(&self) -> Option<Callback> {
+        None
+    }
+
+    fn show(&self) -> Option<Callback> {
+        None
+    }
+
+    fn create(&self) -> Option<Callback> {
+        None
+    }
+
+    fn update(&self) -> Option<Callback> {
+        None
+    }
+
+    fn destroy(&self) -> Option<Callback> {
+        None
+    }
+
+    /// The resources nested under a member of this resource, with their paths relative to the
+    /// member, e.g. `("/posts", Box::new(Posts))`. The id of this resource is then named after the
+    /// singular of its last path segment in the nested routes, e.g. `user_id` for `/users`, such
+    /// that it won't clash with the `id` of the nested resource.
+    fn nested(&self) -> Vec<(&'static str, Box<dyn ResourceController>)> {
+        Vec::new()
+    }
+}
+
+/// List the routes of the resource at the path, including the nested ones, see
+/// `ResourceController`.
+pub(crate) fn routes(
+    path: &str,
+    controller: &dyn ResourceController,
+) -> Vec<(REST, String, Callback)> {
+    if path.is_empty() || !path.starts_with('/') {
+        panic!("Resource path must have valid contents and start with '/'.");
+    }
+
+    let mut result = Vec::new();
+    collect(path.trim_end_matches('/'), controller, &mut result);
+    result
+}
+
+fn collect(
+    path: &str,
+    controller: &dyn ResourceController,
+    result: &mut Vec<(REST, String, Callback)>,
+) {
+    let collection = match path {
+        "" => "/",
+        _ => path,
+    };
+
+    let member = format!("{}/:id", path);
+
+    let actions = [
+        (REST::GET, collection, controller.index()),
+        (REST::POST, collection, controller.create()),
+        (REST::GET, member.as_str(), controller.show()),
+        (REST::PUT, member.as_str(), controller.update()),
+        (REST::PATCH, member.as_str(), controller.update()),
+        (REST::DELETE, member.as_str(), controller.destroy()),
+    ];
+
+    for (method, uri, action) in actions.iter() {
+        if let Some(callback) = action {
+            result.push((method.clone(), (*uri).to_owned(), *callback));
+        }
+    }
+
+    let nested = controller.nested();
+    if nested.is_empty() {
+        return;
+    }
+
+    let parent = format!("{}/:{}_id", path, singular(path));
+    for (sub_path, sub_controller) in nested.iter() {
+        if sub_path.is_empty() || !sub_path.starts_with('/') {
+            panic!("Nested resource path must have valid contents and start with '/'.");
+        }
+
+        collect(
+            &[parent.as_str(), sub_path.trim_end_matches('/')].join(""),
+            sub_controller.as_ref(),
+            result,
+        );
+    }
+}
+
+/// The singular of the last path segment, e.g. `user` for `/api/users`, and `category` for
+/// `/categories`.
+fn singular(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or_default();
+
+    if name.is_empty() || name.starts_with(':') {
+        panic!(
+            "Resource with nested resources must end with a name: {}",
+            path
+        );
+    }
+
+    if let Some(stem) = name.strip_suffix("ies") {
+        return format!("{}y", stem);
+    }
+
+    match name.strip_suffix('s') {
+        Some(stem) if !stem.is_empty() && !stem.ends_with('s') => stem.to_owned(),
+        _ => name.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod resource_test {
+    use super::*;
+    use crate::core::http::{Request, Response, ResponseWriter};
+    use crate::core::router::Router;
+    use crate::core::server::HttpServer;
+    use crate::core::testing::{TestClient, TestRequest};
+
+    fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+    fn update_user(req: &Box<Request>, resp: &mut Box<Response>) {
+        resp.send(&format!("updated {}", req.param("id").unwrap_or_default()));
+    }
+
+    struct Accounts;
+
+    impl ResourceController for Accounts {
+        fn show(&self) -> Option<Callback> {
+            Some(one)
+        }
+
+        fn update(&self) -> Option<Callback> {
+            Some(update_user)
+        }
+    }
+
+    struct Users;
+    struct Posts;
+
+    impl ResourceController for Users {
+        fn index(&self) -> Option<Callback> {
+            Some(one)
+        }
+
+        fn update(&self) -> Option<Callback> {
+            Some(one)
+        }
+
+        fn nested(&self) -> Vec<(&'static str, Box<dyn ResourceController>)> {
+            vec![("/posts/", Box::new(Posts))]
+        }
+    }
+
+    impl ResourceController for Posts {
+        fn show(&self) -> Option<Callback> {
+            Some(one)
+        }
+    }
+
+    #[test]
+    fn routes_test() {
+        let found: Vec<(String, String)> = routes("/api/users/", &Users)
+            .into_iter()
+            .map(|(method, uri, _)| (method.to_string(), uri))
+            .collect();
+
+        let expected = [
+            ("GET", "/api/users"),
+            ("PUT", "/api/users/:id"),
+            ("PATCH", "/api/users/:id"),
+            ("GET", "/api/users/:user_id/posts/:id"),
+        ];
+
+        assert_eq!(found.len(), expected.len());
+        for ((method, uri), (exp_method, exp_uri)) in found.iter().zip(expected.iter()) {
+            assert_eq!((method.as_str(), uri.as_str()), (*exp_method, *exp_uri));
+        }
+    }
+
+    #[test]
+    fn patch_test() {
+        let mut server = HttpServer::new();
+        server.resource("/api/users", &Accounts);
+
+        let client = TestClient::new(&server);
+        let response = client.send(TestRequest::new(REST::PATCH, "/api/users/42"));
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text(), "updated 42");
+    }
+
+    #[test]
+    fn singular_test() {
+        assert_eq!(singular("/users"), "user");
+        assert_eq!(singular("/categories"), "category");
+        assert_eq!(singular("/address"), "address");
+        assert_eq!(singular("/sheep"), "sheep");
+    }
+}
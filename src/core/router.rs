@@ -1,33 +1,37 @@
 #![allow(unused)]
 #![allow(clippy::borrowed_box)]
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::ptr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::thread;
 
 use crate::channel;
+use crate::core::auth::{self, AuthPolicy};
+use crate::core::config::ConnMetadata;
+use crate::core::guard::RequestGuard;
 use crate::core::http::{Request, Response, ResponseWriter};
-use crate::core::syncstore::StaticStore;
+use crate::core::instance;
+use crate::core::resource::{self, ResourceController};
 use crate::hashbrown::{HashMap, HashSet};
+use crate::parking_lot::{Mutex, RwLock};
 use crate::regex::Regex;
-use crate::support::common::cpu_relax;
+use crate::support::common::percent_encode;
 use crate::support::{
     common::MapUpdates,
+    constraint,
     debug::{self, InfoLevel},
-    Field, RouteTrie,
+    glob::Glob,
+    listing, Field, RouteTrie,
 };
 use std::sync::Arc;
 
-//TODO: impl route caching: 1) only explicit and wildcard will get cached ... especially the wildcard
-//      one. 2) store uri in the "method:path" format.
+type CachedRoute = (RouteHandler, HashMap<String, String>);
 
-static mut ROUTER: StaticStore<(Route, AtomicUsize)> = StaticStore::init();
-static mut ROUTE_CACHE: StaticStore<HashMap<(REST, String), RouteHandler>> = StaticStore::init();
-
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum REST {
     GET,
     PATCH,
@@ -58,6 +62,18 @@ impl Default for REST {
     }
 }
 
+/// The request path of a route, which can be:
+/// - `Explicit`: the exact path, e.g. `/api/users`;
+/// - `ExplicitWithParams`: the path with named params, e.g. `/api/user/:id`, `/:id(\d+)` with a
+///   regex validation, or `/:id<u64>` with a typed constraint (`u64`, `i64`, `slug`, `uuid`, `date`
+///   or a custom one added via `Route::add_constraint`), glob pattern segments, e.g. `/assets/*.js`, and the catch-all segment at the
+///   end, e.g. `/files/*path`, which takes the rest of the uri as the param `path`. A segment is
+///   matched in the order of precedence: exact names, then glob patterns, then params, then the
+///   catch-all;
+/// - `WildCard`: the regex matching the whole path, which is tried after all the routes above and
+///   in the order they're added. A `WildCard` route is the last resort: any `ExplicitWithParams`
+///   route matching the uri wins over it, including a catch-all like `/files/*path`, no matter how
+///   specific the regex is.
 #[derive(PartialEq, Eq, Hash)]
 pub enum RequestPath<'a> {
     Explicit(&'a str),
@@ -86,8 +102,17 @@ pub type Callback = fn(&Box<Request>, &mut Box<Response>);
 /// The use of the AuthFunc is totally optional, you can also check authentication within individual
 /// request handlers as well. You can also use the `context` and/or `session` modules to store, or
 /// update persistent information regarding the client requestor.
+///
+/// To authorize the requests per route or per router, with the `401` or a redirect to the login
+/// page, see `AuthPolicy`.
 pub type AuthFunc = fn(&Box<Request>, &str) -> bool;
 
+/// `ParamConstraint` is a type alias to the functions checking if the value of a typed route param
+/// is valid, e.g. for the `u64` type in `/user/:id<u64>`, which can be added via
+/// `Route::add_constraint`.
+pub type ParamConstraint = fn(&str) -> bool;
+
+#[derive(Clone)]
 struct RegexRoute {
     regex: Regex,
     handler: RouteHandler,
@@ -99,75 +124,426 @@ impl RegexRoute {
     }
 }
 
+/// How to treat the symbolic links in the static folders.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SymlinkPolicy {
+    /// Never serve a file through a symbolic link.
+    Deny,
+    /// Only follow the symbolic links that resolve to somewhere inside the static folder.
+    WithinRoot,
+    /// Follow all symbolic links, even if they lead out of the static folder.
+    Follow,
+}
+
+/// The options of a static folder mount, which decide how to serve a request that doesn't map to
+/// a file in the folder directly, e.g. `/docs/` or a deep link into a single page app.
+///
+/// # Example
+///
+/// ```no_run
+/// use rusty_express::prelude::*;
+/// use std::path::PathBuf;
+///
+/// let mut options = StaticOptions::new();
+/// options.set_listing(true);
+/// options.set_fallback("index.html");
+///
+/// let mut server = HttpServer::new();
+/// server.use_static_with(PathBuf::from(r"./static"), options);
+/// ```
 #[derive(Clone)]
-enum StaticListData {
-    Ext(Arc<String>),
-    Loc(Arc<String>),
+pub struct StaticOptions {
+    index: Vec<String>,
+    listing: bool,
+    fallback: Option<String>,
+    try_files: Vec<String>,
+    symlinks: SymlinkPolicy,
+    hidden_files: bool,
+}
+
+impl StaticOptions {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The files to serve when a folder is requested, tried in the given order. Default to
+    /// `index.html`.
+    pub fn set_index(&mut self, files: &[&str]) {
+        self.index = files.iter().map(|f| (*f).to_owned()).collect();
+    }
+
+    pub fn get_index(&self) -> &[String] {
+        &self.index
+    }
+
+    /// Generate the listing of a requested folder if it doesn't contain any index file. The listing
+    /// is served as html, or as json if the client prefers `application/json`. Default to `false`.
+    pub fn set_listing(&mut self, allow: bool) {
+        self.listing = allow;
+    }
+
+    pub fn get_listing(&self) -> bool {
+        self.listing
+    }
+
+    /// The file to serve, relative to the static folder, when nothing else matches the request,
+    /// which is usually the `index.html` of a single page app.
+    pub fn set_fallback(&mut self, file: &str) {
+        if file.is_empty() {
+            self.fallback = None;
+        } else {
+            self.fallback = Some(file.to_owned());
+        }
+    }
+
+    pub fn get_fallback(&self) -> Option<&String> {
+        self.fallback.as_ref()
+    }
+
+    /// The order to look up the request in the static folder, in the same fashion as nginx's
+    /// `try_files`, where `$uri` is replaced by the request path, an entry ending with `/` is tried
+    /// as a folder, and any other entry is tried as a file, e.g. `["$uri", "$uri.html", "$uri/"]`.
+    /// Default to `["$uri", "$uri/"]`, and the fallback file, if set, is always tried last.
+    pub fn set_try_files(&mut self, order: &[&str]) {
+        self.try_files = order.iter().map(|f| (*f).to_owned()).collect();
+    }
+
+    pub fn get_try_files(&self) -> &[String] {
+        &self.try_files
+    }
+
+    /// Whether to serve the files through symbolic links. Default to `SymlinkPolicy::WithinRoot`.
+    pub fn set_symlinks(&mut self, policy: SymlinkPolicy) {
+        self.symlinks = policy;
+    }
+
+    pub fn get_symlinks(&self) -> SymlinkPolicy {
+        self.symlinks
+    }
+
+    /// Whether to serve the hidden files and folders, i.e. the ones whose names start with a `.`,
+    /// such as `.git` or `.env`. Default to `false`.
+    pub fn set_hidden_files(&mut self, allow: bool) {
+        self.hidden_files = allow;
+    }
+
+    pub fn get_hidden_files(&self) -> bool {
+        self.hidden_files
+    }
+}
+
+impl Default for StaticOptions {
+    fn default() -> Self {
+        StaticOptions {
+            index: vec![String::from("index.html")],
+            listing: false,
+            fallback: None,
+            try_files: vec![String::from("$uri"), String::from("$uri/")],
+            symlinks: SymlinkPolicy::WithinRoot,
+            hidden_files: false,
+        }
+    }
+}
+
+/// How to treat the trailing slash of the request path. The routes are kept as they're added, e.g.
+/// `/docs/` and `/docs` are different routes, and the request path always matches the route added
+/// the exact same way first, then the policy decides if it can match the route with or without the
+/// trailing slash instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrailingSlash {
+    /// Serve `/users/` by the route of `/users`, and vice versa.
+    Ignore,
+    /// Only serve the path exactly as the route is added, such that `/users/` ends up as a `404`
+    /// if only the route of `/users` exists.
+    Strict,
+    /// Redirect `/users/` to `/users` with a `308 Permanent Redirect` if only the route of `/users`
+    /// exists, and vice versa.
+    Redirect,
+}
+
+/// The options to normalize the request path before it's routed, which apply to all requests to
+/// the server, see `Route::use_path_options`. The static files served from a static folder are not
+/// subject to the trailing slash policy, since a folder is usually requested with the slash.
+///
+/// # Example
+///
+/// ```
+/// use rusty_express::prelude::*;
+///
+/// let mut options = PathOptions::new();
+/// options.set_trailing_slash(TrailingSlash::Redirect);
+///
+/// let mut server = HttpServer::new();
+/// Route::use_path_options(options);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PathOptions {
+    merge_slashes: bool,
+    resolve_dots: bool,
+    trailing_slash: TrailingSlash,
+}
+
+impl PathOptions {
+    // packed as: merge slashes | resolve dots << 1 | trailing slash << 2
+    const DEFAULT: u8 = 0b011;
+
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Merge the duplicate slashes, e.g. `/api//users` is routed as `/api/users`. Default to `true`.
+    pub fn set_merge_slashes(&mut self, merge: bool) {
+        self.merge_slashes = merge;
+    }
+
+    pub fn get_merge_slashes(&self) -> bool {
+        self.merge_slashes
+    }
+
+    /// Resolve the `.` and `..` segments, e.g. `/api/./v1/../users` is routed as `/api/users`, and
+    /// a `..` can't go above the root. Default to `true`.
+    pub fn set_resolve_dots(&mut self, resolve: bool) {
+        self.resolve_dots = resolve;
+    }
+
+    pub fn get_resolve_dots(&self) -> bool {
+        self.resolve_dots
+    }
+
+    /// Default to `TrailingSlash::Ignore`.
+    pub fn set_trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    pub fn get_trailing_slash(&self) -> TrailingSlash {
+        self.trailing_slash
+    }
+
+    fn current() -> Self {
+        let packed = instance::current()
+            .router
+            .path_options
+            .load(Ordering::Acquire);
+
+        PathOptions {
+            merge_slashes: packed & 0b001 != 0,
+            resolve_dots: packed & 0b010 != 0,
+            trailing_slash: match packed >> 2 {
+                1 => TrailingSlash::Strict,
+                2 => TrailingSlash::Redirect,
+                _ => TrailingSlash::Ignore,
+            },
+        }
+    }
+
+    fn pack(&self) -> u8 {
+        let trailing_slash = match self.trailing_slash {
+            TrailingSlash::Ignore => 0,
+            TrailingSlash::Strict => 1,
+            TrailingSlash::Redirect => 2,
+        };
+
+        (self.merge_slashes as u8) | (self.resolve_dots as u8) << 1 | trailing_slash << 2
+    }
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions {
+            merge_slashes: true,
+            resolve_dots: true,
+            trailing_slash: TrailingSlash::Ignore,
+        }
+    }
 }
 
 struct StaticLocRoute {
     location: PathBuf,
-    black_list: Vec<StaticListData>,
-    white_list: Vec<StaticListData>,
+    root: PathBuf,
+    black_list: Vec<Glob>,
+    white_list: Vec<Glob>,
+    options: StaticOptions,
 }
 
 impl StaticLocRoute {
-    fn check_access(&self, loc: &PathBuf) -> bool {
-        // the extension prefix
-        let mut ext = String::from("*.");
-
-        // the requested file exists, now check white-list and black-list, in this order
-        match loc.extension() {
-            Some(e) => {
-                if let Some(e_str) = e.to_str() {
-                    ext.push_str(e_str);
-                } else {
-                    return false;
-                }
+    fn new(location: PathBuf, options: StaticOptions) -> Self {
+        // all requested paths are resolved against the canonical root
+        let root = fs::canonicalize(&location).unwrap_or_else(|_| location.clone());
+
+        StaticLocRoute {
+            location,
+            root,
+            black_list: Vec::new(),
+            white_list: Vec::new(),
+            options,
+        }
+    }
+
+    /// Add the glob rule to the white-list or the black-list. For backward compatibility, an
+    /// absolute path under the static folder is taken as the folder and everything beneath it.
+    fn add_rule(&mut self, pattern: &str, is_white_list: bool) {
+        let rule = match Path::new(pattern).strip_prefix(&self.location) {
+            Ok(relative) if Path::new(pattern).is_absolute() => {
+                Glob::new(&relative.to_string_lossy().replace('\\', "/"))
             }
-            _ => return false,
+            _ => Glob::new(pattern),
         };
 
-        if !self.white_list.is_empty() {
-            for wl in self.white_list.iter() {
-                match wl {
-                    StaticListData::Ext(e) => {
-                        if e.as_str() == ext {
-                            return true;
-                        }
-                    }
-                    StaticListData::Loc(l) => {
-                        if loc.starts_with(l.as_ref()) {
-                            return true;
-                        }
-                    }
+        match rule {
+            Some(glob) if is_white_list => self.white_list.push(glob),
+            Some(glob) => self.black_list.push(glob),
+            None => debug::print(
+                &format!("Invalid static rule is ignored: {}", pattern),
+                InfoLevel::Warning,
+            ),
+        }
+    }
+
+    /// Look up the request in the static folder following the `try_files` order, and then the
+    /// fallback file. A folder is only returned if its listing is allowed.
+    fn try_files(&self, raw_uri: &str) -> Option<PathBuf> {
+        let uri = raw_uri.trim_matches('/');
+
+        for entry in self
+            .options
+            .try_files
+            .iter()
+            .chain(self.options.fallback.iter())
+        {
+            let candidate = entry.replace("$uri", uri);
+
+            let path = match self.resolve(&candidate) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            if candidate.ends_with('/') {
+                if let Some(p) = self.try_folder(path) {
+                    return Some(p);
                 }
+            } else if path.is_file() && self.check_access(&path, false) {
+                return Some(path);
             }
+        }
 
-            // we can't match to a white-listed position, quit
-            return false;
+        None
+    }
+
+    fn try_folder(&self, dir: PathBuf) -> Option<PathBuf> {
+        if !dir.is_dir() || !self.check_access(&dir, true) {
+            return None;
         }
 
-        if !self.black_list.is_empty() {
-            for wl in self.black_list.iter() {
-                match wl {
-                    StaticListData::Ext(e) => {
-                        if e.as_str() == ext {
-                            return false;
-                        }
-                    }
-                    StaticListData::Loc(l) => {
-                        if loc.starts_with(l.as_ref()) {
-                            return false;
-                        }
+        for index in self.options.index.iter() {
+            let file = dir.join(index);
+            if file.is_file() && self.check_access(&file, false) {
+                return Some(file);
+            }
+        }
+
+        if self.options.listing {
+            return Some(dir);
+        }
+
+        None
+    }
+
+    /// Resolve the request path under the static folder, where each segment is percent-decoded,
+    /// and we won't go above the root folder, into the hidden files, or through the symbolic links,
+    /// unless allowed by the options. The path is not guaranteed to exist.
+    fn resolve(&self, raw_uri: &str) -> Option<PathBuf> {
+        let mut path = self.root.clone();
+
+        for raw in raw_uri.split('/') {
+            let part = percent_decode(raw)?;
+
+            match part.as_str() {
+                "" | "." => continue,
+                ".." => return None,
+                p if p.starts_with('.') && !self.options.hidden_files => return None,
+                _ => {}
+            }
+
+            // the decoded segment must remain a single plain segment, e.g. no `%2F`, `\` or `C:`
+            let mut components = Path::new(&part).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) if !part.contains('\\') => path.push(&part),
+                _ => return None,
+            }
+
+            if self.options.symlinks == SymlinkPolicy::Deny {
+                if let Ok(meta) = fs::symlink_metadata(&path) {
+                    if meta.file_type().is_symlink() {
+                        return None;
                     }
                 }
             }
+        }
 
-            // we can't match to a black-listed position, we're good
+        if self.options.symlinks == SymlinkPolicy::WithinRoot {
+            if let Ok(real_path) = fs::canonicalize(&path) {
+                if !real_path.starts_with(&self.root) {
+                    return None;
+                }
+            }
         }
 
-        true
+        Some(path)
+    }
+
+    /// Check if the entry found under the static folder, e.g. by folder listing, can be reached per
+    /// the symbolic link policy.
+    fn check_link(&self, path: &Path) -> bool {
+        match self.options.symlinks {
+            SymlinkPolicy::Follow => true,
+            SymlinkPolicy::Deny => fs::symlink_metadata(path)
+                .map(|meta| !meta.file_type().is_symlink())
+                .unwrap_or(false),
+            SymlinkPolicy::WithinRoot => fs::canonicalize(path)
+                .map(|real_path| real_path.starts_with(&self.root))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Check the resolved path against the hidden-file policy, the black-list and the white-list,
+    /// in this order. A rule matching a folder also covers everything beneath it, and the white-list
+    /// only applies to files.
+    fn check_access(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+
+        let mut prefixes = Vec::new();
+        let mut current = String::new();
+
+        for part in relative.components() {
+            let name = part.as_os_str().to_string_lossy();
+            if name.starts_with('.') && !self.options.hidden_files {
+                return false;
+            }
+
+            if !current.is_empty() {
+                current.push('/');
+            }
+
+            current.push_str(&name);
+            prefixes.push(current.clone());
+        }
+
+        let matches = |rules: &[Glob]| {
+            rules
+                .iter()
+                .any(|rule| prefixes.iter().any(|prefix| rule.is_match(prefix)))
+        };
+
+        if matches(&self.black_list) {
+            return false;
+        }
+
+        is_dir || self.white_list.is_empty() || matches(&self.white_list)
     }
 }
 
@@ -175,18 +551,105 @@ impl Clone for StaticLocRoute {
     fn clone(&self) -> Self {
         StaticLocRoute {
             location: self.location.clone(),
+            root: self.root.clone(),
             black_list: self.black_list.clone(),
             white_list: self.white_list.clone(),
+            options: self.options.clone(),
+        }
+    }
+}
+
+/// The kind of the route, see `RouteInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteKind {
+    Explicit,
+    Params,
+    WildCard,
+    Static,
+    StaticFolder,
+    Redirect,
+}
+
+impl fmt::Display for RouteKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            RouteKind::Explicit => "explicit",
+            RouteKind::Params => "params",
+            RouteKind::WildCard => "wildcard",
+            RouteKind::Static => "static",
+            RouteKind::StaticFolder => "static folder",
+            RouteKind::Redirect => "redirect",
+        };
+
+        write!(fmt, "{}", label)
+    }
+}
+
+/// The description of a route added to the router, which can be obtained via `Route::routes`.
+#[derive(Clone, Debug)]
+pub struct RouteInfo {
+    /// The request method, where `*` means all the methods.
+    pub method: REST,
+    /// The path or the regex of the route, including the prefix of the router it's mounted to.
+    pub pattern: String,
+    /// The host pattern of the router serving the route, see `Router::host`.
+    pub host: Option<String>,
+    pub kind: RouteKind,
+    /// The name given to the route, see `Router::name`.
+    pub name: Option<String>,
+    /// The file or the folder of a static route, or the location of a redirect.
+    pub target: Option<String>,
+    /// If the route has the request guard, see `Router::guarded`.
+    pub guarded: bool,
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{:<7} {} ({}",
+            self.method.to_string(),
+            self.pattern,
+            self.kind
+        )?;
+
+        if self.guarded {
+            write!(fmt, ", guarded")?;
+        }
+
+        if let Some(name) = self.name.as_ref() {
+            write!(fmt, ", name: {}", name)?;
+        }
+
+        if let Some(target) = self.target.as_ref() {
+            write!(fmt, ", to: {}", target)?;
+        }
+
+        if let Some(host) = self.host.as_ref() {
+            write!(fmt, ", host: {}", host)?;
         }
+
+        write!(fmt, ")")
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct RouteMap {
     explicit: HashMap<String, RouteHandler>,
     explicit_with_params: RouteTrie,
-    wildcard: HashMap<String, RegexRoute>,
+    wildcard: Vec<RegexRoute>,
     static_path: Option<StaticLocRoute>,
     case_sensitive: bool,
+    routes: Vec<RouteEntry>,
+}
+
+/// The record of a route added to the map, in the order they're added, see `Route::routes`.
+#[derive(Clone)]
+struct RouteEntry {
+    pattern: String,
+    kind: RouteKind,
+    target: Option<String>,
+    guarded: bool,
 }
 
 impl RouteMap {
@@ -194,53 +657,159 @@ impl RouteMap {
         RouteMap {
             explicit: HashMap::new(),
             explicit_with_params: RouteTrie::initialize(),
-            wildcard: HashMap::new(),
+            wildcard: Vec::new(),
             static_path: None,
             case_sensitive: false,
+            routes: Vec::new(),
         }
     }
 
-    pub fn insert(&mut self, uri: RequestPath<'_>, handler: RouteHandler) {
-        match uri {
+    /// Add the route to the map, or return `false` if it conflicts with a route added before, i.e.
+    /// one that would match the same uri, in which case the map is not changed.
+    pub fn insert(&mut self, uri: RequestPath<'_>, handler: RouteHandler) -> bool {
+        let target = handler.path().map(|p| p.to_string_lossy().into_owned());
+        let guarded = handler.4.is_some();
+
+        let (req_uri, kind) = match uri {
             RequestPath::Explicit(req_uri) => {
                 if req_uri.is_empty() || !req_uri.starts_with('/') {
                     panic!("Request path must have valid contents and start with '/'.");
                 }
 
-                self.explicit
-                    .add(req_uri, handler, false, self.case_sensitive);
+                if !self.insert_explicit(req_uri, handler) {
+                    return false;
+                }
+
+                (req_uri, RouteKind::Explicit)
             }
             RequestPath::WildCard(req_uri) => {
                 if req_uri.is_empty() {
                     panic!("Request path must have valid contents.");
                 }
 
-                if self.wildcard.contains_key(req_uri) {
-                    return;
-                }
+                if let Some(route) = self
+                    .wildcard
+                    .iter_mut()
+                    .find(|route| route.regex.as_str() == req_uri)
+                {
+                    if !route.handler.merge(handler) {
+                        return false;
+                    }
+                } else {
+                    // the wildcard routes are matched in the order they're added
+                    match Regex::new(req_uri) {
+                        Ok(re) => self.wildcard.push(RegexRoute::new(re, handler)),
+                        Err(err) => {
+                            debug::print(
+                                &format!("Invalid wildcard route `{}`: {}", req_uri, err),
+                                InfoLevel::Warning,
+                            );
 
-                if let Ok(re) = Regex::new(req_uri) {
-                    self.wildcard.add(
-                        req_uri,
-                        RegexRoute::new(re, handler),
-                        false,
-                        self.case_sensitive,
-                    );
+                            return true;
+                        }
+                    }
                 }
+
+                (req_uri, RouteKind::WildCard)
             }
             RequestPath::ExplicitWithParams(req_uri) => {
-                if !req_uri.contains("/:") && !req_uri.contains(":\\") {
-                    self.explicit
-                        .add(req_uri, handler, false, self.case_sensitive);
-                    return;
+                if !req_uri.contains("/:")
+                    && !req_uri.contains(":\\")
+                    && !req_uri.contains(['*', '?', '['])
+                {
+                    if !self.insert_explicit(req_uri, handler) {
+                        return false;
+                    }
+
+                    (req_uri, RouteKind::Explicit)
+                } else {
+                    if !self.explicit_with_params.add(
+                        RouteMap::params_fields(req_uri, self.case_sensitive),
+                        handler,
+                    ) {
+                        return false;
+                    }
+
+                    (req_uri, RouteKind::Params)
                 }
+            }
+        };
 
-                self.explicit_with_params.add(
-                    RouteMap::params_parser(req_uri, self.case_sensitive),
-                    handler,
-                );
+        let kind = if target.is_some() {
+            RouteKind::Static
+        } else {
+            kind
+        };
+
+        self.routes.push(RouteEntry {
+            pattern: req_uri.to_owned(),
+            kind,
+            target,
+            guarded,
+        });
+
+        true
+    }
+
+    fn insert_explicit(&mut self, req_uri: &str, handler: RouteHandler) -> bool {
+        let key = if self.case_sensitive {
+            req_uri.to_owned()
+        } else {
+            req_uri.to_lowercase()
+        };
+
+        if let Some(existing) = self.explicit.get_mut(&key) {
+            return existing.merge(handler);
+        }
+
+        self.explicit
+            .add(req_uri, handler, false, self.case_sensitive);
+
+        true
+    }
+
+    /// Remove the route of the exact same path, including the param names, and return `false` if
+    /// there's no such route.
+    fn remove(&mut self, uri: RequestPath) -> bool {
+        let (req_uri, removed) = match uri {
+            RequestPath::Explicit(req_uri) => (req_uri, self.remove_explicit(req_uri)),
+            RequestPath::ExplicitWithParams(req_uri) => {
+                let removed = if !req_uri.contains("/:")
+                    && !req_uri.contains(":\\")
+                    && !req_uri.contains(['*', '?', '['])
+                {
+                    self.remove_explicit(req_uri)
+                } else {
+                    self.explicit_with_params
+                        .remove(&RouteMap::params_fields(req_uri, self.case_sensitive))
+                };
+
+                (req_uri, removed)
+            }
+            RequestPath::WildCard(req_uri) => {
+                let count = self.wildcard.len();
+                self.wildcard
+                    .retain(|route| route.regex.as_str() != req_uri);
+
+                (req_uri, self.wildcard.len() < count)
             }
+        };
+
+        if removed {
+            self.routes.retain(|entry| entry.pattern != req_uri);
         }
+
+        removed
+    }
+
+    fn remove_explicit(&mut self, req_uri: &str) -> bool {
+        let key = if self.case_sensitive {
+            req_uri.to_owned()
+        } else {
+            req_uri.to_lowercase()
+        };
+
+        self.explicit.remove(&key).is_some()
     }
 
     pub fn case_sensitive(&mut self, allow_case: bool) {
@@ -251,10 +820,25 @@ impl RouteMap {
         self.case_sensitive
     }
 
+    /// The fields of the params route, where the trailing slash is kept as an empty segment, such
+    /// that it must be matched as well.
+    fn params_fields(source_uri: &str, allow_case: bool) -> Vec<Field> {
+        let mut fields = RouteMap::params_parser(source_uri, allow_case);
+
+        // the fields are in the reversed order
+        let catch_all = fields.first().is_some_and(Field::is_catch_all);
+        if !catch_all && source_uri.len() > 1 && source_uri.ends_with('/') {
+            fields.insert(0, Field::new(String::new(), false, None));
+        }
+
+        fields
+    }
+
     fn params_parser(source_uri: &str, allow_case: bool) -> Vec<Field> {
         let mut param_names = HashSet::new();
 
         let mut validation: Option<Regex> = None;
+        let mut constraint: Option<(String, ParamConstraint)> = None;
         let mut name = "";
         let mut is_param = false;
 
@@ -262,7 +846,8 @@ impl RouteMap {
         //         1 -- Just split;
         //         2 -- In params;
         //         4 -- In params regex;
-        //         8 -- Params regex just end, must split next or panic;
+        //         8 -- Params regex or type just end, must split next or panic;
+        //        16 -- In params type;
         let mut split_status: u8 = 0;
 
         let mut result: Vec<Field> = source_uri
@@ -281,6 +866,14 @@ impl RouteMap {
                         split_status <<= 1; // 8 -- in params regex end
                         false
                     },
+                    '<' if split_status == 2 => {
+                        split_status = 16;  // 16 -- in params type
+                        false
+                    },
+                    '>' if split_status == 16 => {
+                        split_status = 8;   // 8 -- in params type end
+                        false
+                    },
                     '/' if split_status == 0 || split_status == 2 || split_status == 8 => {
                         split_status = 1;   // reset to 1 -- just split
                         true
@@ -289,6 +882,10 @@ impl RouteMap {
                         panic!("Route can't contain empty segment between '/'s: {}", source_uri);
                     },
                     _ => {
+                        if split_status == 16 && !char::is_alphanumeric(c) && c != '_' {
+                            panic!("Route's parameter type can only contain alpha-numeric characters: {}", source_uri);
+                        }
+
                         if split_status == 2 && !char::is_alphanumeric(c) {
                             panic!("Route's parameter name can only contain alpha-numeric characters: {}", source_uri);
                         }
@@ -311,8 +908,20 @@ impl RouteMap {
                 }
 
                 validation = None;
+                constraint = None;
                 is_param = false;
 
+                // the catch-all segment, e.g. `*path`, or `*` if the value is not needed
+                if s.starts_with('*') && s[1..].chars().all(char::is_alphanumeric) {
+                    name = &s[1..];
+
+                    if !name.is_empty() && !param_names.insert(name) {
+                        panic!("Route parameters must have unique name: {}", s);
+                    }
+
+                    return Some(Field::catch_all(name.to_owned()));
+                }
+
                 if s.starts_with(':') {
                     name = &s[1..];
                     let size = name.len();
@@ -338,7 +947,8 @@ impl RouteMap {
 
                                 match pos {
                                     0 => actual_name = seg,
-                                    1 => validation = Regex::new(seg).ok(),
+                                    // the whole segment must match, not just a part of it
+                                    1 => validation = Regex::new(&format!("^(?:{})$", seg)).ok(),
                                     _ => return,
                                 };
 
@@ -348,12 +958,31 @@ impl RouteMap {
                         if !actual_name.is_empty() {
                             name = actual_name;
                         }
+                    } else if let Some(pos) = name.find('<') {
+                        // the param with a typed constraint, e.g. `:id<u64>`
+                        let type_name = name[pos + 1..].trim_end_matches('>');
+                        if pos == 0 || type_name.is_empty() || !name.ends_with('>') {
+                            panic!("Route parameters with type constraint must have a non-null parameter and type: {}", s);
+                        }
+
+                        constraint = match constraint::find(type_name) {
+                            Some(check) => Some((type_name.to_owned(), check)),
+                            None => panic!("Route parameter type `{}` is unknown: {}", type_name, s),
+                        };
+
+                        name = &name[..pos];
                     }
 
                     // if a parameter identifier already exists, panic for we won't allow it.
                     if !param_names.insert(name) {
                         panic!("Route parameters must have unique name: {}", s);
                     }
+                } else if s.contains(['*', '?', '[']) {
+                    // the glob pattern segment, e.g. `*.json`
+                    return match Glob::build(s, allow_case) {
+                        Some(pattern) => Some(Field::pattern(s.to_owned(), pattern)),
+                        None => panic!("Route segment has an invalid glob pattern: {}", s),
+                    };
                 } else {
                     name = &s;
                 }
@@ -364,10 +993,19 @@ impl RouteMap {
                     name.to_owned()
                 };
 
-                Some(Field::new(field, is_param, validation.take()))
+                Some(Field::new(field, is_param, validation.take()).with_constraint(constraint.take()))
             })
             .collect();
 
+        if let Some(pos) = result.iter().position(Field::is_catch_all) {
+            if pos + 1 != result.len() {
+                panic!(
+                    "Route's catch-all segment must be the last one: {}",
+                    source_uri
+                );
+            }
+        }
+
         result.reverse();
         result
     }
@@ -408,16 +1046,33 @@ impl RouteMap {
             actual_uri.push_str(part);
         }
 
-        // uri doesn't contain a file name, actual_uri === raw_uri, done (and route not found).
+        // uri doesn't contain a file name, actual_uri === raw_uri, only the static folder is left.
         if file_name.is_empty() {
-            return RouteHandler::default();
+            return self.search_static_mount(raw_uri);
         }
 
         if actual_uri.is_empty() {
             actual_uri.push_str(raw_uri);
         }
 
-        self.search(&actual_uri, raw_uri, file_name, params)
+        let result = self.search(&actual_uri, raw_uri, file_name, params);
+        if result.is_some() {
+            return result;
+        }
+
+        self.search_static_mount(raw_uri)
+    }
+
+    /// The last resort: the index files, folder listing or the fallback file of the static folder.
+    fn search_static_mount(&self, raw_uri: &str) -> RouteHandler {
+        match self
+            .static_path
+            .as_ref()
+            .and_then(|static_path| static_path.try_files(raw_uri))
+        {
+            Some(path) => RouteHandler::new(None, Some(path)),
+            None => RouteHandler::default(),
+        }
     }
 
     fn search(
@@ -429,9 +1084,16 @@ impl RouteMap {
     ) -> RouteHandler {
         let for_file = !file_name.is_empty();
 
-        if let Some(callback) = self.explicit.get(uri) {
-            // only exact match can return: callback and no file name, or path with file name (custom
-            if (!for_file && callback.0.is_some()) || (for_file && callback.1.is_some()) {
+        // the explicit routes are keyed in lowercase if the map is case-insensitive
+        let explicit = if !self.case_sensitive && uri.chars().any(char::is_uppercase) {
+            self.explicit.get(&uri.to_lowercase())
+        } else {
+            self.explicit.get(uri)
+        };
+
+        if let Some(callback) = explicit {
+            // only exact match can return: callback and no file name, or path with file name (custom
+            if (!for_file && callback.has_callback()) || (for_file && callback.1.is_some()) {
                 return RouteHandler::update_handler(callback.clone(), file_name);
             }
         }
@@ -458,37 +1120,137 @@ impl RouteMap {
         if !self.explicit_with_params.is_empty() {
             let result = search_params_router(&self.explicit_with_params, uri, params);
 
-            if (!for_file && result.0.is_some()) || (for_file && result.1.is_some()) {
+            if (!for_file && result.has_callback()) || (for_file && result.1.is_some()) {
                 return RouteHandler::update_handler(result, file_name);
             }
         }
 
+        // the wildcard routes only get a chance if no trie route, catch-all included, has matched
         if !self.wildcard.is_empty() {
             let result = search_wildcard_router(&self.wildcard, uri);
 
-            if (!for_file && result.0.is_some()) || (for_file && result.1.is_some()) {
+            if (!for_file && result.has_callback()) || (for_file && result.1.is_some()) {
                 return RouteHandler::update_handler(result, file_name);
             }
         }
 
-        RouteHandler(None, None)
+        RouteHandler::default()
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Route {
     store: HashMap<REST, RouteMap>,
+    redirects: HashMap<String, Arc<Redirect>>,
+    mounts: Vec<Mount>,
+    hosts: Vec<VirtualHost>,
+    names: HashMap<String, String>,
+    last_route: Option<String>,
     auth_func: Option<AuthFunc>,
+    policy: Option<AuthPolicy>,
 }
 
-impl Route {
-    pub(crate) fn init() {
-        unsafe {
-            ROUTER.set((Route::new(), AtomicUsize::new(1)));
-            ROUTE_CACHE.set(HashMap::new());
+/// A router serving the requests to the matching host names only, see `Router::host`.
+#[derive(Clone)]
+struct VirtualHost {
+    pattern: String,
+    labels: Vec<String>,
+    route: Route,
+}
+
+impl VirtualHost {
+    fn new(pattern: &str, route: Route) -> Self {
+        let pattern = pattern.trim().trim_end_matches('.').to_lowercase();
+        let mut names = HashSet::new();
+
+        let labels: Vec<String> = pattern.split('.').map(String::from).collect();
+        for label in labels.iter() {
+            let valid = match label.as_str() {
+                "" => false,
+                "*" => true,
+                l if l.starts_with(':') => {
+                    l.len() > 1
+                        && l[1..].chars().all(char::is_alphanumeric)
+                        && names.insert(l.to_owned())
+                }
+                l => l.chars().all(|c| c.is_alphanumeric() || c == '-'),
+            };
+
+            if !valid {
+                panic!(
+                    "Host pattern can only contain the names, `*` or unique `:params` between the dots: {}",
+                    pattern
+                );
+            }
+        }
+
+        VirtualHost {
+            pattern,
+            labels,
+            route,
+        }
+    }
+
+    fn is_exact(&self) -> bool {
+        !self.pattern.contains(['*', ':'])
+    }
+
+    /// Match the normalized host name, where `*` matches any label, and `:param` also saves the
+    /// label as the param, e.g. `:tenant.example.com`.
+    fn matches(&self, host: &str, params: &mut HashMap<String, String>) -> bool {
+        let parts: Vec<&str> = host.split('.').collect();
+        if parts.len() != self.labels.len() {
+            return false;
+        }
+
+        let found = self.labels.iter().zip(parts.iter()).all(|(label, part)| {
+            !part.is_empty() && (label == "*" || label.starts_with(':') || label == part)
+        });
+
+        if !found {
+            return false;
+        }
+
+        for (label, part) in self.labels.iter().zip(parts.iter()) {
+            if let Some(name) = label.strip_prefix(':') {
+                params.insert(name.to_owned(), (*part).to_owned());
+            }
+        }
+
+        true
+    }
+}
+
+/// A sub-router mounted under the path prefix, which only sees the remainder of the request uri.
+#[derive(Clone)]
+struct Mount {
+    prefix: String,
+    case_sensitive: bool,
+    route: Route,
+}
+
+impl Mount {
+    /// Strip the prefix from the uri, which must match the whole segments, e.g. `/api` matches
+    /// `/api` and `/api/users`, but not `/apis`.
+    fn strip<'a>(&self, uri: &'a str) -> Option<&'a str> {
+        let size = self.prefix.len();
+        let head = uri.get(..size)?;
+
+        if (self.case_sensitive && head != self.prefix)
+            || (!self.case_sensitive && !head.eq_ignore_ascii_case(&self.prefix))
+        {
+            return None;
+        }
+
+        match &uri[size..] {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
         }
     }
+}
 
+impl Route {
     pub fn new() -> Self {
         Default::default()
     }
@@ -513,7 +1275,10 @@ impl Route {
     }
 
     pub fn use_router_async(another: Route) {
-        thread::spawn(|| {
+        let instance = instance::current();
+
+        thread::spawn(move || {
+            let _scope = instance::enter(&instance);
             Self::use_router(another);
         });
     }
@@ -547,509 +1312,1926 @@ impl Route {
         Route::write().with(|r| r.add(method, uri, callback));
     }
 
-    pub(crate) fn add_static(method: REST, uri: Option<RequestPath>, path: PathBuf) {
-        Route::write().with(|r| match uri {
-            Some(u) => r.add(method, u, RouteHandler(None, Some(path))),
-            None => r.set_static(method, path),
-        });
-    }
-
-    pub(crate) fn static_lists(loc_or_ext: String, is_white_list: bool, for_path: Option<PathBuf>) {
-        Route::write().with(|r| {
-            if is_white_list {
-                r.static_white_list(loc_or_ext, for_path);
-            } else {
-                r.static_black_list(loc_or_ext, for_path);
-            }
-        })
-    }
-
-    fn add(&mut self, method: REST, uri: RequestPath, callback: RouteHandler) {
-        if let Some(r) = self.store.get_mut(&method) {
-            //find, insert, done.
-            r.insert(uri, callback);
-            return;
-        }
-
-        let mut map = RouteMap::new();
-        map.insert(uri, callback);
-        self.store.insert(method, map);
-    }
-
-    fn set_static(&mut self, method: REST, path: PathBuf) {
-        if !path.exists() || !path.is_dir() {
-            panic!("The static path must point to a folder");
-        }
-
-        let static_route = StaticLocRoute {
-            location: path,
-            black_list: Vec::new(),
-            white_list: Vec::new(),
-        };
-
-        if let Some(r) = self.store.get_mut(&method) {
-            //find, insert, done.
-            r.static_path.replace(static_route);
-            return;
-        }
-
-        let mut map = RouteMap::new();
-        map.static_path.replace(static_route);
-        self.store.insert(method, map);
-    }
-
-    fn replace_with(&mut self, mut another: Route) {
-        self.store = another.store;
-        self.auth_func = another.auth_func.take();
-    }
-
-    fn read() -> RouteGuard<'static> {
-        RouteGuard::checkout(true)
+    /// Add the route to the live router, which can be done while the server is running: the
+    /// requests in flight are still served by the version of the router they're matched against,
+    /// and the requests coming after will see the new route.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let server = HttpServer::new();
+    /// Route::insert_route(REST::GET, RequestPath::Explicit("/beta"), simple_handler);
+    /// assert!(Route::remove_route(REST::GET, RequestPath::Explicit("/beta")));
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send("Hello from the beta route");
+    /// }
+    /// ```
+    pub fn insert_route(method: REST, uri: RequestPath, callback: Callback) {
+        Route::add_route(method, uri, RouteHandler::new(Some(callback), None));
     }
 
-    fn write() -> RouteGuard<'static> {
-        RouteGuard::checkout(false)
+    /// Remove the route of the exact same method and path from the live router, see
+    /// `Route::insert_route`. Returns `false` if there's no such route.
+    pub fn remove_route(method: REST, uri: RequestPath) -> bool {
+        Route::write().with(|r| r.remove(&method, uri))
     }
-}
-
-pub trait Router {
-    fn get(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn patch(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn post(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn put(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn delete(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn options(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn other(&mut self, method: &str, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn all(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
-    fn use_static(&mut self, path: PathBuf) -> &mut dyn Router;
-    fn use_custom_static(&mut self, uri: RequestPath, path: PathBuf) -> &mut dyn Router;
-    fn static_white_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>);
-    fn static_black_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>);
-    fn case_sensitive(&mut self, allow_case: bool, method: Option<REST>);
-}
 
-impl Router for Route {
-    fn get(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::GET, uri, RouteHandler(Some(callback), None));
-        self
+    /// The version of the live router, which is bumped whenever the changes made to it since the
+    /// last lookup are published.
+    pub fn version() -> u64 {
+        Route::read().0
     }
 
-    fn patch(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::PATCH, uri, RouteHandler(Some(callback), None));
-        self
+    pub(crate) fn add_static(method: REST, uri: Option<RequestPath>, path: PathBuf) {
+        Route::write().with(|r| match uri {
+            Some(u) => r.add(method, u, RouteHandler::new(None, Some(path))),
+            None => r.set_static(method, path, StaticOptions::default()),
+        });
     }
 
-    fn post(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::POST, uri, RouteHandler(Some(callback), None));
-        self
+    pub(crate) fn add_static_with(method: REST, path: PathBuf, options: StaticOptions) {
+        Route::write().with(|r| r.set_static(method, path, options));
     }
 
-    fn put(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::PUT, uri, RouteHandler(Some(callback), None));
-        self
+    /// Check if the file can be served by the static folders it's in, per their white-lists and
+    /// black-lists.
+    pub(crate) fn static_access(path: &Path, is_dir: bool) -> bool {
+        Route::read().with(|r| r.check_static_access(path, is_dir))
     }
 
-    fn delete(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::DELETE, uri, RouteHandler(Some(callback), None));
-        self
+    fn check_static_access(&self, path: &Path, is_dir: bool) -> bool {
+        let allowed = self
+            .store
+            .values()
+            .filter_map(|map| map.static_path.as_ref())
+            .filter(|static_path| path.starts_with(&static_path.root))
+            .all(|static_path| {
+                static_path.check_link(path) && static_path.check_access(path, is_dir)
+            });
+
+        allowed
+            && self
+                .mounts
+                .iter()
+                .all(|mount| mount.route.check_static_access(path, is_dir))
+            && self
+                .hosts
+                .iter()
+                .all(|vhost| vhost.route.check_static_access(path, is_dir))
     }
 
-    fn options(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.add(REST::OPTIONS, uri, RouteHandler(Some(callback), None));
-        self
-    }
+    /// Build the url to the named route, with the params filled into the `:param` and the `*rest`
+    /// segments, and the params not used by the route appended as the query string. The param
+    /// values are percent-encoded, and must pass the regex validation of the segment, if any.
+    /// Return `None` if the route is not found or the params don't fit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server
+    ///     .get(RequestPath::ExplicitWithParams(r"/user/:id(\d+)"), simple_handler)
+    ///     .name("user_show");
+    ///
+    /// assert_eq!(
+    ///     Route::url_for("user_show", &[("id", "42"), ("tab", "posts")]),
+    ///     Some(String::from("/user/42?tab=posts"))
+    /// );
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send("hello world");
+    /// }
+    /// ```
+    pub fn url_for(name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let template = Route::read().with(|r| r.find_name(name));
+
+        match template {
+            Some(t) => build_url(&t, params),
+            None => {
+                debug::print(
+                    &format!("Unable to find the route named: {}", name),
+                    InfoLevel::Warning,
+                );
 
-    fn other(&mut self, method: &str, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        if method.is_empty() {
-            panic!("Must provide a valid method!");
+                None
+            }
         }
-
-        let request_method = REST::OTHER(method.to_uppercase());
-        self.add(request_method, uri, RouteHandler(Some(callback), None));
-
-        self
-    }
-
-    /// Function 'all' will match the uri on all request methods. Note that the "match all" paradigm
-    /// is used in this framework as a safe fallback, which means that if a different callback
-    /// has been defined for the same uri but under a explicitly defined request method (e.g. get,
-    /// post, etc.), it will be matched and invoked instead of the "match all" callback functions.
-    fn all(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
-        self.other("*", uri, callback)
     }
 
-    /// Define a static folder location, where the request will be forwarded to and read the desired
-    /// file as the response body.
+    /// List all the routes added to the server, including the ones from the mounted routers, e.g.
+    /// to be logged at startup, or to be shown by a debug endpoint.
     ///
     /// # Example
     ///
     /// ```
     /// use rusty_express::prelude::*;
     ///
-    /// // define http server now
     /// let mut server = HttpServer::new();
-    /// server.set_pool_size(8);
-    /// server.use_static(PathBuf::from(r".\static"));
+    /// server.get(RequestPath::Explicit("/routes"), list_routes);
+    ///
+    /// pub fn list_routes(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     let routes: Vec<String> = Route::routes().iter().map(|r| r.to_string()).collect();
+    ///     resp.send(&routes.join("\n"));
+    /// }
     /// ```
-    fn use_static(&mut self, path: PathBuf) -> &mut dyn Router {
-        self.set_static(REST::GET, path);
-        self
+    pub fn routes() -> Vec<RouteInfo> {
+        let mut routes = Vec::new();
+        Route::read().with(|r| r.list(None, "", &mut routes));
+        routes
     }
 
-    /// Define a customized static folder location, where the requested file will be served only if
-    /// it matches the URI rules defined by the API.
+    /// A route conflicts with another one if they would match the same request, e.g. the same path
+    /// for the same method, or the params routes only differ in the param names. By default, the
+    /// route added later is ignored with a warning, and if set to be strict, we will panic instead.
+    pub fn strict_routes(strict: bool) {
+        instance::current()
+            .router
+            .strict
+            .store(strict, Ordering::Release);
+    }
+
+    /// Set how the request paths are normalized before they're routed, which applies to the requests
+    /// coming after, see `PathOptions`.
+    pub fn use_path_options(options: PathOptions) {
+        instance::current()
+            .router
+            .path_options
+            .store(options.pack(), Ordering::Release);
+        clear_cache();
+    }
+
+    pub fn get_path_options() -> PathOptions {
+        PathOptions::current()
+    }
+
+    /// Add the custom type for the typed route params, e.g. `/user/:name<handle>`, which shall be
+    /// added before the routes using it. A custom type replaces the built-in one of the same name.
     ///
     /// # Example
     ///
     /// ```
     /// use rusty_express::prelude::*;
     ///
-    /// // define http server now
+    /// Route::add_constraint("handle", |v| v.len() <= 16 && v.chars().all(char::is_alphanumeric));
+    ///
     /// let mut server = HttpServer::new();
+    /// server.get(RequestPath::ExplicitWithParams("/user/:name<handle>"), simple_handler);
     ///
-    /// // only the `index.html` file in the static folder of the project location will be served.
-    /// server.use_custom_static(RequestPath::Explicit("/index.html"), PathBuf::from(r".\static"));
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send(&format!("hello {}", req.param("name").unwrap_or_default()));
+    /// }
     /// ```
-    fn use_custom_static(&mut self, uri: RequestPath, path: PathBuf) -> &mut dyn Router {
-        self.add(REST::GET, uri, RouteHandler(None, Some(path)));
-        self
+    pub fn add_constraint(name: &str, check: ParamConstraint) {
+        constraint::register(name, check);
     }
 
-    /// This API will add the location or the extension that are allowed to be served to all the static
-    /// routes. If a location is white-listed, you must provide a normalized and absolute path to the
-    /// folder, and all files or sub-folders under the given path will be deemed as white-listed;
-    /// if an extension is provided, it must be formatted as `*.<extension>`, for example,
-    /// `*.txt` is a valid extension, though `.txt` or `txt` is not.
-    ///
-    /// Note that if the `for_path` params are provided, the white list will only be applied to the
-    /// given path (i.e. defined prior with the path to the static folder location).
-    fn static_white_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
-        let data = unsafe {
-            let raw = Arc::new(loc_or_ext.to_lowercase());
-
-            if loc_or_ext.starts_with("*.") {
-                StaticListData::Ext(raw)
-            } else {
-                StaticListData::Loc(raw)
-            }
-        };
+    pub(crate) fn set_policy(policy: AuthPolicy) {
+        Route::write().with(|r| r.policy = Some(policy));
+    }
 
-        self.store.values_mut().for_each(|mut m| {
-            if let Some(s_route) = m.static_path.as_mut() {
-                if let Some(p) = for_path.as_ref() {
-                    if &s_route.location != p {
-                        return;
-                    }
-                }
+    pub(crate) fn name_route(name: &str) {
+        Route::write().with(|r| r.set_name(name));
+    }
 
-                s_route.white_list.push(data.clone());
-            }
-        });
+    pub(crate) fn add_host(pattern: &str, route: Route) {
+        Route::write().with(|r| r.set_host(pattern, route));
     }
 
-    /// This API will add the location or the extension that are *NOT* allowed to be served to any
-    /// of the static routes. If a folder location is black-listed, you must provide a normalized and
-    /// absolute path to this folder, and all files or sub-folders under the given path will be
-    /// deemed as black-listed; if an extension is provided, it must be formatted as
-    /// `*.<extension>`, for example, `*.txt` is a valid extension, though `.txt` or `txt` is not.
-    ///
-    /// Note that if the `for_path` params are provided, the white list will only be applied to the
-    /// given path (i.e. defined prior with the path to the static folder location).
-    fn static_black_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
-        let data = unsafe {
-            let raw = Arc::new(loc_or_ext.to_lowercase());
+    pub(crate) fn add_mount(prefix: &str, route: Route) {
+        Route::write().with(|r| r.set_mount(prefix, route));
+    }
 
-            if loc_or_ext.starts_with("*.") {
-                StaticListData::Ext(raw)
-            } else {
-                StaticListData::Loc(raw)
-            }
-        };
+    pub(crate) fn add_resource(path: &str, controller: &dyn ResourceController) {
+        Route::write().with(|r| r.set_resource(path, controller));
+    }
 
-        self.store.values_mut().for_each(|mut route| {
-            if let Some(s_route) = route.static_path.as_mut() {
-                if let Some(p) = for_path.as_ref() {
-                    if &s_route.location != p {
-                        return;
-                    }
-                }
+    pub(crate) fn add_redirect(from: &str, to: &str, status: u16) {
+        Route::write().with(|r| r.set_redirect(from, to, status));
+    }
 
-                s_route.black_list.push(data.clone());
-            }
-        });
+    pub(crate) fn static_lists(loc_or_ext: String, is_white_list: bool, for_path: Option<PathBuf>) {
+        Route::write().with(|r| r.static_rule(&loc_or_ext, is_white_list, for_path.as_ref()))
     }
 
-    /// Note: this API only affect routes moving forward, and it will not be applied to routes
-    /// already in the `Router`.
-    fn case_sensitive(&mut self, allow_case: bool, method: Option<REST>) {
-        if method.is_none() {
-            for maps in self.store.values_mut() {
-                maps.case_sensitive = allow_case;
+    fn add(&mut self, method: REST, uri: RequestPath, callback: RouteHandler) {
+        // remember the route, in case it's going to be named
+        self.last_route = match uri {
+            RequestPath::Explicit(path) | RequestPath::ExplicitWithParams(path) => {
+                Some(path.to_owned())
             }
+            RequestPath::WildCard(_) => None,
+        };
 
-            return;
-        }
+        let req_uri = match uri {
+            RequestPath::Explicit(path)
+            | RequestPath::ExplicitWithParams(path)
+            | RequestPath::WildCard(path) => path,
+        };
 
-        if let Some(m) = method {
-            if let Some(maps) = self.store.get_mut(&m) {
-                maps.case_sensitive = allow_case
-            }
+        let map = self
+            .store
+            .entry(method.clone())
+            .or_insert_with(RouteMap::new);
+        if !map.insert(uri, callback) {
+            report_conflict(&format!("`{} {}`", method, req_uri));
         }
     }
-}
 
-pub(crate) trait RouteSeeker {
-    fn seek(method: &REST, uri: &str, tx: channel::Sender<(RouteHandler, HashMap<String, String>)>);
-    fn seek_sync(method: &REST, uri: &str) -> (RouteHandler, HashMap<String, String>);
-}
+    fn remove(&mut self, method: &REST, uri: RequestPath) -> bool {
+        let path = match uri {
+            RequestPath::Explicit(path)
+            | RequestPath::ExplicitWithParams(path)
+            | RequestPath::WildCard(path) => path.to_owned(),
+        };
 
-impl RouteSeeker for Route {
-    fn seek(
-        method: &REST,
-        uri: &str,
-        tx: channel::Sender<(RouteHandler, HashMap<String, String>)>,
-    ) {
-        if let Err(e) = tx.send(Self::seek_sync(method, uri)) {
-            debug::print("Unable to find the route handler", InfoLevel::Error);
+        let removed = match self.store.get_mut(method) {
+            Some(map) => map.remove(uri),
+            None => false,
+        };
+
+        // forget the names of the path, once it's not served by any method
+        if removed
+            && !self
+                .store
+                .values()
+                .any(|map| map.routes.iter().any(|entry| entry.pattern == path))
+        {
+            self.names.retain(|_, named| *named != path);
         }
+
+        removed
     }
 
-    fn seek_sync(method: &REST, uri: &str) -> (RouteHandler, HashMap<String, String>) {
-        //TODO: check cache first
+    fn set_static(&mut self, method: REST, path: PathBuf, options: StaticOptions) {
+        if !path.exists() || !path.is_dir() {
+            panic!("The static path must point to a folder");
+        }
 
-        // keep the route_store in limited scope so we can release the read lock ASAP
-        Route::read().with(|r| {
-            let mut result = RouteHandler(None, None);
-            let mut params = HashMap::new();
+        let map = self
+            .store
+            .entry(method.clone())
+            .or_insert_with(RouteMap::new);
+        if map.static_path.is_some() {
+            report_conflict(&format!("the static folder for `{}`", method));
+            return;
+        }
 
-            // get from the method
-            if let Some(routes) = r.store.get(method) {
-                result = routes.seek_path(uri, &mut params);
-            }
+        map.static_path.replace(StaticLocRoute::new(path, options));
+    }
 
-            // if a header only request, fallback to search with REST::GET
-            if result.is_none() && method == &REST::OTHER(String::from("HEADER")) {
-                if let Some(routes) = r.store.get(&REST::GET) {
-                    result = routes.seek_path(uri, &mut params);
-                }
-            }
+    fn set_name(&mut self, name: &str) {
+        if name.is_empty() {
+            panic!("Route name must have valid contents.");
+        }
 
-            // otherwise, try the all-match routes
-            if result.is_none() {
-                if let Some(all_routes) = r.store.get(&REST::OTHER(String::from("*"))) {
-                    result = all_routes.seek_path(uri, &mut params);
-                }
-            }
+        let path = match self.last_route.as_ref() {
+            Some(path) => path.to_owned(),
+            None => panic!(
+                "Only the explicit routes, with or without params, can be named: {}",
+                name
+            ),
+        };
 
-            (result, params)
-        })
+        if self.names.contains_key(name) {
+            panic!("Route name must be unique: {}", name);
+        }
 
-        //TODO: Caching the request, also maintain the hash-map if it gets too large
+        self.names.insert(name.to_owned(), path);
     }
-}
 
-//TODO: add the 3rd field -- Option<Route>
-pub(crate) struct RouteHandler(Option<Callback>, Option<PathBuf>);
+    /// Find the path of the named route, including the ones from the mounted routers.
+    fn find_name(&self, name: &str) -> Option<String> {
+        if let Some(path) = self.names.get(name) {
+            return Some(path.to_owned());
+        }
 
-impl RouteHandler {
+        self.mounts
+            .iter()
+            .find_map(|mount| {
+                mount
+                    .route
+                    .find_name(name)
+                    .map(|path| [mount.prefix.as_str(), path.as_str()].join(""))
+            })
+            .or_else(|| {
+                self.hosts
+                    .iter()
+                    .find_map(|vhost| vhost.route.find_name(name))
+            })
+    }
+
+    fn set_host(&mut self, pattern: &str, route: Route) {
+        let vhost = VirtualHost::new(pattern, route);
+
+        // replace the router already serving the same host
+        self.hosts.retain(|h| h.pattern != vhost.pattern);
+
+        // the exact host names are matched first, then the patterns in the order they're added
+        let pos = if vhost.is_exact() {
+            self.hosts
+                .iter()
+                .position(|h| !h.is_exact())
+                .unwrap_or(self.hosts.len())
+        } else {
+            self.hosts.len()
+        };
+
+        self.hosts.insert(pos, vhost);
+    }
+
+    /// Describe the routes, with the mounted ones under their prefixes, see `Route::routes`.
+    fn list(&self, host: Option<&str>, prefix: &str, routes: &mut Vec<RouteInfo>) {
+        let mut methods: Vec<&REST> = self.store.keys().collect();
+        methods.sort_by_key(|method| method.to_string());
+
+        for method in methods {
+            let map = match self.store.get(method) {
+                Some(map) => map,
+                None => continue,
+            };
+
+            for entry in map.routes.iter() {
+                let name = self
+                    .names
+                    .iter()
+                    .find(|(_, path)| **path == entry.pattern)
+                    .map(|(name, _)| name.to_owned());
+
+                routes.push(RouteInfo {
+                    method: method.clone(),
+                    pattern: [prefix, &entry.pattern].join(""),
+                    host: host.map(String::from),
+                    kind: entry.kind,
+                    name,
+                    target: entry.target.clone(),
+                    guarded: entry.guarded,
+                });
+            }
+
+            if let Some(static_path) = map.static_path.as_ref() {
+                routes.push(RouteInfo {
+                    method: method.clone(),
+                    pattern: [prefix, "/"].join(""),
+                    host: host.map(String::from),
+                    kind: RouteKind::StaticFolder,
+                    name: None,
+                    target: Some(static_path.location.to_string_lossy().into_owned()),
+                    guarded: false,
+                });
+            }
+        }
+
+        let mut redirects: Vec<(&String, &Arc<Redirect>)> = self.redirects.iter().collect();
+        redirects.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (_, redirect) in redirects {
+            routes.push(RouteInfo {
+                method: REST::OTHER(String::from("*")),
+                pattern: [prefix, &redirect.from].join(""),
+                host: host.map(String::from),
+                kind: RouteKind::Redirect,
+                name: None,
+                target: Some(redirect.location.clone()),
+                guarded: false,
+            });
+        }
+
+        for mount in self.mounts.iter() {
+            mount
+                .route
+                .list(host, &[prefix, &mount.prefix].join(""), routes);
+        }
+
+        for vhost in self.hosts.iter() {
+            vhost.route.list(Some(&vhost.pattern), prefix, routes);
+        }
+    }
+
+    fn set_resource(&mut self, path: &str, controller: &dyn ResourceController) {
+        for (method, uri, callback) in resource::routes(path, controller) {
+            self.add(
+                method,
+                RequestPath::ExplicitWithParams(&uri),
+                RouteHandler::new(Some(callback), None),
+            );
+        }
+    }
+
+    fn set_mount(&mut self, prefix: &str, route: Route) {
+        if prefix.is_empty() || !prefix.starts_with('/') {
+            panic!("Mount path must have valid contents and start with '/'.");
+        }
+
+        let prefix = prefix.trim_end_matches('/');
+        if prefix.is_empty() {
+            panic!("Can't mount a router to the root path, use the `use_router` API instead.");
+        }
+
+        let mount = Mount {
+            prefix: prefix.to_owned(),
+            case_sensitive: route.store.values().any(|map| map.case_sensitive),
+            route,
+        };
+
+        // replace the router already mounted at the same path
+        self.mounts.retain(|m| m.prefix != mount.prefix);
+
+        // the longest prefix shall be matched first
+        let pos = self
+            .mounts
+            .iter()
+            .position(|m| m.prefix.len() < mount.prefix.len())
+            .unwrap_or(self.mounts.len());
+
+        self.mounts.insert(pos, mount);
+    }
+
+    fn static_rule(&mut self, pattern: &str, is_white_list: bool, for_path: Option<&PathBuf>) {
+        self.store.values_mut().for_each(|route| {
+            if let Some(s_route) = route.static_path.as_mut() {
+                if let Some(p) = for_path {
+                    if &s_route.location != p {
+                        return;
+                    }
+                }
+
+                s_route.add_rule(pattern, is_white_list);
+            }
+        });
+    }
+
+    fn set_redirect(&mut self, from: &str, to: &str, status: u16) {
+        if from.is_empty() || !from.starts_with('/') {
+            panic!("Redirect source must have valid contents and start with '/'.");
+        }
+
+        if to.is_empty() {
+            panic!("Redirect target must have valid contents.");
+        }
+
+        let status = match status {
+            301 | 302 | 303 | 307 | 308 => status,
+            _ => panic!("Redirect status must be one of 301, 302, 303, 307 or 308."),
+        };
+
+        // the redirect source is matched with or without the trailing slash, see `find_redirect`
+        let from = trim_trailing_slash(from);
+
+        // keyed in lower case like the explicit routes
+        let key = from.to_lowercase();
+        if self.redirects.contains_key(&key) {
+            report_conflict(&format!("the redirect from `{}`", from));
+            return;
+        }
+
+        self.redirects.insert(
+            key,
+            Arc::new(Redirect {
+                from: from.to_owned(),
+                location: to.to_owned(),
+                status,
+            }),
+        );
+    }
+
+    /// Find the redirect from the uri, which is matched case-insensitively unless the routes are
+    /// set to be case-sensitive.
+    fn find_redirect(&self, uri: &str) -> Option<&Arc<Redirect>> {
+        if self.redirects.is_empty() {
+            return None;
+        }
+
+        let uri = trim_trailing_slash(uri);
+
+        let redirect = self.redirects.get(&uri.to_lowercase())?;
+        if redirect.from != uri && self.store.values().any(|map| map.case_sensitive) {
+            return None;
+        }
+
+        Some(redirect)
+    }
+
+    fn replace_with(&mut self, mut another: Route) {
+        self.store = another.store;
+        self.redirects = another.redirects;
+        self.mounts = another.mounts;
+        self.hosts = another.hosts;
+        self.names = another.names;
+        self.last_route = another.last_route;
+        self.auth_func = another.auth_func.take();
+        self.policy = another.policy.take();
+    }
+
+    /// If any host routes are registered, including the ones in the mounted routers.
+    fn has_hosts(&self) -> bool {
+        !self.hosts.is_empty() || self.mounts.iter().any(|mount| mount.route.has_hosts())
+    }
+
+    fn seek_route(
+        &self,
+        method: &REST,
+        host: &str,
+        uri: &str,
+        params: &mut HashMap<String, String>,
+    ) -> RouteHandler {
+        let mut result = self.find_route(method, host, uri, params);
+
+        // the policy of the router guards all its routes, including the mounted ones
+        if let Some(policy) = self.policy {
+            if result.is_some() {
+                result.require(policy);
+            }
+        }
+
+        result
+    }
+
+    /// Seek the route of the uri as it is, and if there's none, apply the trailing slash policy to
+    /// the route of the uri with or without the trailing slash instead, see `PathOptions`.
+    fn seek_slashed(
+        &self,
+        method: &REST,
+        host: &str,
+        uri: &str,
+        params: &mut HashMap<String, String>,
+        policy: TrailingSlash,
+    ) -> RouteHandler {
+        let result = self.seek_route(method, host, uri, params);
+        if result.has_callback() || result.2.is_some() || policy == TrailingSlash::Strict {
+            return result;
+        }
+
+        let trimmed = trim_trailing_slash(uri);
+        let other = if trimmed.len() < uri.len() {
+            trimmed.to_owned()
+        } else if uri.len() > 1 {
+            [uri, "/"].join("")
+        } else {
+            return result;
+        };
+
+        let mut other_params = HashMap::new();
+        let found = self.seek_route(method, host, &other, &mut other_params);
+        if !found.has_callback() {
+            return result;
+        }
+
+        params.clear();
+        match policy {
+            TrailingSlash::Redirect => RouteHandler::redirect(uri, &other, 308),
+            _ => {
+                params.extend(other_params);
+                found
+            }
+        }
+    }
+
+    fn find_route(
+        &self,
+        method: &REST,
+        host: &str,
+        uri: &str,
+        params: &mut HashMap<String, String>,
+    ) -> RouteHandler {
+        // the router of the matching host takes over all the requests to the host
+        for vhost in self.hosts.iter() {
+            if vhost.matches(host, params) {
+                return vhost.route.seek_route(method, host, uri, params);
+            }
+        }
+
+        let mut result = RouteHandler::default();
+
+        // the redirect table takes precedence over the routes, regardless of the method
+        if let Some(redirect) = self.find_redirect(uri) {
+            result.2 = Some(Arc::clone(redirect));
+            return result;
+        }
+
+        // then the mounted routers, which only see the remainder of the uri
+        for mount in self.mounts.iter() {
+            if let Some(rest) = mount.strip(uri) {
+                result = mount.route.seek_route(method, host, rest, params);
+
+                if result.is_some() {
+                    let base = match result.3.take() {
+                        Some(inner) => [&mount.prefix, inner.as_str()].join(""),
+                        None => mount.prefix.clone(),
+                    };
+
+                    result.3 = Some(Arc::new(base));
+                    return result;
+                }
+
+                params.clear();
+            }
+        }
+
+        // get from the method
+        if let Some(routes) = self.store.get(method) {
+            result = routes.seek_path(uri, params);
+        }
+
+        // if a header only request, fallback to search with REST::GET
+        if result.is_none() && method == &REST::OTHER(String::from("HEADER")) {
+            if let Some(routes) = self.store.get(&REST::GET) {
+                result = routes.seek_path(uri, params);
+            }
+        }
+
+        // otherwise, try the all-match routes
+        if result.is_none() {
+            if let Some(all_routes) = self.store.get(&REST::OTHER(String::from("*"))) {
+                result = all_routes.seek_path(uri, params);
+            }
+        }
+
+        result
+    }
+
+    fn read() -> RouteSnapshot {
+        RouteSnapshot::checkout()
+    }
+
+    fn write() -> RouteGuard {
+        RouteGuard::checkout()
+    }
+}
+
+pub trait Router {
+    fn get(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn patch(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn post(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn put(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn delete(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn options(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn other(&mut self, method: &str, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn all(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router;
+    fn use_static(&mut self, path: PathBuf) -> &mut dyn Router;
+    fn use_static_with(&mut self, path: PathBuf, options: StaticOptions) -> &mut dyn Router;
+    fn use_custom_static(&mut self, uri: RequestPath, path: PathBuf) -> &mut dyn Router;
+    fn static_white_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>);
+    fn static_black_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>);
+    fn case_sensitive(&mut self, allow_case: bool, method: Option<REST>);
+    fn redirect(&mut self, from: &str, to: &str, status: u16) -> &mut dyn Router;
+    fn mount(&mut self, prefix: &str, router: Route) -> &mut dyn Router;
+    fn host(&mut self, pattern: &str, router: Route) -> &mut dyn Router;
+    fn guarded(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        guard: RequestGuard,
+        callback: Callback,
+    ) -> &mut dyn Router;
+    fn name(&mut self, name: &str) -> &mut dyn Router;
+    fn authorized(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        policy: AuthPolicy,
+        callback: Callback,
+    ) -> &mut dyn Router;
+    fn policy(&mut self, policy: AuthPolicy) -> &mut dyn Router;
+    fn resource(&mut self, path: &str, controller: &dyn ResourceController) -> &mut dyn Router;
+}
+
+impl Router for Route {
+    fn get(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::GET, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn patch(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::PATCH, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn post(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::POST, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn put(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::PUT, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn delete(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::DELETE, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn options(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.add(REST::OPTIONS, uri, RouteHandler::new(Some(callback), None));
+        self
+    }
+
+    fn other(&mut self, method: &str, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        if method.is_empty() {
+            panic!("Must provide a valid method!");
+        }
+
+        let request_method = REST::OTHER(method.to_uppercase());
+        self.add(request_method, uri, RouteHandler::new(Some(callback), None));
+
+        self
+    }
+
+    /// Function 'all' will match the uri on all request methods. Note that the "match all" paradigm
+    /// is used in this framework as a safe fallback, which means that if a different callback
+    /// has been defined for the same uri but under a explicitly defined request method (e.g. get,
+    /// post, etc.), it will be matched and invoked instead of the "match all" callback functions.
+    fn all(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        self.other("*", uri, callback)
+    }
+
+    /// Define a static folder location, where the request will be forwarded to and read the desired
+    /// file as the response body.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_express::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// // define http server now
+    /// let mut server = HttpServer::new();
+    /// server.set_pool_size(8);
+    /// server.use_static(PathBuf::from(r".\static"));
+    /// ```
+    fn use_static(&mut self, path: PathBuf) -> &mut dyn Router {
+        self.set_static(REST::GET, path, StaticOptions::default());
+        self
+    }
+
+    /// Define a static folder location with the options on how to serve the folders, e.g. the index
+    /// files, folder listing, or the fallback file for a single page app.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_express::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// let mut options = StaticOptions::new();
+    /// options.set_try_files(&["$uri", "$uri.html", "$uri/"]);
+    /// options.set_fallback("index.html");
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.use_static_with(PathBuf::from(r".\static"), options);
+    /// ```
+    fn use_static_with(&mut self, path: PathBuf, options: StaticOptions) -> &mut dyn Router {
+        self.set_static(REST::GET, path, options);
+        self
+    }
+
+    /// Define a customized static folder location, where the requested file will be served only if
+    /// it matches the URI rules defined by the API.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// // define http server now
+    /// let mut server = HttpServer::new();
+    ///
+    /// // only the `index.html` file in the static folder of the project location will be served.
+    /// server.use_custom_static(RequestPath::Explicit("/index.html"), PathBuf::from(r".\static"));
+    /// ```
+    fn use_custom_static(&mut self, uri: RequestPath, path: PathBuf) -> &mut dyn Router {
+        self.add(REST::GET, uri, RouteHandler::new(None, Some(path)));
+        self
+    }
+
+    /// This API will add the glob rule of the files that are allowed to be served to all the static
+    /// routes, and once a white-list is set, only the files matching one of its rules are served. A
+    /// rule without a `/` matches the file or folder name at any level, e.g. `*.txt` or `public`,
+    /// otherwise it matches the path from the static folder root, e.g. `assets/**/*.js`, and a rule
+    /// matching a folder covers everything beneath it. An absolute path to a folder under the static
+    /// folder is also accepted, which white-lists the folder and everything beneath it.
+    ///
+    /// Note that if the `for_path` params are provided, the white list will only be applied to the
+    /// given path (i.e. defined prior with the path to the static folder location).
+    fn static_white_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
+        self.static_rule(&loc_or_ext, true, for_path.as_ref());
+    }
+
+    /// This API will add the glob rule of the files that are *NOT* allowed to be served to any of
+    /// the static routes, which takes precedence over the white-list. The rules follow the same
+    /// syntax as the `static_white_list`, e.g. `*.map`, `private/**` or `**/secret.*`.
+    ///
+    /// Note that if the `for_path` params are provided, the black list will only be applied to the
+    /// given path (i.e. defined prior with the path to the static folder location).
+    fn static_black_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
+        self.static_rule(&loc_or_ext, false, for_path.as_ref());
+    }
+
+    /// Note: this API only affect routes moving forward, and it will not be applied to routes
+    /// already in the `Router`.
+    fn case_sensitive(&mut self, allow_case: bool, method: Option<REST>) {
+        if method.is_none() {
+            for maps in self.store.values_mut() {
+                maps.case_sensitive = allow_case;
+            }
+
+            return;
+        }
+
+        if let Some(m) = method {
+            if let Some(maps) = self.store.get_mut(&m) {
+                maps.case_sensitive = allow_case
+            }
+        }
+    }
+
+    /// Add a redirect rule to the route-level redirect table, such that requests to the `from` path,
+    /// regardless of the request method, will be redirected to the `to` location without going
+    /// through a handler. The query string of the request will be carried over to the new location.
+    ///
+    /// The `to` location can be an internal path or an absolute URL, and the latter is only allowed
+    /// if its host has been added to the allowlist with `ServerConfig::allow_redirect_host`. The
+    /// `status` must be one of 301, 302, 303, 307 or 308, and note that only 307 and 308 will keep
+    /// the request method and body as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.redirect("/login", "/account/login", 302);
+    /// ```
+    fn redirect(&mut self, from: &str, to: &str, status: u16) -> &mut dyn Router {
+        self.set_redirect(from, to, status);
+        self
+    }
+
+    /// Mount the sub-router under the path prefix, such that a feature module can build its own
+    /// `Route` independently, e.g. with its own routes, static folders, white-lists and black-lists,
+    /// and even its own mounted routers. The sub-router only sees the remainder of the request uri,
+    /// i.e. a route `/users` in the sub-router mounted at `/api/v1` will be matched by the request
+    /// to `/api/v1/users`, and the handler can get the mount path via `Request::base_uri`.
+    ///
+    /// The longest mount path is matched first, and if nothing matches in the sub-router, we will
+    /// keep searching in the routes of this router. The mount path is matched case-insensitively,
+    /// unless the sub-router is set to be case sensitive before it's mounted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut api = Route::new();
+    /// api.get(RequestPath::Explicit("/users"), simple_handler);
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.mount("/api/v1", api);
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send(&format!("{} is mounted at {}", req.local_uri(), req.base_uri()));
+    /// }
+    /// ```
+    fn mount(&mut self, prefix: &str, router: Route) -> &mut dyn Router {
+        self.set_mount(prefix, router);
+        self
+    }
+
+    /// Serve the requests to the matching host with the given router, instead of the routes of this
+    /// router, which serve as the default for the requests to any other hosts. The host pattern can
+    /// be the exact host name, e.g. `api.example.com`, or contain the `*` matching any subdomain
+    /// label, e.g. `*.example.com`, or the `:param` whose value is saved as a request param, e.g.
+    /// `:tenant.example.com`. The host names are matched case-insensitively without the port, the
+    /// exact ones first, then the patterns in the order they're added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut tenant = Route::new();
+    /// tenant.get(RequestPath::Explicit("/"), simple_handler);
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.host(":tenant.example.com", tenant);
+    ///
+    /// pub fn simple_handler(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send(&format!("Welcome, {}", req.param("tenant").unwrap_or_default()));
+    /// }
+    /// ```
+    fn host(&mut self, pattern: &str, router: Route) -> &mut dyn Router {
+        self.set_host(pattern, router);
+        self
+    }
+
+    /// Add the route which is only picked if the request meets the guard, and more guarded routes
+    /// can be added to the same path, which are checked in the order they're added, while a route
+    /// added without the guard serves as the fallback. See `RequestGuard` for the conditions.
+    fn guarded(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        guard: RequestGuard,
+        callback: Callback,
+    ) -> &mut dyn Router {
+        self.add(method, uri, RouteHandler::guarded(guard, callback));
+        self
+    }
+
+    /// Name the route added right before, such that its url can be built with `Route::url_for`.
+    /// Only the `Explicit` and `ExplicitWithParams` routes can be named, and the names of the routes
+    /// in a mounted router are visible from the router it's mounted to.
+    fn name(&mut self, name: &str) -> &mut dyn Router {
+        self.set_name(name);
+        self
+    }
+
+    /// Add the route which is only served if the request is allowed by the policy, otherwise the
+    /// response is decided by the policy, see `AuthDecision`. The policy is checked after the
+    /// policies of the routers the route is in.
+    fn authorized(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        policy: AuthPolicy,
+        callback: Callback,
+    ) -> &mut dyn Router {
+        self.add(method, uri, RouteHandler::authorized(policy, callback));
+        self
+    }
+
+    /// Set the policy guarding all the routes of this router, which is checked before the policies
+    /// of the routes, and also applies to the routers mounted to it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut admin = Route::new();
+    /// admin.policy(signed_in).get(RequestPath::Explicit("/"), dashboard);
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.mount("/admin", admin);
+    ///
+    /// pub fn signed_in(req: &Request) -> AuthDecision {
+    ///     match req.cookie("user") {
+    ///         Some(user) => AuthDecision::Allow(Some(user)),
+    ///         None => AuthDecision::Redirect(String::from("/login")),
+    ///     }
+    /// }
+    ///
+    /// pub fn dashboard(req: &Box<Request>, resp: &mut Box<Response>) {
+    ///     resp.send(&format!("Hello, {}", req.principal().unwrap_or_default()));
+    /// }
+    /// ```
+    fn policy(&mut self, policy: AuthPolicy) -> &mut dyn Router {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Add the routes of the resource actions provided by the controller, including the nested
+    /// resources, see `ResourceController` for the paths and the methods of the actions.
+    fn resource(&mut self, path: &str, controller: &dyn ResourceController) -> &mut dyn Router {
+        self.set_resource(path, controller);
+        self
+    }
+}
+
+pub(crate) trait RouteSeeker {
+    fn seek(
+        method: &REST,
+        host: &str,
+        uri: &str,
+        tx: channel::Sender<(RouteHandler, HashMap<String, String>)>,
+    );
+    fn seek_sync(method: &REST, host: &str, uri: &str) -> (RouteHandler, HashMap<String, String>);
+}
+
+impl RouteSeeker for Route {
+    fn seek(
+        method: &REST,
+        host: &str,
+        uri: &str,
+        tx: channel::Sender<(RouteHandler, HashMap<String, String>)>,
+    ) {
+        if let Err(e) = tx.send(Self::seek_sync(method, host, uri)) {
+            debug::print("Unable to find the route handler", InfoLevel::Error);
+        }
+    }
+
+    fn seek_sync(method: &REST, host: &str, uri: &str) -> (RouteHandler, HashMap<String, String>) {
+        let host = normalize_host(host);
+
+        // the cached routes are dropped once the changes made since are published
+        publish(&instance::current().router);
+
+        if let Some(hit) = route_cache(|cache| cache.get(method, &host, uri)) {
+            return hit;
+        }
+
+        // the snapshot stays valid even if the routes are changed in the meantime
+        let snapshot = Route::read();
+        let policy = PathOptions::current().trailing_slash;
+
+        snapshot.with(|r| {
+            let mut params = HashMap::new();
+            let result = r.seek_slashed(method, &host, uri, &mut params, policy);
+
+            // only the routes to the callbacks and the redirects are cached, since the static files
+            // may come and go without us knowing, and the misses could flood the cache
+            if result.0.is_some() || result.2.is_some() || result.4.is_some() {
+                // the entry can be shared by the uri in any case, if it resolves to the same route
+                let mut lower_params = HashMap::new();
+                let caseless = params.is_empty()
+                    && uri.chars().any(char::is_uppercase)
+                    && r.seek_slashed(
+                        method,
+                        &host,
+                        &uri.to_lowercase(),
+                        &mut lower_params,
+                        policy,
+                    )
+                    .same_route(&result)
+                    && lower_params.is_empty();
+
+                // don't cache the lookup if the routes have changed since the snapshot is taken
+                route_cache(|cache| {
+                    if cache.version == snapshot.0 {
+                        cache.put(method, &host, uri, &result, &params, caseless);
+                    }
+                });
+            }
+
+            (result, params)
+        })
+    }
+}
+
+/// The route handler, holding: 1) the callback; 2) the static file path; 3) the redirect rule;
+/// 4) the path of the router it's mounted under, if any; 5) the guarded callbacks; 6) the
+/// authorization policies, from the outermost router to the route itself.
+pub(crate) struct RouteHandler(
+    Option<Callback>,
+    Option<PathBuf>,
+    Option<Arc<Redirect>>,
+    Option<Arc<String>>,
+    Option<Arc<Vec<(RequestGuard, Callback)>>>,
+    Option<Arc<Vec<AuthPolicy>>>,
+);
+
+impl RouteHandler {
     pub(crate) fn new(cb: Option<Callback>, path: Option<PathBuf>) -> Self {
-        RouteHandler(cb, path)
+        RouteHandler(cb, path, None, None, None, None)
+    }
+
+    pub(crate) fn guarded(guard: RequestGuard, cb: Callback) -> Self {
+        RouteHandler(
+            None,
+            None,
+            None,
+            None,
+            Some(Arc::new(vec![(guard, cb)])),
+            None,
+        )
+    }
+
+    fn redirect(from: &str, to: &str, status: u16) -> Self {
+        let redirect = Redirect {
+            from: from.to_owned(),
+            location: to.to_owned(),
+            status,
+        };
+
+        RouteHandler(None, None, Some(Arc::new(redirect)), None, None, None)
+    }
+
+    pub(crate) fn authorized(policy: AuthPolicy, cb: Callback) -> Self {
+        RouteHandler(
+            Some(cb),
+            None,
+            None,
+            None,
+            None,
+            Some(Arc::new(vec![policy])),
+        )
+    }
+
+    /// Put the policy of the router in front of the ones already found for the route.
+    fn require(&mut self, policy: AuthPolicy) {
+        let mut policies = vec![policy];
+        if let Some(own) = self.5.as_ref() {
+            policies.extend(own.iter());
+        }
+
+        self.5 = Some(Arc::new(policies));
+    }
+
+    /// Check the request against the authorization policies of the route, and if turned down, the
+    /// response has been set up and the handler shall not be executed.
+    pub(crate) fn authorize(&self, req: &mut Box<Request>, resp: &mut Box<Response>) -> bool {
+        match self.5.as_ref() {
+            Some(policies) => auth::check(policies, req, resp),
+            None => true,
+        }
+    }
+
+    pub(crate) fn path(&self) -> Option<&PathBuf> {
+        self.1.as_ref()
+    }
+
+    pub(crate) fn base_uri(&self) -> Option<&str> {
+        self.3.as_ref().map(|base| base.as_str())
+    }
+
+    pub(crate) fn is_some(&self) -> bool {
+        self.0.is_some() || self.1.is_some() || self.2.is_some() || self.4.is_some()
+    }
+
+    pub(crate) fn is_none(&self) -> bool {
+        !self.is_some()
+    }
+
+    /// If the route is served by a callback, with or without the guards.
+    fn has_callback(&self) -> bool {
+        self.0.is_some() || self.4.is_some()
+    }
+
+    /// If both handlers are resolved from the same route.
+    fn same_route(&self, other: &RouteHandler) -> bool {
+        fn same<T>(a: &Option<Arc<T>>, b: &Option<Arc<T>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        self.0.map(|cb| cb as usize) == other.0.map(|cb| cb as usize)
+            && self.1 == other.1
+            && same(&self.2, &other.2)
+            && same(&self.3, &other.3)
+            && same(&self.4, &other.4)
+            && same(&self.5, &other.5)
+    }
+
+    /// Merge the route to the same path, which is only allowed for the guarded routes, and at most
+    /// one route without the guard, which serves as the fallback if none of the guards are met.
+    pub(crate) fn merge(&mut self, other: RouteHandler) -> bool {
+        if self.1.is_some() || self.2.is_some() || other.1.is_some() || other.2.is_some() {
+            return false;
+        }
+
+        // the policies of a route apply to all its handlers, so they can't be merged either
+        if self.5.is_some() || other.5.is_some() {
+            return false;
+        }
+
+        match other.4 {
+            Some(guards) => {
+                let mut merged = self.4.as_ref().map_or_else(Vec::new, |own| own.to_vec());
+                merged.extend(guards.iter().cloned());
+                self.4 = Some(Arc::new(merged));
+            }
+            None if self.0.is_none() => self.0 = other.0,
+            None => return false,
+        }
+
+        true
+    }
+
+    pub(crate) fn execute(&mut self, req: &Box<Request>, resp: &mut Box<Response>) {
+        assert!(self.is_some());
+
+        if let Some(guards) = self.4.take() {
+            let mut status = 404;
+
+            for (guard, cb) in guards.iter() {
+                match guard.check(req) {
+                    Ok(()) => {
+                        cb(req, resp);
+                        return;
+                    }
+                    // the guard failing at the later check is closer to a match, see `RequestGuard`
+                    Err(code) => status = status.max(code),
+                }
+            }
+
+            if self.0.is_none() {
+                resp.status(status);
+                return;
+            }
+        }
+
+        if let Some(cb) = self.0.take() {
+            cb(req, resp);
+            return;
+        }
+
+        if let Some(path) = self.1.take() {
+            if path.is_dir() {
+                send_listing(req, resp, &path);
+            } else {
+                resp.send_file_from_path_async(path);
+            }
+
+            return;
+        }
+
+        if let Some(redirect) = self.2.take() {
+            resp.redirect_with(&redirect.location, redirect.status, true);
+        }
+    }
+
+    fn update_handler(mut handler: RouteHandler, file_name: &str) -> RouteHandler {
+        if file_name.is_empty() {
+            return handler;
+        }
+
+        if let Some(mut p) = handler.1.take() {
+            if handler.0.is_some() {
+                // the callback always wins when executed, so the static path is never served
+                debug::print(
+                    &format!(
+                        "Router error: callback and static files are found for this route, \
+                         the static path {} is ignored.",
+                        p.display()
+                    ),
+                    InfoLevel::Warning,
+                );
+
+                return handler;
+            }
+
+            p.push(file_name);
+            handler.1.replace(p);
+        };
+
+        handler
+    }
+}
+
+impl Default for RouteHandler {
+    fn default() -> Self {
+        RouteHandler(None, None, None, None, None, None)
+    }
+}
+
+impl Clone for RouteHandler {
+    fn clone(&self) -> Self {
+        RouteHandler(
+            self.0,
+            self.1.clone(),
+            self.2.clone(),
+            self.3.clone(),
+            self.4.clone(),
+            self.5.clone(),
+        )
+    }
+}
+
+/// A redirect rule from the route-level redirect table, which is served without a handler.
+pub(crate) struct Redirect {
+    from: String,
+    location: String,
+    status: u16,
+}
+
+/// The router of a server instance, with the lookup cache and the settings on how the routes are
+/// added and looked up.
+pub(crate) struct RouterState {
+    store: RouteStore,
+    cache: Mutex<RouteCache>,
+    strict: AtomicBool,
+    path_options: AtomicU8,
+}
+
+impl RouterState {
+    pub(crate) fn new() -> Self {
+        RouterState {
+            store: RouteStore::new(),
+            cache: Mutex::new(RouteCache::new(0)),
+            strict: AtomicBool::new(false),
+            path_options: AtomicU8::new(PathOptions::DEFAULT),
+        }
+    }
+}
+
+/// The live router, published as the immutable snapshots: a lookup holds the `Arc` of the snapshot
+/// it started with till it's done, while the changes are made to a draft copy of the router, which
+/// is swapped in at once by the next lookup, such that the lookups never wait for the changes, and
+/// the other way around. The changes made in a row, e.g. adding the routes before the server is
+/// launched, share the same draft, hence the router is only copied once for all of them. Each time
+/// a draft is published, the version of the router is bumped.
+struct RouteStore {
+    current: RwLock<(u64, Arc<Route>)>,
+    draft: Mutex<Option<Route>>,
+    pending: AtomicBool,
+}
+
+impl RouteStore {
+    fn new() -> Self {
+        RouteStore {
+            current: RwLock::new((0, Arc::new(Route::new()))),
+            draft: Mutex::new(None),
+            pending: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Publish the draft of the router, if there's one, such that the lookups from now on are made
+/// with the changes.
+fn publish(state: &RouterState) {
+    if !state.store.pending.load(Ordering::Acquire) {
+        return;
+    }
+
+    let mut draft = state.store.draft.lock();
+    let route = match draft.take() {
+        Some(route) => route,
+        // published by another lookup already
+        None => return,
+    };
+
+    state.store.pending.store(false, Ordering::Release);
+
+    let by_host = route.has_hosts();
+    let next = Arc::new(route);
+
+    let version = {
+        let mut current = state.store.current.write();
+        let version = current.0 + 1;
+        *current = (version, next);
+        version
+    };
+
+    // the routes may have changed, so drop the cached routes, and only the lookups made with the
+    // new version can be cached from now on
+    let mut cache = state.cache.lock();
+    cache.clear();
+    cache.by_host = by_host;
+    cache.version = version;
+}
+
+/// The snapshot of the router at the time of the lookup, holding: 1) the version of the router;
+/// 2) the router itself.
+#[doc(hidden)]
+struct RouteSnapshot(u64, Arc<Route>);
+
+impl RouteSnapshot {
+    fn checkout() -> Self {
+        let instance = instance::current();
+        publish(&instance.router);
+
+        let current = instance.router.store.current.read();
+        RouteSnapshot(current.0, Arc::clone(&current.1))
+    }
+
+    fn with<T, F: FnOnce(&Route) -> T>(&self, f: F) -> T {
+        f(&self.1)
+    }
+}
+
+/// The router guard struct, holding the instance whose router is to be changed. The changes are
+/// made to the draft of the router, with the lock keeping the other writers out, and the draft is
+/// then published by the next lookup, see `RouteStore`.
+#[doc(hidden)]
+struct RouteGuard(Arc<instance::Instance>);
+
+impl RouteGuard {
+    fn checkout() -> Self {
+        RouteGuard(instance::current())
+    }
+
+    fn with<T, F: FnOnce(&mut Route) -> T>(&mut self, f: F) -> T {
+        let store = &self.0.router.store;
+        let mut draft = store.draft.lock();
+
+        let route = draft.get_or_insert_with(|| store.current.read().1.as_ref().clone());
+        let result = f(route);
+
+        store.pending.store(true, Ordering::Release);
+        result
+    }
+}
+
+/// The statistics of the route lookup cache, which can be obtained via
+/// `ServerConfig::route_cache_stats`.
+#[derive(Clone, Debug, Default)]
+pub struct RouteCacheStats {
+    /// The number of route lookups served from the cache.
+    pub hits: u64,
+    /// The number of route lookups not found in the cache.
+    pub misses: u64,
+    /// The number of routes removed from the cache to make room for new routes.
+    pub evictions: u64,
+    /// The number of times the cache is dropped because the routes have changed.
+    pub invalidations: u64,
+    /// The number of routes currently in the cache.
+    pub entries: usize,
+    /// The max number of routes the cache can hold, and 0 means the cache is off.
+    pub capacity: usize,
+}
+
+impl RouteCacheStats {
+    /// The ratio of the lookups served from the cache, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.hits as f64 / total as f64
+    }
+}
+
+/// The LRU cache of the route lookups keyed by the method, the host and the request uri, where the
+/// `order` map keeps the entries sorted by the last time they're used. The host is only a part of
+/// the key if any host routes are registered, and the uri is lowercased if the route it resolves
+/// to is found in any case, i.e. the case-insensitive explicit routes and the redirects.
+struct RouteCache {
+    entries: HashMap<(REST, String, String), CacheEntry>,
+    order: BTreeMap<u64, (REST, String, String)>,
+    tick: u64,
+    by_host: bool,
+    version: u64,
+    stats: RouteCacheStats,
+}
+
+struct CacheEntry {
+    route: CachedRoute,
+    tick: u64,
+    caseless: bool,
+}
+
+impl RouteCache {
+    fn new(capacity: usize) -> Self {
+        RouteCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            by_host: false,
+            version: 0,
+            stats: RouteCacheStats {
+                capacity,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get(&mut self, method: &REST, host: &str, uri: &str) -> Option<CachedRoute> {
+        if self.stats.capacity == 0 {
+            return None;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+
+        let mut key = self.key(method, host, uri, false);
+        if !self.entries.contains_key(&key) && uri.chars().any(char::is_uppercase) {
+            key = self.key(method, host, uri, true);
+        }
+
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.caseless || key.2 == uri => {
+                if let Some(key) = self.order.remove(&entry.tick) {
+                    self.order.insert(tick, key);
+                }
+
+                entry.tick = tick;
+                self.stats.hits += 1;
+
+                Some((entry.route.0.clone(), entry.route.1.clone()))
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn put(
+        &mut self,
+        method: &REST,
+        host: &str,
+        uri: &str,
+        handler: &RouteHandler,
+        params: &HashMap<String, String>,
+        caseless: bool,
+    ) {
+        if self.stats.capacity == 0 {
+            return;
+        }
+
+        let key = self.key(method, host, uri, caseless);
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.caseless |= caseless;
+            return;
+        }
+
+        while self.entries.len() >= self.stats.capacity {
+            let oldest = match self.order.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+
+            if let Some(victim) = self.order.remove(&oldest) {
+                self.entries.remove(&victim);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                route: (handler.clone(), params.clone()),
+                tick: self.tick,
+                caseless,
+            },
+        );
+
+        self.stats.entries = self.entries.len();
+    }
+
+    fn key(&self, method: &REST, host: &str, uri: &str, caseless: bool) -> (REST, String, String) {
+        let host = if self.by_host { host } else { "" };
+        let uri = if caseless {
+            uri.to_lowercase()
+        } else {
+            uri.to_owned()
+        };
+
+        (method.clone(), host.to_owned(), uri)
+    }
+
+    fn clear(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        self.entries.clear();
+        self.order.clear();
+        self.stats.entries = 0;
+        self.stats.invalidations += 1;
+    }
+}
+
+fn route_cache<T, F: FnOnce(&mut RouteCache) -> T>(f: F) -> T {
+    f(&mut instance::current().router.cache.lock())
+}
+
+/// Turn on the route lookup cache with the max number of routes it can hold, or turn it off if
+/// the `capacity` is 0. The cached routes are dropped, but the counters are kept.
+pub(crate) fn init_cache(capacity: usize) {
+    route_cache(|cache| {
+        let stats = cache.stats.clone();
+        let (by_host, version) = (cache.by_host, cache.version);
+
+        *cache = RouteCache::new(capacity);
+        cache.by_host = by_host;
+        cache.version = version;
+        cache.stats = RouteCacheStats {
+            entries: 0,
+            capacity,
+            ..stats
+        };
+    })
+}
+
+pub(crate) fn clear_cache() {
+    route_cache(|cache| cache.clear())
+}
+
+pub(crate) fn cache_stats() -> RouteCacheStats {
+    route_cache(|cache| cache.stats.clone())
+}
+
+fn search_wildcard_router(routes: &[RegexRoute], uri: &str) -> RouteHandler {
+    let mut result = RouteHandler::default();
+    for route in routes.iter() {
+        if route.regex.is_match(&uri) {
+            result = route.handler.clone();
+            break;
+        }
     }
 
-    pub(crate) fn is_some(&self) -> bool {
-        self.0.is_some() || self.1.is_some()
+    result
+}
+
+fn search_params_router(
+    head: &RouteTrie,
+    uri: &str,
+    params: &mut HashMap<String, String>,
+) -> RouteHandler {
+    // the trailing slash is kept as an empty segment, see `RouteMap::params_fields`
+    let raw_segments: Vec<String> = uri
+        .trim_start_matches('/')
+        .split('/')
+        .map(String::from)
+        .collect();
+
+    params.reserve(raw_segments.len());
+    let result = RouteTrie::find(head, raw_segments.as_slice(), params);
+
+    params.shrink_to_fit();
+    result
+}
+
+fn search_static_router(path: &StaticLocRoute, raw_uri: &str) -> Result<RouteHandler, ()> {
+    // check if static path can be met, and quit if the request tries to break out of the folder
+    let file = match path.resolve(raw_uri) {
+        Some(f) => f,
+        None => return Err(()),
+    };
+
+    // only if the file exists
+    if !file.is_file() {
+        // call the fallback methods and keep searching
+        return Ok(RouteHandler::default());
     }
 
-    pub(crate) fn is_none(&self) -> bool {
-        self.0.is_none() && self.1.is_none()
+    if !path.check_access(&file, false) {
+        return Err(());
     }
 
-    pub(crate) fn execute(&mut self, req: &Box<Request>, resp: &mut Box<Response>) {
-        assert!(self.is_some());
+    Ok(RouteHandler::new(None, Some(file)))
+}
 
-        if let Some(cb) = self.0.take() {
-            cb(req, resp);
-            return;
-        }
+/// Normalize the request path in place before it's routed, see `PathOptions`. The trailing slash is
+/// kept for the router to decide unless it's ignored.
+pub(crate) fn normalize_path(path: &mut String) {
+    normalize_path_with(path, &PathOptions::current());
+}
 
-        if let Some(path) = self.1.take() {
-            resp.send_file_from_path_async(path);
+fn normalize_path_with(path: &mut String, options: &PathOptions) {
+    let needs_rebuild = (options.merge_slashes && path.contains("//"))
+        || (options.resolve_dots && path.split('/').any(|seg| seg == "." || seg == ".."));
+
+    if needs_rebuild {
+        let mut segments: Vec<&str> = Vec::new();
+        let mut trailing = false;
+
+        for seg in path.split('/').skip(1) {
+            trailing = true;
+
+            match seg {
+                "" if options.merge_slashes => {}
+                "." if options.resolve_dots => {}
+                ".." if options.resolve_dots => {
+                    segments.pop();
+                }
+                _ => {
+                    segments.push(seg);
+                    trailing = false;
+                }
+            }
         }
-    }
 
-    fn update_handler(mut handler: RouteHandler, file_name: &str) -> RouteHandler {
-        if file_name.is_empty() {
-            return handler;
+        let mut normalized = String::with_capacity(path.len());
+        for seg in segments {
+            normalized.push('/');
+            normalized.push_str(seg);
         }
 
-        if let Some(mut p) = handler.1.take() {
-            assert!(
-                handler.0.is_none(),
-                "Router error: callback and static files are found for this route ..."
-            );
+        if trailing || normalized.is_empty() {
+            normalized.push('/');
+        }
 
-            p.push(file_name);
-            handler.1.replace(p);
-        };
+        *path = normalized;
+    }
 
-        handler
+    if options.trailing_slash == TrailingSlash::Ignore {
+        let size = trim_trailing_slash(path).len();
+        path.truncate(size);
     }
 }
 
-impl Default for RouteHandler {
-    fn default() -> Self {
-        RouteHandler(None, None)
+/// The path without the trailing slash, except for the root.
+fn trim_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
     }
 }
 
-impl Clone for RouteHandler {
-    fn clone(&self) -> Self {
-        RouteHandler(self.0, self.1.clone())
+/// The host name in lower case, without the port or the trailing dot, e.g. `Example.com.:8080` is
+/// normalized to `example.com`.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+
+    let name = if host.starts_with('[') {
+        // the IPv6 address
+        match host.find(']') {
+            Some(pos) => &host[..=pos],
+            None => host,
+        }
+    } else {
+        match host.rfind(':') {
+            Some(pos) => &host[..pos],
+            None => host,
+        }
+    };
+
+    name.trim_end_matches('.').to_lowercase()
+}
+
+/// Handle the route conflicting with the one added before, see `Route::strict_routes`.
+fn report_conflict(route: &str) {
+    if instance::current().router.strict.load(Ordering::Acquire) {
+        panic!("Route conflict: {} has been added before.", route);
     }
+
+    debug::print(
+        &format!(
+            "Route conflict: {} has been added before, and the new one is ignored.",
+            route
+        ),
+        InfoLevel::Warning,
+    );
 }
 
-/// The router guard struct, holding: 1) (mutable) reference to the underlying route; 2) The reader
-/// counter reference; 3) if guarding a read access, or not.
-#[doc(hidden)]
-struct RouteGuard<'a>(&'a mut Route, &'a AtomicUsize, bool);
-
-impl<'a> RouteGuard<'a> {
-    fn checkout(is_reader: bool) -> Self {
-        // prepare the base reference
-        let r = unsafe { ROUTER.as_mut().unwrap() };
-
-        if is_reader {
-            // initial guess, doesn't really matter if we have to compete for the lock
-            let mut curr = r.1.load(Ordering::Relaxed);
-
-            // waiting for the write lock to release
-            while let Err(old) =
-                r.1.compare_exchange(curr, curr + 1, Ordering::Acquire, Ordering::Relaxed)
-            {
-                let count = if old < 1 {
-                    // a writer holds the lock, wait till it releases it
-                    curr = 1;
-                    16
-                } else {
-                    // otherwise we're in readers mode, try to grab the lock before it goes away to
-                    // a contentious writer.
-                    curr = old;
-                    4
-                };
+/// Fill the params into the route path, see `Route::url_for`.
+fn build_url(template: &str, params: &[(&str, &str)]) -> Option<String> {
+    let mut url = String::with_capacity(template.len());
+    let mut used = HashSet::new();
+
+    // the fields are in the reversed order
+    for field in RouteMap::params_parser(template, true).iter().rev() {
+        if !field.is_param() {
+            if field.is_pattern() {
+                debug::print(
+                    &format!(
+                        "Unable to build the url with a glob pattern segment: {}",
+                        template
+                    ),
+                    InfoLevel::Warning,
+                );
 
-                // take a break: if a writer has the lock, wait longer to check again.
-                cpu_relax(count);
+                return None;
             }
-        } else {
-            // keep checking until all readers have released and no other writer is holding the lock
-            while r
-                .1
-                .compare_exchange(1, 0, Ordering::SeqCst, Ordering::Relaxed)
-                .is_err()
-            {
-                cpu_relax(8);
+
+            url.push('/');
+            url.push_str(field.name());
+            continue;
+        }
+
+        let value = match params.iter().find(|(key, _)| *key == field.name()) {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                used.insert(*key);
+                value
+            }
+            _ => {
+                debug::print(
+                    &format!(
+                        "Missing the param `{}` for the route: {}",
+                        field.name(),
+                        template
+                    ),
+                    InfoLevel::Warning,
+                );
+
+                return None;
             }
+        };
+
+        if field.is_catch_all() {
+            url.push('/');
+            url.push_str(&percent_encode(value.trim_matches('/'), true));
+            continue;
         }
 
-        RouteGuard(&mut r.0, &r.1, is_reader)
-    }
+        if !field.accepts(value) {
+            debug::print(
+                &format!(
+                    "The param `{}` doesn't match the route validation: {}",
+                    field.name(),
+                    value
+                ),
+                InfoLevel::Warning,
+            );
 
-    fn with<T, F: FnOnce(&mut Route) -> T>(&mut self, f: F) -> T {
-        f(self.0)
+            return None;
+        }
+
+        url.push('/');
+        url.push_str(&percent_encode(value, false));
     }
-}
 
-impl<'a> Drop for RouteGuard<'a> {
-    fn drop(&mut self) {
-        if self.2 {
-            // this is a reader guard
-            self.1.fetch_sub(1, Ordering::Release);
-        } else {
-            // this is a writer guard
-            self.1.store(1, Ordering::SeqCst);
-        }
+    if url.is_empty() {
+        url.push('/');
     }
-}
 
-pub(crate) fn drop_statics() {
-    unsafe {
-        ptr::drop_in_place(&mut ROUTER as *mut StaticStore<(Route, AtomicUsize)>);
-        ptr::drop_in_place(
-            &mut ROUTE_CACHE as *mut StaticStore<HashMap<(REST, String), RouteHandler>>,
-        );
+    let query: Vec<String> = params
+        .iter()
+        .filter(|(key, _)| !key.is_empty() && !used.contains(key))
+        .map(|(key, value)| [percent_encode(key, false), percent_encode(value, false)].join("="))
+        .collect();
+
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&query.join("&"));
     }
+
+    Some(url)
 }
 
-fn search_wildcard_router(routes: &HashMap<String, RegexRoute>, uri: &str) -> RouteHandler {
-    let mut result = RouteHandler(None, None);
-    for (_, route) in routes.iter() {
-        if route.regex.is_match(&uri) {
-            result = route.handler.clone();
-            break;
-        }
+/// Decode the `%XX` escapes in the path segment, or `None` if it's malformed or not valid utf-8.
+fn percent_decode(raw: &str) -> Option<String> {
+    if !raw.contains('%') {
+        return Some(raw.to_owned());
     }
 
-    result
-}
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
 
-fn search_params_router(
-    head: &RouteTrie,
-    uri: &str,
-    params: &mut HashMap<String, String>,
-) -> RouteHandler {
-    let raw_segments: Vec<String> = uri.trim_matches('/').split('/').map(String::from).collect();
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = raw.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
 
-    params.reserve(raw_segments.len());
-    let result = RouteTrie::find(head, raw_segments.as_slice(), params);
+    let result = String::from_utf8(decoded).ok()?;
+    if result.contains('\0') {
+        return None;
+    }
 
-    params.shrink_to_fit();
-    result
+    Some(result)
 }
 
-fn search_static_router(path: &StaticLocRoute, raw_uri: &str) -> Result<RouteHandler, ()> {
-    // check if static path can be met
-    let mut normalized_uri = path.location.clone();
-    normalized_uri.push(raw_uri.trim_start_matches(|x| x == '.' || x == '/'));
+fn send_listing(req: &Box<Request>, resp: &mut Box<Response>, dir: &PathBuf) {
+    let mut entries: Vec<listing::Entry> = match fs::read_dir(dir) {
+        Ok(dir_entries) => dir_entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let meta = fs::metadata(entry.path()).ok()?;
+                let name = entry.file_name().into_string().ok()?;
 
-    let meta = match fs::metadata(&normalized_uri) {
-        Ok(m) => m,
-        _ => return Ok(RouteHandler::default()), // call the fallback methods and keep searching
-    };
+                // the files and folders not allowed to be served are not listed
+                if !Route::static_access(&entry.path(), meta.is_dir()) {
+                    return None;
+                }
 
-    // only if the file exists
-    if meta.is_file() {
-        if !path.check_access(&normalized_uri) {
-            return Err(());
+                Some(listing::Entry::new(name, meta.is_dir(), meta.len()))
+            })
+            .collect(),
+        Err(e) => {
+            debug::print(
+                &format!("Unable to read the folder for listing: {}", e),
+                InfoLevel::Warning,
+            );
+
+            resp.status(404);
+            return;
         }
+    };
 
-        //        if !path.white_list.is_empty()
-        //            && (!ext.is_empty() && !path.white_list.contains(ext))
-        //            && (!file.is_empty() && !path.white_list.contains(file))
-        //        {
-        //            return Err(());
-        //        }
-        //
-        //        if !path.black_list.is_empty()
-        //            && ((!ext.is_empty() && path.black_list.contains(ext))
-        //                || (!file.is_empty() && path.black_list.contains(file)))
-        //        {
-        //            return Err(());
-        //        }
+    entries.sort();
+    resp.header("Vary", "Accept", false);
 
-        return Ok(RouteHandler(None, Some(normalized_uri)));
+    match req.accepts(&["text/html", "application/json"]) {
+        Some(ref t) if t == "application/json" => {
+            resp.set_content_type("application/json");
+            resp.send(&listing::json(&entries));
+        }
+        _ => {
+            resp.set_content_type(&ConnMetadata::with_charset(String::from("text/html")));
+            resp.send(&listing::html(&req.uri, &entries));
+        }
     }
-
-    Ok(RouteHandler::default())
 }
 
 #[cfg(test)]
 mod route_test {
-    use super::{Field, RouteMap};
+    use super::{
+        build_url, normalize_host, normalize_path_with, percent_decode, Field, Mount, PathOptions,
+        RequestPath, Route, RouteCache, RouteHandler, RouteKind, RouteMap, Router, TrailingSlash,
+        REST,
+    };
+    use crate::core::auth::AuthDecision;
+    use crate::core::guard::RequestGuard;
+    use crate::core::http::{Request, Response};
+    use crate::core::instance;
+    use crate::hashbrown::HashMap;
     use regex::*;
+    use std::path::PathBuf;
 
     #[test]
     fn params_parser_test_one() {
-        let regex = Regex::new("a=[/]bdc").unwrap();
+        let regex = Regex::new("^(?:a=[/]bdc)$").unwrap();
         let base = vec![
             Field::new(String::from("check"), true, None),
             Field::new(String::from("this."), false, None),
@@ -1068,4 +3250,633 @@ mod route_test {
             num += 1;
         }
     }
+
+    #[test]
+    fn build_url_test() {
+        assert_eq!(
+            build_url(r"/user/:id(\d+)/posts", &[("id", "42")]),
+            Some(String::from("/user/42/posts"))
+        );
+        assert_eq!(
+            build_url("/search/:term", &[("term", "a b/c"), ("page", "2")]),
+            Some(String::from("/search/a%20b%2Fc?page=2"))
+        );
+        assert_eq!(
+            build_url("/files/*path", &[("path", "docs/a b.txt")]),
+            Some(String::from("/files/docs/a%20b.txt"))
+        );
+        assert_eq!(build_url("/", &[]), Some(String::from("/")));
+
+        assert_eq!(build_url(r"/user/:id(\d+)", &[("id", "abc")]), None);
+        assert_eq!(build_url(r"/user/:id(\d+)", &[("id", "12abc")]), None);
+        assert_eq!(build_url(r"/user/:id(\d|x)", &[("id", "1x")]), None);
+        assert_eq!(build_url("/user/:id", &[("name", "42")]), None);
+        assert_eq!(build_url("/assets/*.js", &[]), None);
+    }
+
+    #[test]
+    fn update_handler_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let handler = RouteHandler::update_handler(
+            RouteHandler::new(None, Some(PathBuf::from("/static"))),
+            "index.html",
+        );
+        assert_eq!(handler.path(), Some(&PathBuf::from("/static/index.html")));
+
+        // the callback wins, and the static path is dropped
+        let handler = RouteHandler::update_handler(
+            RouteHandler::new(Some(one), Some(PathBuf::from("/static"))),
+            "index.html",
+        );
+        assert!(handler.0.is_some());
+        assert!(handler.path().is_none());
+    }
+
+    #[test]
+    fn wildcard_precedence_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+        fn two(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut map = RouteMap::new();
+        assert!(map.insert(
+            RequestPath::WildCard(r"^/files/\d+\.txt$"),
+            RouteHandler::new(Some(one), None)
+        ));
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams("/files/*path"),
+            RouteHandler::new(Some(two), None)
+        ));
+
+        // the catch-all wins over the more specific wildcard route
+        let mut params = HashMap::new();
+        assert!(map.seek_path("/files/12.txt", &mut params).is_some());
+        assert_eq!(params.get("path").map(String::as_str), Some("12.txt"));
+
+        // the wildcard route is tried once nothing in the trie matches
+        let mut map = RouteMap::new();
+        assert!(map.insert(
+            RequestPath::WildCard(r"^/files/\d+\.txt$"),
+            RouteHandler::new(Some(one), None)
+        ));
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams("/files/:id(\\d+)"),
+            RouteHandler::new(Some(two), None)
+        ));
+
+        // only the param route fills the `id` param
+        let mut params = HashMap::new();
+        assert!(map.seek_path("/files/12", &mut params).is_some());
+        assert_eq!(params.get("id").map(String::as_str), Some("12"));
+
+        let mut params = HashMap::new();
+        assert!(map.seek_path("/files/12.txt", &mut params).is_some());
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn params_regex_full_match_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut map = RouteMap::new();
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams(r"/user/:id(\d+)"),
+            RouteHandler::new(Some(one), None)
+        ));
+
+        let mut params = HashMap::new();
+        assert!(map.seek_path("/user/12", &mut params).is_some());
+        assert!(map.seek_path("/user/12abc", &mut params).is_none());
+        assert!(map.seek_path("/user/x12", &mut params).is_none());
+    }
+
+    #[test]
+    fn percent_decode_test() {
+        assert_eq!(percent_decode("a%20b.txt"), Some(String::from("a b.txt")));
+        assert_eq!(percent_decode("%2e%2E"), Some(String::from("..")));
+        assert_eq!(percent_decode("caf%C3%A9"), Some(String::from("caf\u{e9}")));
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("a%00"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+
+    #[test]
+    fn mount_strip_test() {
+        let mount = Mount {
+            prefix: String::from("/api/v1"),
+            case_sensitive: false,
+            route: Route::new(),
+        };
+
+        assert_eq!(mount.strip("/api/v1"), Some("/"));
+        assert_eq!(mount.strip("/API/v1/users"), Some("/users"));
+        assert_eq!(mount.strip("/api/v12"), None);
+        assert_eq!(mount.strip("/api"), None);
+    }
+
+    #[test]
+    fn auth_policy_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+        fn outer(_: &Request) -> AuthDecision {
+            AuthDecision::Allow(Some(String::from("outer")))
+        }
+        fn inner(_: &Request) -> AuthDecision {
+            AuthDecision::Forbidden
+        }
+
+        let mut admin = Route::new();
+        admin
+            .policy(outer)
+            .get(RequestPath::Explicit("/"), one)
+            .authorized(REST::GET, RequestPath::Explicit("/users"), inner, one);
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/"), one)
+            .mount("/admin", admin);
+
+        let mut params = HashMap::new();
+        assert!(route
+            .seek_route(&REST::GET, "", "/", &mut params)
+            .5
+            .is_none());
+
+        let found = route.seek_route(&REST::GET, "", "/admin", &mut params);
+        assert_eq!(found.5.map(|p| p.len()), Some(1));
+
+        // the policy of the router goes before the one of the route
+        let found = route.seek_route(&REST::GET, "", "/admin/users", &mut params);
+        let policies = found.5.unwrap();
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0](&Request::new()), outer(&Request::new()));
+        assert_eq!(policies[1](&Request::new()), AuthDecision::Forbidden);
+    }
+
+    #[test]
+    fn remove_route_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/Home"), one)
+            .get(RequestPath::ExplicitWithParams("/user/:id/posts"), one)
+            .get(RequestPath::ExplicitWithParams("/user/:id"), one)
+            .get(RequestPath::WildCard(r"/files/\w+"), one);
+        Router::case_sensitive(&mut route, false, Some(REST::GET));
+
+        let mut params = HashMap::new();
+        let snapshot = route.clone();
+
+        assert!(route.remove(&REST::GET, RequestPath::Explicit("/home")));
+        assert!(!route.remove(&REST::GET, RequestPath::Explicit("/home")));
+        assert!(!route.remove(&REST::POST, RequestPath::ExplicitWithParams("/user/:id")));
+        assert!(route.remove(
+            &REST::GET,
+            RequestPath::ExplicitWithParams("/user/:id/posts")
+        ));
+        assert!(route.remove(&REST::GET, RequestPath::WildCard(r"/files/\w+")));
+
+        assert!(route
+            .seek_route(&REST::GET, "", "/Home", &mut params)
+            .is_none());
+        assert!(route
+            .seek_route(&REST::GET, "", "/user/7/posts", &mut params)
+            .is_none());
+        assert!(route
+            .seek_route(&REST::GET, "", "/files/abc", &mut params)
+            .is_none());
+
+        // the sibling route on the same branch is kept
+        assert!(!route
+            .seek_route(&REST::GET, "", "/user/7", &mut params)
+            .is_none());
+
+        // the copy taken before the changes still serves all the routes
+        assert!(!snapshot
+            .seek_route(&REST::GET, "", "/home", &mut params)
+            .is_none());
+        assert!(!snapshot
+            .seek_route(&REST::GET, "", "/user/7/posts", &mut params)
+            .is_none());
+        assert!(!snapshot
+            .seek_route(&REST::GET, "", "/files/abc", &mut params)
+            .is_none());
+    }
+
+    #[test]
+    fn normalize_path_test() {
+        let normalize = |path: &str, options: &PathOptions| {
+            let mut path = path.to_owned();
+            normalize_path_with(&mut path, options);
+            path
+        };
+
+        let mut options = PathOptions::new();
+        assert_eq!(normalize("/", &options), "/");
+        assert_eq!(normalize("//api///users/", &options), "/api/users");
+        assert_eq!(normalize("/api/./v1/../users", &options), "/api/users");
+        assert_eq!(normalize("/../../etc/passwd", &options), "/etc/passwd");
+        assert_eq!(normalize("/api/..", &options), "/");
+
+        options.set_trailing_slash(TrailingSlash::Strict);
+        assert_eq!(normalize("/api//users/", &options), "/api/users/");
+        assert_eq!(normalize("/api/users/..", &options), "/api/");
+        assert_eq!(normalize("/api/users", &options), "/api/users");
+
+        options.set_merge_slashes(false);
+        options.set_resolve_dots(false);
+        assert_eq!(normalize("/api//./users/", &options), "/api//./users/");
+    }
+
+    #[test]
+    fn trailing_slash_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/Users"), one)
+            .get(RequestPath::Explicit("/Docs/"), one)
+            .get(RequestPath::ExplicitWithParams("/user/:id"), one)
+            .get(RequestPath::ExplicitWithParams("/post/:id/"), one);
+        Router::case_sensitive(&mut route, false, Some(REST::GET));
+
+        let mut params = HashMap::new();
+        let mut seek = |uri: &str, policy: TrailingSlash| {
+            params.clear();
+            let found = route.seek_slashed(&REST::GET, "", uri, &mut params, policy);
+            (found, params.get("id").cloned())
+        };
+
+        // the routes are matched exactly as they're added
+        assert!(seek("/users", TrailingSlash::Strict).0.has_callback());
+        assert!(seek("/docs/", TrailingSlash::Strict).0.has_callback());
+        assert!(seek("/post/7/", TrailingSlash::Strict).0.has_callback());
+        assert!(seek("/users/", TrailingSlash::Strict).0.is_none());
+        assert!(seek("/docs", TrailingSlash::Strict).0.is_none());
+        assert!(seek("/user/7/", TrailingSlash::Strict).0.is_none());
+        assert!(seek("/post/7", TrailingSlash::Strict).0.is_none());
+        assert!(seek("/", TrailingSlash::Strict).0.is_none());
+
+        // or with and without the trailing slash alike
+        assert!(seek("/users/", TrailingSlash::Ignore).0.has_callback());
+        assert!(seek("/docs", TrailingSlash::Ignore).0.has_callback());
+        assert_eq!(seek("/user/7/", TrailingSlash::Ignore).1, Some("7".into()));
+        assert_eq!(seek("/post/7", TrailingSlash::Ignore).1, Some("7".into()));
+
+        // the redirect keeps the case of the request, which is matched case-insensitively
+        for (uri, location) in [("/USERS/", "/USERS"), ("/DOCS", "/DOCS/")].iter() {
+            let (found, _) = seek(uri, TrailingSlash::Redirect);
+            let redirect = found.2.as_ref().unwrap();
+            assert!(!found.has_callback());
+            assert_eq!(
+                (redirect.location.as_str(), redirect.status),
+                (*location, 308)
+            );
+        }
+
+        assert!(seek("/nowhere/", TrailingSlash::Redirect).0.is_none());
+    }
+
+    #[test]
+    fn publish_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let instance = instance::Instance::new();
+        let _scope = instance::enter(&instance);
+        let version = Route::version();
+
+        // the changes in a row are made to the same draft, and published at once
+        for uri in ["/a", "/b", "/c"].iter() {
+            Route::insert_route(REST::GET, RequestPath::Explicit(uri), one);
+        }
+
+        assert_eq!(Route::version(), version + 1);
+        assert_eq!(Route::routes().len(), 3);
+
+        assert!(Route::remove_route(REST::GET, RequestPath::Explicit("/b")));
+        assert_eq!(Route::routes().len(), 2);
+        assert_eq!(Route::version(), version + 2);
+    }
+
+    #[test]
+    fn redirect_lookup_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/new"), one)
+            .redirect("/Old/", "/new", 308);
+
+        assert!(route.find_redirect("/old").is_some());
+        assert!(route.find_redirect("/OLD").is_some());
+        assert!(route.find_redirect("/older").is_none());
+
+        // only the exact source is matched once the routes are case-sensitive
+        route.case_sensitive(true, None);
+        assert!(route.find_redirect("/Old").is_some());
+        assert!(route.find_redirect("/old").is_none());
+    }
+
+    #[test]
+    fn route_conflict_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+        fn two(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut api = Route::new();
+        api.get(RequestPath::ExplicitWithParams("/user/:id"), one)
+            .name("user_show");
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/about"), one)
+            .get(RequestPath::Explicit("/ABOUT"), two)
+            .get(RequestPath::ExplicitWithParams("/post/:id"), one)
+            .get(RequestPath::ExplicitWithParams("/post/:pid"), two)
+            .post(RequestPath::Explicit("/about"), two)
+            .redirect("/old", "/about", 301)
+            .mount("/api", api);
+
+        let mut routes = Vec::new();
+        route.list(None, "", &mut routes);
+
+        let listed: Vec<(String, String, RouteKind)> = routes
+            .iter()
+            .map(|r| (r.method.to_string(), r.pattern.clone(), r.kind))
+            .collect();
+
+        assert_eq!(
+            listed,
+            vec![
+                (
+                    String::from("GET"),
+                    String::from("/about"),
+                    RouteKind::Explicit
+                ),
+                (
+                    String::from("GET"),
+                    String::from("/post/:id"),
+                    RouteKind::Params
+                ),
+                (
+                    String::from("POST"),
+                    String::from("/about"),
+                    RouteKind::Explicit
+                ),
+                (String::from("*"), String::from("/old"), RouteKind::Redirect),
+                (
+                    String::from("GET"),
+                    String::from("/api/user/:id"),
+                    RouteKind::Params
+                ),
+            ]
+        );
+
+        assert_eq!(routes[4].name, Some(String::from("user_show")));
+        assert_eq!(
+            routes[4].to_string(),
+            "GET     /api/user/:id (params, name: user_show)"
+        );
+
+        // the first route is kept
+        let mut params = HashMap::new();
+        assert!(route
+            .seek_route(&REST::GET, "", "/post/42", &mut params)
+            .is_some());
+        assert_eq!(params.get("id"), Some(&String::from("42")));
+        assert!(params.get("pid").is_none());
+    }
+
+    #[test]
+    fn guarded_route_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let guard = RequestGuard::new();
+        let mut map = RouteMap::new();
+
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams("/users/:id"),
+            RouteHandler::guarded(guard.clone(), one)
+        ));
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams("/users/:id"),
+            RouteHandler::guarded(guard.clone(), one)
+        ));
+        assert!(map.insert(
+            RequestPath::ExplicitWithParams("/users/:id"),
+            RouteHandler::new(Some(one), None)
+        ));
+
+        // only one fallback route, and the param names must match to share the path
+        assert!(!map.insert(
+            RequestPath::ExplicitWithParams("/users/:id"),
+            RouteHandler::new(Some(one), None)
+        ));
+        assert!(!map.insert(
+            RequestPath::ExplicitWithParams("/users/:uid"),
+            RouteHandler::guarded(guard.clone(), one)
+        ));
+
+        let handler = map.seek_path("/users/42", &mut HashMap::new());
+        assert!(handler.0.is_some());
+        assert_eq!(handler.4.map(|guards| guards.len()), Some(2));
+        assert_eq!(map.routes.iter().filter(|r| r.guarded).count(), 2);
+
+        // the route with the guards only is matched as well
+        assert!(map.insert(
+            RequestPath::Explicit("/report"),
+            RouteHandler::guarded(guard, one)
+        ));
+        assert!(map.seek_path("/report", &mut HashMap::new()).is_some());
+    }
+
+    #[test]
+    fn virtual_host_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        assert_eq!(normalize_host("API.Example.com.:8080"), "api.example.com");
+        assert_eq!(normalize_host("[::1]:8080"), "[::1]");
+
+        let mut api = Route::new();
+        api.get(RequestPath::Explicit("/users"), one);
+
+        let mut tenant = Route::new();
+        tenant.get(RequestPath::ExplicitWithParams("/users/:id"), one);
+
+        let mut route = Route::new();
+        route
+            .get(RequestPath::Explicit("/"), one)
+            .host(":tenant.example.com", tenant)
+            .host("API.example.com", api);
+
+        let seek = |host: &str, uri: &str| {
+            let mut params = HashMap::new();
+            let found = route
+                .seek_route(&REST::GET, host, uri, &mut params)
+                .is_some();
+            (found, params)
+        };
+
+        // the exact host goes first
+        assert!(seek("api.example.com", "/users").0);
+        assert!(!seek("api.example.com", "/").0);
+
+        let (found, params) = seek("acme.example.com", "/users/42");
+        assert!(found);
+        assert_eq!(params.get("tenant"), Some(&String::from("acme")));
+        assert_eq!(params.get("id"), Some(&String::from("42")));
+
+        // the other hosts fall back to the default routes
+        assert!(seek("a.b.example.com", "/").0);
+        assert!(seek("", "/").0);
+    }
+
+    #[test]
+    fn route_cache_test() {
+        let mut cache = RouteCache::new(2);
+        let params = HashMap::new();
+
+        cache.put(
+            &REST::GET,
+            "",
+            "/a",
+            &RouteHandler::default(),
+            &params,
+            false,
+        );
+        cache.put(
+            &REST::GET,
+            "",
+            "/b",
+            &RouteHandler::default(),
+            &params,
+            false,
+        );
+        assert!(cache.get(&REST::GET, "", "/a").is_some());
+        assert!(cache.get(&REST::POST, "", "/a").is_none());
+
+        // `/b` is the least recently used one
+        cache.put(
+            &REST::GET,
+            "",
+            "/c",
+            &RouteHandler::default(),
+            &params,
+            false,
+        );
+        assert!(cache.get(&REST::GET, "", "/b").is_none());
+        assert!(cache.get(&REST::GET, "", "/c").is_some());
+
+        let stats = cache.stats.clone();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 2, 1));
+        assert_eq!(stats.entries, 2);
+        assert!((stats.hit_rate() - 0.5).abs() < 1e-9);
+
+        cache.clear();
+        assert_eq!(cache.stats.entries, 0);
+        assert_eq!(cache.stats.invalidations, 1);
+    }
+
+    #[test]
+    fn route_cache_key_test() {
+        let mut cache = RouteCache::new(4);
+        let params = HashMap::new();
+
+        // without the host routes, the host is not a part of the key
+        cache.put(
+            &REST::GET,
+            "a.com",
+            "/a",
+            &RouteHandler::default(),
+            &params,
+            false,
+        );
+        assert!(cache.get(&REST::GET, "b.com", "/a").is_some());
+
+        cache.clear();
+        cache.by_host = true;
+        cache.put(
+            &REST::GET,
+            "a.com",
+            "/a",
+            &RouteHandler::default(),
+            &params,
+            false,
+        );
+        assert!(cache.get(&REST::GET, "b.com", "/a").is_none());
+        assert!(cache.get(&REST::GET, "a.com", "/a").is_some());
+
+        // only the caseless entries are shared by the uri in any case
+        cache.put(
+            &REST::GET,
+            "a.com",
+            "/Home",
+            &RouteHandler::default(),
+            &params,
+            true,
+        );
+        assert!(cache.get(&REST::GET, "a.com", "/home").is_some());
+        assert!(cache.get(&REST::GET, "a.com", "/HOME").is_some());
+        assert!(cache.get(&REST::GET, "a.com", "/A").is_none());
+        assert_eq!(cache.stats.entries, 2);
+    }
+
+    #[test]
+    fn case_insensitive_lookup_test() {
+        fn one(_: &Box<Request>, _: &mut Box<Response>) {}
+
+        let mut map = RouteMap::new();
+        assert!(map.insert(
+            RequestPath::Explicit("/Home"),
+            RouteHandler::new(Some(one), None)
+        ));
+
+        let mut params = HashMap::new();
+        assert!(map.seek_path("/home", &mut params).is_some());
+        assert!(map.seek_path("/HOME", &mut params).is_some());
+
+        map.case_sensitive(true);
+        assert!(map.seek_path("/HOME", &mut params).is_none());
+    }
+
+    #[test]
+    fn params_parser_test_catch_all() {
+        let test = RouteMap::params_parser("/files/*.json/*path", false);
+
+        assert_eq!(test.len(), 3);
+        assert_eq!(test[0], Field::catch_all(String::from("path")));
+        assert!(!test[1].is_catch_all());
+        assert_eq!(test[2], Field::new(String::from("files"), false, None));
+    }
+
+    #[test]
+    #[should_panic]
+    fn params_parser_test_catch_all_not_last() {
+        RouteMap::params_parser("/files/*path/edit", false);
+    }
+
+    #[test]
+    fn params_parser_test_typed() {
+        let test = RouteMap::params_parser("/user/:id<u64>/posts/:day<date>", true);
+
+        assert_eq!(test.len(), 4);
+        assert_eq!(test[2].name(), "id");
+        assert!(test[2].accepts("42"));
+        assert!(!test[2].accepts("abc"));
+        assert_eq!(test[0].name(), "day");
+        assert!(test[0].accepts("2024-02-29"));
+        assert!(!test[0].accepts("2023-02-29"));
+
+        assert_eq!(
+            build_url("/user/:id<u64>", &[("id", "42")]),
+            Some(String::from("/user/42"))
+        );
+        assert_eq!(build_url("/user/:id<u64>", &[("id", "-1")]), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn params_parser_test_unknown_type() {
+        RouteMap::params_parser("/user/:id<nope>", false);
+    }
 }
@@ -6,27 +6,45 @@ use std::time::Duration;
 
 use crate::channel;
 use crate::core::{
+    auth::AuthPolicy,
     config::{ServerConfig, ViewEngine, ViewEngineDefinition},
     conn::{self, StreamHandler},
+    guard::RequestGuard,
     http,
-    router::{self, Callback, RequestPath, Route, RouteHandler, Router, REST},
-    states::{AsyncController, ControlMessage, ServerStates},
+    instance::{self, Instance},
+    resource::ResourceController,
+    router::{self, Callback, RequestPath, Route, RouteHandler, Router, StaticOptions, REST},
+    states::{AsyncController, ControlMessage, ServerStates, SignalHook},
     stream::Stream,
 };
 use crate::hashbrown::HashMap;
 use crate::native_tls::TlsAcceptor;
+
+#[cfg(feature = "http2")]
+use crate::core::h2;
 use crate::support::{
+    cache,
     debug::{self, InfoLevel},
+    drain::{self, ShutdownStats},
+    limits::{self, ConnectionStats, Limits, Overflow},
     session::*,
     shared_pool, ThreadPool, TimeoutPolicy,
 };
 
+#[cfg(target_os = "linux")]
+use crate::support::signals;
+
 //TODO: Impl middlewear
 
-/// The server instance that represents and controls the underlying http-service.
+/// The server instance that represents and controls the underlying http-service. Each server owns
+/// its router, object and worker pools, and configs, such that multiple servers can run in the same
+/// process independently, e.g. on different ports. The static APIs, e.g. `Route::use_router`, are
+/// applied to the server they're called from, i.e. in its handlers, or otherwise to the server
+/// created last.
 pub struct HttpServer {
     config: ServerConfig,
     state: ServerStates,
+    instance: Arc<Instance>,
 }
 
 impl HttpServer {
@@ -40,11 +58,13 @@ impl HttpServer {
         HttpServer {
             config,
             state: ServerStates::new(),
+            instance: instance::claim(),
         }
     }
 
     /// `listen` will take 1 parameter for the port that the server will be monitoring at, aka
-    /// `127.0.0.1:port`. This function will block until the server is shut down.
+    /// `127.0.0.1:port`. This function will block until the server is shut down, and returns how
+    /// many requests in flight were dropped at the shutdown deadline, if any.
     ///
     /// # Examples
     ///
@@ -60,16 +80,17 @@ impl HttpServer {
     /// server.def_router(router);
     /// server.listen(8080);
     /// ```
-    pub fn listen(&mut self, port: u16) {
+    pub fn listen(&mut self, port: u16) -> ShutdownStats {
         // delegate the actual work to the more robust routine.
-        self.listen_and_serve(port, None);
+        self.listen_and_serve(port, None)
     }
 
     /// `listen_and_serve` will take 2 parameters: 1) the port that the server will be monitoring at,
     ///  or `127.0.0.1:port`; 2) the callback closure that will take an async-controller as input,
     /// and run in parallel to the current server instance for async operations.
     ///
-    /// This function will block until the server is shut down.
+    /// This function will block until the server is shut down, and returns the statistics of the
+    /// shutdown, e.g. the requests in flight dropped at the deadline.
     ///
     /// # Examples
     ///
@@ -92,10 +113,17 @@ impl HttpServer {
     ///     controller.send(ControlMessage::Terminate);
     /// }));
     /// ```
-    pub fn listen_and_serve(&mut self, port: u16, callback: Option<fn(AsyncController)>) {
+    pub fn listen_and_serve(
+        &mut self,
+        port: u16,
+        callback: Option<fn(AsyncController)>,
+    ) -> ShutdownStats {
         // initialize the debug service, which setup the debug level based on the environment variable
         debug::initialize();
 
+        // the server is run with the router, pools and configs of its own
+        let _scope = self.enter();
+
         // update the server state for the socket-host address
         self.state.set_port(port);
 
@@ -107,10 +135,13 @@ impl HttpServer {
 
         // obtain the control message courier service and start the callback
         let (control_handler, controller_tx) = if let Some(cb) = callback {
-            let sender = self.state.get_courier_sender();
+            let sender = self.state.get_courier_sender(&self.instance);
             let (tx, rx) = channel::bounded(1);
+            let instance = Arc::clone(&self.instance);
 
             let handler = thread::spawn(move || {
+                let _scope = instance::enter(&instance);
+
                 // wait for server to launch before it's ready to take control messages.
                 let _ = rx.recv();
                 cb(sender);
@@ -121,11 +152,29 @@ impl HttpServer {
             (None, None)
         };
 
+        // translate the signals into the control messages, if asked to
+        #[cfg(target_os = "linux")]
+        let handle_signals = self.state.get_signals();
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(on_usr1) = handle_signals {
+                signals::install(self.state.get_courier_sender(&self.instance), on_usr1);
+            }
+        }
+
         // launch the service, now this will block until the server is shutdown
         println!("Listening for connections on port {}", port);
 
         // actually mounting the server
-        self.launch_with(&listener, controller_tx);
+        let stats = self.launch_with(&listener, controller_tx);
+
+        #[cfg(target_os = "linux")]
+        {
+            if handle_signals.is_some() {
+                signals::uninstall();
+            }
+        }
 
         // start to shut down the TcpListener
         println!("Shutting down...");
@@ -139,6 +188,8 @@ impl HttpServer {
                 );
             });
         }
+
+        stats
     }
 
     /// Obtain an `AsyncController`, which can be run in a parallel thread and control or update
@@ -146,7 +197,14 @@ impl HttpServer {
     #[inline]
     #[must_use]
     pub fn get_courier(&self) -> AsyncController {
-        self.state.get_courier_sender()
+        self.state.get_courier_sender(&self.instance)
+    }
+
+    /// Get the statistics of the connections of the server, e.g. the ones turned down for the
+    /// limits, see `ServerConfig::set_max_connections`. While the server is running, the
+    /// statistics can be obtained via the `AsyncController` instead.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.instance.limits.stats()
     }
 
     #[inline]
@@ -180,7 +238,45 @@ impl HttpServer {
         }
     }
 
-    fn launch_with(&mut self, listener: &TcpListener, mut cb_sig: Option<channel::Sender<()>>) {
+    /// Handle the Unix signals once the server is launched: `SIGTERM` and `SIGINT` shut the server
+    /// down gracefully as `ControlMessage::Terminate` does, `SIGHUP` reloads the configuration as
+    /// `ControlMessage::HotReloadConfig` does, and `SIGUSR1` invokes the hook, if any. If `SIGTERM`
+    /// or `SIGINT` comes again while the server is shutting down, the process is terminated right
+    /// away. The default actions of the signals are restored after the server is shut down.
+    ///
+    /// The signals are handled for one server at a time, i.e. the one launched last in the process.
+    /// This API is only available on Linux, and is a no-op elsewhere.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.handle_signals(Some(rotate_logs));
+    ///
+    /// fn rotate_logs() {
+    ///     // ... reopen the log files ...
+    /// }
+    /// ```
+    pub fn handle_signals(&mut self, on_usr1: Option<SignalHook>) {
+        if cfg!(not(target_os = "linux")) {
+            debug::print(
+                "Handling the signals is only supported on Linux",
+                InfoLevel::Warning,
+            );
+
+            return;
+        }
+
+        self.state.set_signals(on_usr1);
+    }
+
+    fn launch_with(
+        &mut self,
+        listener: &TcpListener,
+        mut cb_sig: Option<channel::Sender<()>>,
+    ) -> ShutdownStats {
         // if using the session module and allow auto clean up, launch the service now.
         if cfg!(feature = "session") {
             self.session_cleanup_config();
@@ -192,6 +288,22 @@ impl HttpServer {
         // initialize the shared object pools
         http::init_pools();
 
+        // turn on the static file cache if required
+        let (max_bytes, max_file_size) = self.config.get_file_cache();
+        cache::init(max_bytes, max_file_size);
+        router::init_cache(self.config.get_route_cache());
+
+        // log the route table for the record
+        let routes: Vec<String> = Route::routes().iter().map(|r| r.to_string()).collect();
+        debug::print(
+            &format!(
+                "Serving {} routes:\r\n {}",
+                routes.len(),
+                routes.join("\r\n ")
+            ),
+            InfoLevel::Info,
+        );
+
         let acceptor: Option<Arc<TlsAcceptor>> = self.config.build_tls_acceptor();
         let (mut read_timeout, mut write_timeout, mut req_limit) = self.config.load_server_params();
 
@@ -228,7 +340,9 @@ impl HttpServer {
                         }
                     }
                     ControlMessage::HotLoadRouter(r) => {
-                        Route::use_router_async(r);
+                        // the swap won't block the readers, so the connections accepted from now
+                        // on are served by the new router
+                        Route::use_router(r);
                     }
                     ControlMessage::HotLoadConfig(c) => {
                         // check pool size param
@@ -245,6 +359,19 @@ impl HttpServer {
                         write_timeout = params.1;
                         req_limit = params.2;
 
+                        // resize the static file cache if it's changed
+                        if c.get_file_cache() != self.config.get_file_cache() {
+                            let (max_bytes, max_file_size) = c.get_file_cache();
+                            cache::init(max_bytes, max_file_size);
+                        }
+
+                        // the cached routes are always dropped, since the routes may be reloaded
+                        if c.get_route_cache() != self.config.get_route_cache() {
+                            router::init_cache(c.get_route_cache());
+                        } else {
+                            router::clear_cache();
+                        }
+
                         // update the config and reset the session clean effort
                         self.config = c;
 
@@ -268,10 +395,9 @@ impl HttpServer {
                     // process the connection
                     self.handle_stream(
                         s,
+                        self.config.get_connection_limits(),
                         &mut workers_pool,
                         acceptor.clone(),
-                        read_timeout,
-                        write_timeout,
                         req_limit,
                     );
                 }
@@ -282,25 +408,68 @@ impl HttpServer {
             }
         }
 
+        // stop accepting, then wait for the connections to drain before the statics are dropped
+        let stats = drain::drain(self.config.get_shutdown_timeout());
+        if stats.closed > 0 {
+            debug::print(
+                &format!(
+                    "Shutdown timed out, closed {} connections by force and dropped {} requests in flight",
+                    stats.closed, stats.dropped
+                ),
+                InfoLevel::Warning,
+            );
+        }
+
         self.state.toggle_running_state(false);
-        self.cleanup();
+        self.cleanup(stats.dropped == 0);
+
+        stats
     }
 
     fn handle_stream(
         &self,
         stream: TcpStream,
+        limits: Limits,
         workers_pool: &mut ThreadPool,
         acceptor: Option<Arc<TlsAcceptor>>,
-        read_timeout: u64,
-        write_timeout: u64,
         req_limit: usize,
     ) {
+        let instance = Arc::clone(&self.instance);
+
         workers_pool.execute(move || {
+            let _scope = instance::enter(&instance);
+
+            // hold a slot for the connection, or turn it down if we're at the limits, which is done
+            // here such that the accepting is never held up by the queued or the rejected ones
+            let _slot = match limits::admit(&stream, limits) {
+                Ok(slot) => slot,
+                Err(Overflow::Reject) if acceptor.is_none() => {
+                    conn::send_err_resp(Stream::Tcp(stream), 503);
+                    return;
+                }
+                Err(_) => {
+                    // no handshake for the rejected TLS connections, just reset them
+                    limits::reset(stream);
+                    return;
+                }
+            };
+
+            // keep the connection on the record till it's done, such that it can be drained
+            let _conn = drain::track(&stream);
+
             if let Some(a) = acceptor {
                 // handshake and encrypt
                 match a.accept(stream) {
                     Ok(s) => {
-                        Stream::Tls(Box::new(s)).process(true, req_limit);
+                        let stream = Stream::Tls(Box::new(s));
+
+                        #[cfg(feature = "http2")]
+                        let stream = match h2::try_serve(stream, true, req_limit) {
+                            Ok(()) => return,
+                            Err(s) => s,
+                        };
+
+                        stream.process(true, req_limit);
                     }
                     Err(e) => debug::print(
                         &format!("Failed to receive the upcoming stream: {:?}", e)[..],
@@ -308,7 +477,15 @@ impl HttpServer {
                     ),
                 };
             } else {
-                Stream::Tcp(stream).process(false, req_limit);
+                let stream = Stream::Tcp(stream);
+
+                #[cfg(feature = "http2")]
+                let stream = match h2::try_serve(stream, false, req_limit) {
+                    Ok(()) => return,
+                    Err(s) => s,
+                };
+
+                stream.process(false, req_limit);
             }
         });
     }
@@ -331,27 +508,39 @@ impl HttpServer {
         ThreadPool::new(size)
     }
 
-    fn cleanup(&self) {
-        // Must close the shared pool, since it's a static and won't drop with the end of the server,
-        // which could cause response executions still on-the-fly to crash.
+    /// Bind the instance of the server to the current thread, such that the static APIs called in
+    /// the scope are applied to this server.
+    fn enter(&self) -> instance::Scope {
+        instance::enter(&self.instance)
+    }
+
+    pub(crate) fn instance(&self) -> &Arc<Instance> {
+        &self.instance
+    }
+
+    fn cleanup(&self, drained: bool) {
+        // Must close the shared pool, which would otherwise keep the workers of the server around,
+        // and the response executions still on-the-fly may crash.
         shared_pool::close();
 
-        // Clean up with static stores. Invoking the statics after this cleanup step will lead to
-        // undefined behaviors (not always panic), since we drop contents in-place and before the
-        // actual destructors are called on the statics
-        http::drop_statics();
-        router::drop_statics();
+        // Drop the object pools. If the requests in flight were dropped at the shutdown deadline,
+        // their handlers may still be running and using the pools, so the pools are left as they
+        // are, and will be dropped with the server.
+        if drained {
+            http::drop_pools();
+        }
+
+        // drop the static file cache
+        cache::init(0, 0);
     }
 }
 
 impl Default for HttpServer {
     fn default() -> Self {
-        // reset the router with the new server instance
-        Route::init();
-
         HttpServer {
             config: Default::default(),
             state: ServerStates::new(),
+            instance: instance::claim(),
         }
     }
 }
@@ -399,6 +588,7 @@ impl ServerDef for HttpServer {
     /// Replace the default server router with the pre-built one. This is a wrapper over
     /// `Route::use_router`, which will achieve same goal.
     fn def_router(&mut self, router: Route) {
+        let _scope = self.enter();
         Route::use_router(router);
     }
 
@@ -439,12 +629,14 @@ impl ServerDef for HttpServer {
     /// Define headers and their contents that shall go along with every http response. This will
     /// remove any existing default headers and corresponding contents.
     fn def_default_response_header(&mut self, header: HashMap<String, String>) {
+        let _scope = self.enter();
         ServerConfig::use_default_header(header);
     }
 
     /// Set or update default headers and their contents that shall go along with every http response.
     /// If a default header with same name exists, the new contents will replace the existing one.
     fn set_default_response_header(&mut self, field: String, value: String) {
+        let _scope = self.enter();
         ServerConfig::set_default_header(field, value, true);
     }
 
@@ -472,36 +664,43 @@ impl ServerDef for HttpServer {
 
 impl Router for HttpServer {
     fn get(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::GET, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn patch(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::PATCH, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn post(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::POST, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn put(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::PUT, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn delete(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::DELETE, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn options(&mut self, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(REST::OPTIONS, uri, RouteHandler::new(Some(callback), None));
         self
     }
 
     fn other(&mut self, method: &str, uri: RequestPath, callback: Callback) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_route(
             REST::OTHER(method.to_uppercase()),
             uri,
@@ -521,8 +720,9 @@ impl Router for HttpServer {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
     /// use rusty_express::prelude::*;
+    /// use std::path::PathBuf;
     ///
     /// // define http server now
     /// let mut server = HttpServer::new();
@@ -530,10 +730,19 @@ impl Router for HttpServer {
     /// server.use_static(PathBuf::from(r".\static"));
     /// ```
     fn use_static(&mut self, path: PathBuf) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_static(REST::GET, None, path);
         self
     }
 
+    /// Define a static folder location with the options on how to serve the folders, e.g. the index
+    /// files, folder listing, or the fallback file for a single page app.
+    fn use_static_with(&mut self, path: PathBuf, options: StaticOptions) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_static_with(REST::GET, path, options);
+        self
+    }
+
     /// Define a customized static folder location, where the requested file will be served only if
     /// it matches the URI rules defined by the API.
     ///
@@ -541,6 +750,7 @@ impl Router for HttpServer {
     ///
     /// ```
     /// use rusty_express::prelude::*;
+    /// use std::path::PathBuf;
     ///
     /// // define http server now
     /// let mut server = HttpServer::new();
@@ -549,37 +759,37 @@ impl Router for HttpServer {
     /// server.use_custom_static(RequestPath::Explicit("/index.html"), PathBuf::from(r".\static"));
     /// ```
     fn use_custom_static(&mut self, uri: RequestPath, path: PathBuf) -> &mut dyn Router {
+        let _scope = self.enter();
         Route::add_static(REST::GET, Some(uri), path);
         self
     }
 
-    /// This API will add the location or the extension that are allowed to be served to all the static
-    /// routes. If a location is white-listed, you must provide a normalized and absolute path to the
-    /// folder, and all files or sub-folders under the given path will be deemed as white-listed;
-    /// if an extension is provided, it must be formatted as `*.<extension>`, for example,
-    /// `*.txt` is a valid extension, though `.txt` or `txt` is not.
+    /// This API will add the glob rule of the files that are allowed to be served to all the static
+    /// routes, and once a white-list is set, only the files matching one of its rules are served. A
+    /// rule without a `/` matches the file or folder name at any level, e.g. `*.txt` or `public`,
+    /// otherwise it matches the path from the static folder root, e.g. `assets/**/*.js`.
     ///
     /// Note that if the `for_path` params are provided, the white list will only be applied to the
     /// given path (i.e. defined prior with the path to the static folder location).
     fn static_white_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
+        let _scope = self.enter();
         Route::static_lists(loc_or_ext, true, for_path);
     }
 
-    /// This API will add the location or the extension that are *NOT* allowed to be served to any
-    /// of the static routes. If a folder location is black-listed, you must provide a normalized and
-    /// absolute path to this folder, and all files or sub-folders under the given path will be
-    /// deemed as black-listed; if an extension is provided, it must be formatted as
-    /// `*.<extension>`, for example, `*.txt` is a valid extension, though `.txt` or `txt` is not.
+    /// This API will add the glob rule of the files that are *NOT* allowed to be served to any of
+    /// the static routes, which takes precedence over the white-list, e.g. `*.map` or `private/**`.
     ///
-    /// Note that if the `for_path` params are provided, the white list will only be applied to the
+    /// Note that if the `for_path` params are provided, the black list will only be applied to the
     /// given path (i.e. defined prior with the path to the static folder location).
     fn static_black_list(&mut self, loc_or_ext: String, for_path: Option<PathBuf>) {
-        Route::static_lists(loc_or_ext, true, for_path);
+        let _scope = self.enter();
+        Route::static_lists(loc_or_ext, false, for_path);
     }
 
     /// Note: this API only affect routes moving forward, and it will not be applied to routes
     /// already in the `Router`.
     fn case_sensitive(&mut self, allow_case: bool, method: Option<REST>) {
+        let _scope = self.enter();
         if method.is_none() {
             Route::all_case_sensitive(allow_case);
             return;
@@ -589,6 +799,88 @@ impl Router for HttpServer {
             Route::case_sensitive(&m, allow_case);
         }
     }
+
+    /// Add a redirect rule to the route-level redirect table, such that requests to the `from` path
+    /// will be redirected to the `to` location with the given `status`, without going through a
+    /// handler. The query string of the request will be carried over to the new location.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_express::prelude::*;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.redirect("/old-index", "/index", 308);
+    /// ```
+    fn redirect(&mut self, from: &str, to: &str, status: u16) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_redirect(from, to, status);
+        self
+    }
+
+    /// Mount the sub-router under the path prefix, and the sub-router only sees the remainder of the
+    /// request uri. See `Route::mount` for details.
+    fn mount(&mut self, prefix: &str, router: Route) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_mount(prefix, router);
+        self
+    }
+
+    /// Serve the requests to the matching host with the given router, see `Router::host` for the
+    /// host patterns.
+    fn host(&mut self, pattern: &str, router: Route) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_host(pattern, router);
+        self
+    }
+
+    /// Add the route which is only picked if the request meets the guard, see `Router::guarded`.
+    fn guarded(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        guard: RequestGuard,
+        callback: Callback,
+    ) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_route(method, uri, RouteHandler::guarded(guard, callback));
+        self
+    }
+
+    /// Name the route added right before, such that its url can be built with `Route::url_for`.
+    fn name(&mut self, name: &str) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::name_route(name);
+        self
+    }
+
+    /// Add the route which is only served if the request is allowed by the policy, see
+    /// `Router::authorized`.
+    fn authorized(
+        &mut self,
+        method: REST,
+        uri: RequestPath,
+        policy: AuthPolicy,
+        callback: Callback,
+    ) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_route(method, uri, RouteHandler::authorized(policy, callback));
+        self
+    }
+
+    /// Set the policy guarding all the routes of the server, see `Router::policy`.
+    fn policy(&mut self, policy: AuthPolicy) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::set_policy(policy);
+        self
+    }
+
+    /// Add the routes of the resource actions provided by the controller, see `Router::resource`.
+    fn resource(&mut self, path: &str, controller: &dyn ResourceController) -> &mut dyn Router {
+        let _scope = self.enter();
+        Route::add_resource(path, controller);
+        self
+    }
 }
 
 impl ViewEngineDefinition for HttpServer {
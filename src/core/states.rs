@@ -1,12 +1,14 @@
 #![allow(dead_code)]
 
 use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::Arc;
 use std::thread::JoinHandle;
 
-use crate::channel::{self, SendError, TryRecvError};
-use crate::core::{config::ServerConfig, router::Route};
+use crate::channel::{self, SendError, TryRecvError, TrySendError};
+use crate::core::{config::ServerConfig, instance::Instance, router::Route};
 use crate::support::{
     debug::{self, InfoLevel},
+    limits::ConnectionStats,
     session::*,
 };
 
@@ -18,22 +20,33 @@ pub enum ControlMessage {
     Custom(String),
 }
 
-pub struct AsyncController(channel::Sender<ControlMessage>, SocketAddr);
+/// `SignalHook` is a type alias to the function invoked on `SIGUSR1`, see
+/// `HttpServer::handle_signals`.
+pub type SignalHook = fn();
+
+pub struct AsyncController(channel::Sender<ControlMessage>, SocketAddr, Arc<Instance>);
 
 impl AsyncController {
-    fn new(messenger: channel::Sender<ControlMessage>, addr: SocketAddr) -> Self {
-        AsyncController(messenger, addr)
+    fn new(
+        messenger: channel::Sender<ControlMessage>,
+        addr: SocketAddr,
+        instance: Arc<Instance>,
+    ) -> Self {
+        AsyncController(messenger, addr, instance)
+    }
+
+    /// Get the statistics of the connections of the server, while it's running.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.2.limits.stats()
     }
 
+    // the failed message is handed back as is, same as the channel does
+    #[allow(clippy::result_large_err)]
     pub fn send(&self, message: ControlMessage) -> Result<(), SendError<ControlMessage>> {
         match message {
             ControlMessage::Terminate => {
                 self.0.send(ControlMessage::Terminate)?;
-
-                // initiate a connection such that the terminal signal can be immediately picked up
-                if let Ok(client) = TcpStream::connect(self.1) {
-                    let _ = client.shutdown(Shutdown::Both);
-                }
+                self.wake();
             }
             other_msg => {
                 self.0.send(other_msg)?;
@@ -42,11 +55,30 @@ impl AsyncController {
 
         Ok(())
     }
+
+    /// Deliver the message without blocking, and wake the listener to pick it up if delivered.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn try_send(
+        &self,
+        message: ControlMessage,
+    ) -> Result<(), TrySendError<ControlMessage>> {
+        self.0.try_send(message)?;
+        self.wake();
+        Ok(())
+    }
+
+    /// Initiate a connection such that the pending message can be immediately picked up by the
+    /// listener, which only checks the messages on accepting a connection.
+    pub(crate) fn wake(&self) {
+        if let Ok(client) = TcpStream::connect(self.1) {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
 }
 
 impl Clone for AsyncController {
     fn clone(&self) -> Self {
-        AsyncController(self.0.clone(), self.1)
+        AsyncController(self.0.clone(), self.1, Arc::clone(&self.2))
     }
 }
 
@@ -58,6 +90,7 @@ pub struct ServerStates {
     ),
     socket_addr: SocketAddr,
     session_auto_clean_handler: Option<JoinHandle<()>>,
+    signals: Option<Option<SignalHook>>,
 }
 
 impl ServerStates {
@@ -67,6 +100,7 @@ impl ServerStates {
             courier_channel: channel::bounded(1),
             socket_addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
             session_auto_clean_handler: None,
+            signals: None,
         }
     }
 
@@ -80,15 +114,29 @@ impl ServerStates {
         }
     }
 
+    pub(crate) fn set_signals(&mut self, on_usr1: Option<SignalHook>) {
+        self.signals = Some(on_usr1);
+    }
+
+    /// If the signals shall be handled, with the hook of `SIGUSR1`.
+    pub(crate) fn get_signals(&self) -> Option<Option<SignalHook>> {
+        self.signals
+    }
+
     pub(crate) fn set_port(&mut self, port: u16) {
         self.socket_addr.set_port(port);
     }
 
     #[inline]
-    pub(crate) fn get_courier_sender(&self) -> AsyncController {
-        AsyncController::new(self.courier_channel.0.clone(), self.socket_addr)
+    pub(crate) fn get_courier_sender(&self, instance: &Arc<Instance>) -> AsyncController {
+        AsyncController::new(
+            self.courier_channel.0.clone(),
+            self.socket_addr,
+            Arc::clone(instance),
+        )
     }
 
+    #[allow(clippy::result_large_err)]
     pub(crate) fn courier_deliver(
         &self,
         msg: ControlMessage,
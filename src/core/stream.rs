@@ -1,11 +1,18 @@
 #![allow(dead_code)]
 
+use std::fs::File;
 use std::io::{self, prelude::*, Error, ErrorKind};
 use std::net::{Shutdown, SocketAddr, TcpStream};
 use std::time::Duration;
 
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
 use crate::native_tls::TlsStream;
 
+/// The max size of each chunk when sending a file, such that we won't hold the whole file in memory.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
 pub(crate) enum Stream {
     Tcp(TcpStream),
     Tls(Box<TlsStream<TcpStream>>),
@@ -64,6 +71,83 @@ impl Stream {
             _ => false,
         }
     }
+
+    /// Send `len` bytes of the file, from its current position, to the stream. On Linux, a plain
+    /// TCP stream will be served with `sendfile(2)`, such that the content is copied by the kernel
+    /// directly from the file to the socket; otherwise, e.g. for TLS streams, the file will be
+    /// copied in bounded chunks.
+    pub(crate) fn send_file(&mut self, file: &File, len: u64) -> io::Result<u64> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Stream::Tcp(tcp) = self {
+                match sendfile(tcp, file, len) {
+                    Ok(sent) => return Ok(sent),
+                    Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                        // the file doesn't support mmap-like operations, fallback to copying
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        copy_chunks(self, file, len)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sendfile(tcp: &TcpStream, file: &File, len: u64) -> io::Result<u64> {
+    let mut sent = 0u64;
+
+    while sent < len {
+        let count = (len - sent).min(FILE_CHUNK_SIZE as u64 * 16) as usize;
+        let res = unsafe {
+            libc::sendfile(
+                tcp.as_raw_fd(),
+                file.as_raw_fd(),
+                std::ptr::null_mut(),
+                count,
+            )
+        };
+
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(err);
+        }
+
+        if res == 0 {
+            // the file has been truncated since we opened it
+            break;
+        }
+
+        sent += res as u64;
+    }
+
+    Ok(sent)
+}
+
+fn copy_chunks(stream: &mut Stream, mut file: &File, len: u64) -> io::Result<u64> {
+    let mut buf = vec![0u8; FILE_CHUNK_SIZE.min(len as usize)];
+    let mut sent = 0u64;
+
+    while sent < len {
+        let count = (len - sent).min(buf.len() as u64) as usize;
+        let read = match file.read(&mut buf[..count]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        stream.write_all(&buf[..read])?;
+        sent += read as u64;
+    }
+
+    stream.flush()?;
+    Ok(sent)
 }
 
 impl Read for Stream {
@@ -287,10 +287,11 @@ impl<T: Default> Bucket<T> {
 impl<T> Drop for Bucket<T> {
     fn drop(&mut self) {
         for item in self.slot.iter_mut() {
-            unsafe {
-                ptr::drop_in_place(*item);
+            // the slot may be empty if its value is checked out
+            let val = mem::replace(item, ptr::null_mut());
+            if !val.is_null() {
+                drop(unsafe { Box::from_raw(val) });
             }
-            *item = ptr::null_mut();
         }
     }
 }
@@ -0,0 +1,272 @@
+//! The in-process test client, which serves the requests with the routes, the authorization and
+//! the handlers of a server as if they're read from a connection, but without binding to a port.
+
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::core::conn;
+use crate::core::http::ResponseBody;
+use crate::core::instance::{self, Instance};
+use crate::core::router::REST;
+use crate::core::server::HttpServer;
+use crate::core::syncstore::Reusable;
+
+/// The client to test the handlers of a server without a socket. The requests are served the same
+/// way as they are from a connection, i.e. routed and authorized, then handled and checked for the
+/// errors, and the response can be inspected afterwards.
+///
+/// # Example
+///
+/// ```
+/// use rusty_express::prelude::*;
+/// use rusty_express::testing::{TestClient, TestRequest};
+///
+/// let mut server = HttpServer::new();
+/// server.post(RequestPath::Explicit("/echo"), echo);
+///
+/// let client = TestClient::new(&server);
+///
+/// let mut request = TestRequest::new(REST::POST, "/echo");
+/// request.set_header("content-type", "application/x-www-form-urlencoded");
+/// request.set_cookie("user", "jacob");
+/// request.set_body("greeting=hello");
+///
+/// let response = client.send(request);
+/// assert_eq!(response.status(), 200);
+/// assert_eq!(response.text(), "hello from jacob");
+/// assert_eq!(response.cookie("seen"), Some(String::from("yes")));
+///
+/// assert_eq!(client.get("/missing").status(), 404);
+///
+/// pub fn echo(req: &Box<Request>, resp: &mut Box<Response>) {
+///     let user = req.cookie("user").unwrap_or_default();
+///     let greeting = req.form_data().remove("greeting").unwrap_or_default();
+///
+///     resp.send(&format!("{} from {}", greeting, user));
+///     resp.set_cookie(Cookie::new("seen", "yes"));
+/// }
+/// ```
+pub struct TestClient {
+    instance: Arc<Instance>,
+}
+
+impl TestClient {
+    /// Create the client serving the requests with the routes and the configs of the server.
+    pub fn new(server: &HttpServer) -> Self {
+        TestClient {
+            instance: Arc::clone(server.instance()),
+        }
+    }
+
+    /// Send a `GET` request to the path, which may come with the query string.
+    pub fn get(&self, path: &str) -> TestResponse {
+        self.send(TestRequest::new(REST::GET, path))
+    }
+
+    /// Serve the request, and return the response once it's handled.
+    pub fn send(&self, request: TestRequest) -> TestResponse {
+        let _scope = instance::enter(&self.instance);
+
+        let mut response = conn::serve_raw(&request.to_raw(), request.client);
+        let (status, headers, body) = response.take_parts();
+        response.release();
+
+        let body = match body {
+            ResponseBody::Bytes(content) => content,
+            ResponseBody::Shared(content) => content.as_ref().clone(),
+            ResponseBody::File(file, len) => {
+                let mut content = Vec::with_capacity(len as usize);
+                file.take(len).read_to_end(&mut content).unwrap_or_default();
+                content
+            }
+        };
+
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+/// The request to be sent by the `TestClient`, which comes from `127.0.0.1` by default.
+pub struct TestRequest {
+    method: REST,
+    path: String,
+    headers: Vec<(String, String)>,
+    cookies: Vec<(String, String)>,
+    body: String,
+    client: Option<SocketAddr>,
+}
+
+impl TestRequest {
+    /// Create the request to the path, which may come with the query string, e.g. `/users?page=2`.
+    pub fn new(method: REST, path: &str) -> Self {
+        TestRequest {
+            method,
+            path: path.to_owned(),
+            headers: Vec::new(),
+            cookies: Vec::new(),
+            body: String::new(),
+            client: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+        }
+    }
+
+    /// Set the header, replacing the one with the same name, if any.
+    pub fn set_header(&mut self, field: &str, value: &str) {
+        let field = field.trim().to_lowercase();
+        self.headers.retain(|(name, _)| name != &field);
+        self.headers.push((field, value.trim().to_owned()));
+    }
+
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.retain(|(key, _)| key != name);
+        self.cookies.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// Set the body, and the `Content-Length` header will be set to its length if not given.
+    pub fn set_body(&mut self, body: &str) {
+        self.body = body.to_owned();
+    }
+
+    /// The address of the client sending the request, or `None` if it's unknown.
+    pub fn set_client(&mut self, client: Option<SocketAddr>) {
+        self.client = client;
+    }
+
+    /// The request as it would be read from the connection.
+    fn to_raw(&self) -> String {
+        let mut raw = format!("{} {} HTTP/1.1\r\n", self.method, self.path);
+
+        if !self.headers.iter().any(|(name, _)| name == "host") {
+            raw.push_str("host: localhost\r\n");
+        }
+
+        for (name, value) in self.headers.iter() {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        if !self.cookies.is_empty() {
+            let cookies: Vec<String> = self
+                .cookies
+                .iter()
+                .map(|(key, value)| [key.as_str(), "=", value.as_str()].join(""))
+                .collect();
+
+            raw.push_str(&format!("cookie: {}\r\n", cookies.join("; ")));
+        }
+
+        let has_length = self
+            .headers
+            .iter()
+            .any(|(name, _)| name == "content-length");
+        if !self.body.is_empty() && !has_length {
+            raw.push_str(&format!("content-length: {}\r\n", self.body.len()));
+        }
+
+        raw.push_str("\r\n");
+        raw.push_str(&self.body);
+        raw
+    }
+}
+
+/// The response to the request sent by the `TestClient`.
+pub struct TestResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The value of the header, where the name is case-insensitive. The `Set-Cookie` headers can be
+    /// read with `TestResponse::cookie` instead.
+    pub fn header(&self, field: &str) -> Option<String> {
+        let field = field.to_lowercase();
+
+        self.headers
+            .iter()
+            .find(|(name, _)| name == &field)
+            .map(|(_, value)| value.to_owned())
+    }
+
+    /// All the headers, with the names in lowercase.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the cookie set by the response.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .filter(|(field, _)| field == "set-cookie")
+            .filter_map(|(_, value)| {
+                let pair = value.split(';').next()?;
+                let pos = pair.find('=')?;
+                Some((pair[..pos].trim(), pair[pos + 1..].trim()))
+            })
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_owned())
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body as text, where the invalid UTF-8 sequences are replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod testing_test {
+    #![allow(clippy::borrowed_box)]
+
+    use super::*;
+    use crate::core::auth::AuthDecision;
+    use crate::core::http::{Request, Response, ResponseWriter};
+    use crate::core::router::{RequestPath, Router};
+
+    fn show(req: &Box<Request>, resp: &mut Box<Response>) {
+        let name = req.param("name").unwrap_or_default();
+        let token = req.header("x-token").unwrap_or_default();
+
+        resp.header("x-name", &name, true);
+        resp.send(&format!("{}:{}", name, token));
+    }
+
+    fn deny(_: &Request) -> AuthDecision {
+        AuthDecision::Unauthorized(String::from("Basic realm=\"admin\""))
+    }
+
+    #[test]
+    fn client_test() {
+        let mut server = HttpServer::new();
+        server
+            .get(RequestPath::ExplicitWithParams("/users/:name"), show)
+            .authorized(REST::GET, RequestPath::Explicit("/admin"), deny, show);
+
+        let client = TestClient::new(&server);
+
+        let mut request = TestRequest::new(REST::GET, "/users/jacob");
+        request.set_header("X-Token", "secret");
+
+        let response = client.send(request);
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.header("X-Name"), Some(String::from("jacob")));
+        assert_eq!(response.text(), "jacob:secret");
+
+        let response = client.get("/admin");
+        assert_eq!(response.status(), 401);
+        assert_eq!(
+            response.header("www-authenticate"),
+            Some(String::from("Basic realm=\"admin\""))
+        );
+        assert_eq!(client.get("/nowhere").status(), 404);
+    }
+}
@@ -29,10 +29,14 @@ extern crate regex;
 #[cfg(feature = "session")]
 extern crate rand;
 
+#[cfg(target_os = "linux")]
+extern crate libc;
+
 pub(crate) mod core;
 pub(crate) mod support;
 
 pub mod prelude {
+    pub use crate::core::auth::{AuthDecision, AuthPolicy};
     pub use crate::core::config::{
         EngineContext, PageGenerator, ServerConfig, ViewEngine, ViewEngineDefinition,
     };
@@ -40,10 +44,18 @@ pub mod prelude {
     pub use crate::core::context as ServerContext;
     pub use crate::core::context::ContextProvider;
     pub use crate::core::cookie::*;
+    pub use crate::core::guard::{GuardFunc, RequestGuard};
     pub use crate::core::http::{Request, RequestWriter, Response, ResponseStates, ResponseWriter};
-    pub use crate::core::router::{RequestPath, Route, Router, REST};
+    pub use crate::core::resource::ResourceController;
+    pub use crate::core::router::{
+        Callback, ParamConstraint, PathOptions, RequestPath, Route, RouteCacheStats, RouteInfo,
+        RouteKind, Router, StaticOptions, SymlinkPolicy, TrailingSlash, REST,
+    };
     pub use crate::core::server::{HttpServer, ServerDef};
-    pub use crate::core::states::{AsyncController, ControlMessage};
+    pub use crate::core::states::{AsyncController, ControlMessage, SignalHook};
+    pub use crate::support::cache::FileCacheStats;
+    pub use crate::support::drain::ShutdownStats;
+    pub use crate::support::limits::{ConnectionStats, OverflowPolicy};
 
     #[cfg(feature = "session")]
     pub use crate::support::session::*;
@@ -52,4 +64,8 @@ pub mod prelude {
     pub use crate::support::logger::InfoLevel;
 }
 
+pub mod testing {
+    pub use crate::core::testing::{TestClient, TestRequest, TestResponse};
+}
+
 use crossbeam_channel as channel;
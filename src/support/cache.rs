@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::core::instance;
+use crate::hashbrown::HashMap;
+
+/// The statistics of the static file cache, which can be obtained via
+/// `ServerConfig::file_cache_stats` while the server is running.
+#[derive(Clone, Debug, Default)]
+pub struct FileCacheStats {
+    /// The number of requested files served from the cache.
+    pub hits: u64,
+    /// The number of requested files not found in the cache, including the stale ones.
+    pub misses: u64,
+    /// The number of files removed from the cache to make room for new files.
+    pub evictions: u64,
+    /// The number of files removed from the cache because they have been modified.
+    pub invalidations: u64,
+    /// The number of files currently in the cache.
+    pub entries: usize,
+    /// The total size of the files currently in the cache, in bytes.
+    pub bytes: usize,
+}
+
+struct CacheEntry {
+    content: Arc<Vec<u8>>,
+    modified: Option<SystemTime>,
+    len: u64,
+    tick: u64,
+}
+
+/// The LRU cache, where the `order` map keeps the entries sorted by the last time they're used,
+/// such that the least recently used entry is always the first one to go.
+pub(crate) struct FileCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    order: BTreeMap<u64, PathBuf>,
+    tick: u64,
+    max_bytes: usize,
+    max_file_size: usize,
+    stats: FileCacheStats,
+}
+
+impl FileCache {
+    pub(crate) fn new(max_bytes: usize, max_file_size: usize) -> Self {
+        FileCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            max_bytes,
+            max_file_size: max_file_size.min(max_bytes),
+            stats: FileCacheStats::default(),
+        }
+    }
+
+    /// Get the cached content of the file, if it's not been modified since it's cached.
+    pub(crate) fn get(
+        &mut self,
+        path: &Path,
+        modified: Option<SystemTime>,
+        len: u64,
+    ) -> Option<Arc<Vec<u8>>> {
+        if len == 0 || len > self.max_file_size as u64 {
+            // won't be cached anyway
+            return None;
+        }
+
+        let tick = self.next_tick();
+
+        let stale = match self.entries.get_mut(path) {
+            Some(entry) if entry.modified == modified && entry.len == len => {
+                self.order.remove(&entry.tick);
+                self.order.insert(tick, path.to_path_buf());
+                entry.tick = tick;
+
+                self.stats.hits += 1;
+                return Some(Arc::clone(&entry.content));
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if stale {
+            self.remove(path);
+            self.stats.invalidations += 1;
+        }
+
+        self.stats.misses += 1;
+        None
+    }
+
+    pub(crate) fn put(
+        &mut self,
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        content: Arc<Vec<u8>>,
+    ) {
+        let size = content.len();
+        if size == 0 || size > self.max_file_size {
+            return;
+        }
+
+        self.remove(&path);
+
+        // make room for the new file
+        while self.stats.bytes + size > self.max_bytes {
+            let oldest = match self.order.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+
+            if let Some(victim) = self.order.remove(&oldest) {
+                self.remove(&victim);
+                self.stats.evictions += 1;
+            }
+        }
+
+        let tick = self.next_tick();
+        self.order.insert(tick, path.clone());
+        self.entries.insert(
+            path,
+            CacheEntry {
+                content,
+                modified,
+                len: size as u64,
+                tick,
+            },
+        );
+
+        self.stats.entries += 1;
+        self.stats.bytes += size;
+    }
+
+    pub(crate) fn stats(&self) -> FileCacheStats {
+        self.stats.clone()
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.tick);
+            self.stats.entries -= 1;
+            self.stats.bytes -= entry.content.len();
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// Turn on the static file cache, or resize it if it's been turned on already. If `max_bytes`
+/// is 0, the cache will be turned off.
+pub(crate) fn init(max_bytes: usize, max_file_size: usize) {
+    let instance = instance::current();
+    let mut store = instance.file_cache.lock();
+
+    if max_bytes == 0 || max_file_size == 0 {
+        store.take();
+        return;
+    }
+
+    let stats = store.as_ref().map(FileCache::stats);
+    let mut cache = FileCache::new(max_bytes, max_file_size);
+
+    if let Some(s) = stats {
+        // the cache contents are dropped, but we'll keep the counters
+        cache.stats = FileCacheStats {
+            entries: 0,
+            bytes: 0,
+            ..s
+        };
+    }
+
+    store.replace(cache);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    instance::current().file_cache.lock().is_some()
+}
+
+/// Look up the file in the cache, which is keyed by the canonical path of the file, such that
+/// different routes to the same file will share the cached content.
+pub(crate) fn lookup(path: &Path) -> Option<Arc<Vec<u8>>> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let (key, modified, len) = file_key(path)?;
+
+    match instance::current().file_cache.lock().as_mut() {
+        Some(cache) => cache.get(&key, modified, len),
+        None => None,
+    }
+}
+
+/// Move the content of the file into the cache, and return the shared content, whether it can be
+/// cached or not.
+pub(crate) fn store(path: &Path, content: Vec<u8>) -> Arc<Vec<u8>> {
+    let content = Arc::new(content);
+    if !is_enabled() {
+        return content;
+    }
+
+    if let Some((key, modified, len)) = file_key(path) {
+        // the file has changed since we read it, don't cache the outdated content
+        if len != content.len() as u64 {
+            return content;
+        }
+
+        if let Some(cache) = instance::current().file_cache.lock().as_mut() {
+            cache.put(key, modified, Arc::clone(&content));
+        }
+    }
+
+    content
+}
+
+pub(crate) fn stats() -> Option<FileCacheStats> {
+    instance::current()
+        .file_cache
+        .lock()
+        .as_ref()
+        .map(FileCache::stats)
+}
+
+pub(crate) fn clear() {
+    if let Some(cache) = instance::current().file_cache.lock().as_mut() {
+        let (max_bytes, max_file_size) = (cache.max_bytes, cache.max_file_size);
+        let stats = cache.stats();
+
+        *cache = FileCache::new(max_bytes, max_file_size);
+        cache.stats = FileCacheStats {
+            entries: 0,
+            bytes: 0,
+            ..stats
+        };
+    }
+}
+
+fn file_key(path: &Path) -> Option<(PathBuf, Option<SystemTime>, u64)> {
+    let key = fs::canonicalize(path).ok()?;
+    let meta = fs::metadata(&key).ok().filter(|m| m.is_file())?;
+
+    Some((key, meta.modified().ok(), meta.len()))
+}
+
+#[cfg(test)]
+mod cache_test {
+    use super::FileCache;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn lru_eviction_and_invalidation() {
+        let mut cache = FileCache::new(10, 6);
+        let t0 = Some(UNIX_EPOCH);
+        let t1 = Some(UNIX_EPOCH + Duration::from_secs(1));
+
+        cache.put(PathBuf::from("/a"), t0, Arc::new(vec![0; 4]));
+        cache.put(PathBuf::from("/b"), t0, Arc::new(vec![0; 4]));
+        cache.put(PathBuf::from("/too_large"), t0, Arc::new(vec![0; 7]));
+        assert_eq!(cache.stats().entries, 2);
+
+        // touch `/a`, so `/b` will be evicted first
+        assert!(cache.get(Path::new("/a"), t0, 4).is_some());
+        cache.put(PathBuf::from("/c"), t0, Arc::new(vec![0; 4]));
+
+        assert!(cache.get(Path::new("/b"), t0, 4).is_none());
+        assert!(cache.get(Path::new("/c"), t0, 4).is_some());
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().bytes, 8);
+
+        // modified since cached
+        assert!(cache.get(Path::new("/a"), t1, 4).is_none());
+        assert_eq!(cache.stats().invalidations, 1);
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().hits, 2);
+    }
+}
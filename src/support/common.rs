@@ -127,6 +127,24 @@ pub(crate) fn cpu_relax(count: usize) {
     }
 }
 
+/// Percent-encode the uri component, leaving the unreserved characters, and the `/` if required,
+/// as is.
+pub(crate) fn percent_encode(raw: &str, keep_slash: bool) -> String {
+    let mut result = String::with_capacity(raw.len());
+
+    for byte in raw.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            b'/' if keep_slash => result.push('/'),
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    result
+}
+
 pub fn json_stringify(contents: &HashMap<String, String>) -> String {
     let mut res: String = String::from("{");
     let mut is_first = true;
@@ -0,0 +1,104 @@
+//! The typed constraints of the route params, e.g. `/user/:id<u64>`, which are checked when the
+//! route is matched. Besides the built-in types, custom ones can be added via
+//! `Route::add_constraint`, and they take precedence over the built-in ones of the same name.
+
+use crate::chrono::NaiveDate;
+use crate::core::router::ParamConstraint;
+use crate::hashbrown::HashMap;
+use crate::parking_lot::RwLock;
+
+lazy_static! {
+    static ref CONSTRAINTS: RwLock<HashMap<String, ParamConstraint>> = RwLock::new(HashMap::new());
+}
+
+pub(crate) fn register(name: &str, check: ParamConstraint) {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        panic!(
+            "Param constraint name can only contain alpha-numeric characters or '_': {}",
+            name
+        );
+    }
+
+    CONSTRAINTS.write().insert(name.to_owned(), check);
+}
+
+pub(crate) fn find(name: &str) -> Option<ParamConstraint> {
+    if let Some(check) = CONSTRAINTS.read().get(name) {
+        return Some(*check);
+    }
+
+    builtin(name)
+}
+
+fn builtin(name: &str) -> Option<ParamConstraint> {
+    let check: ParamConstraint = match name {
+        "u64" => |v| v.parse::<u64>().is_ok() && !v.starts_with('+'),
+        "i64" => |v| v.parse::<i64>().is_ok() && !v.starts_with('+'),
+        "slug" => is_slug,
+        "uuid" => is_uuid,
+        "date" => |v| v.len() == 10 && NaiveDate::parse_from_str(v, "%Y-%m-%d").is_ok(),
+        _ => return None,
+    };
+
+    Some(check)
+}
+
+/// Lower case letters and digits, separated by single dashes, e.g. `hello-world-2`.
+fn is_slug(value: &str) -> bool {
+    !value.is_empty()
+        && value.split('-').all(|part| {
+            !part.is_empty()
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+        })
+}
+
+/// The hyphenated uuid, e.g. `123e4567-e89b-12d3-a456-426614174000`.
+fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.bytes().enumerate().all(|(pos, b)| match pos {
+            8 | 13 | 18 | 23 => b == b'-',
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+#[cfg(test)]
+mod constraint_test {
+    use super::*;
+
+    #[test]
+    fn builtin_test() {
+        let check = |name: &str, value: &str| find(name).unwrap()(value);
+
+        assert!(check("u64", "42"));
+        assert!(!check("u64", "-42"));
+        assert!(!check("u64", "+42"));
+        assert!(!check("u64", "18446744073709551616"));
+        assert!(check("i64", "-42"));
+
+        assert!(check("slug", "hello-world-2"));
+        assert!(!check("slug", "Hello-world"));
+        assert!(!check("slug", "hello--world"));
+        assert!(!check("slug", "-hello"));
+
+        assert!(check("uuid", "123e4567-E89B-12d3-a456-426614174000"));
+        assert!(!check("uuid", "123e4567e89b12d3a456426614174000"));
+
+        assert!(check("date", "2024-02-29"));
+        assert!(!check("date", "2023-02-29"));
+        assert!(!check("date", "2023-2-9"));
+
+        assert!(find("unknown").is_none());
+    }
+
+    #[test]
+    fn custom_test() {
+        register("even", |v| {
+            v.parse::<u64>().map(|n| n % 2 == 0).unwrap_or(false)
+        });
+
+        assert!(find("even").unwrap()("42"));
+        assert!(!find("even").unwrap()("7"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Keep track of the connections being served and the requests in flight, such that the server can
+//! stop accepting new connections and drain the existing ones before it's shut down, see
+//! `ServerConfig::set_shutdown_timeout`.
+
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::core::instance::{self, Instance};
+use crate::hashbrown::HashMap;
+use crate::parking_lot::Mutex;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The connections and the requests of a server instance.
+pub(crate) struct DrainState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    connections: Mutex<HashMap<usize, TcpStream>>,
+}
+
+impl DrainState {
+    pub(crate) fn new() -> Self {
+        DrainState {
+            draining: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// The outcome of shutting down the server, which is returned by `HttpServer::listen` and
+/// `HttpServer::listen_and_serve`, see `ServerConfig::set_shutdown_timeout`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownStats {
+    /// The connections still open at the deadline, which are closed by force.
+    pub closed: usize,
+    /// The requests still in flight at the deadline, whose responses are lost.
+    pub dropped: usize,
+}
+
+/// Keep the connection on the record till the guard is dropped, i.e. when the connection is done.
+pub(crate) struct ConnGuard(Arc<Instance>, Option<usize>);
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.1 {
+            self.0.drain.connections.lock().remove(&id);
+        }
+    }
+}
+
+/// Count the request as in flight till the guard is dropped, i.e. when its response is ready.
+pub(crate) struct FlightGuard(Arc<Instance>);
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.0.drain.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub(crate) fn track(stream: &TcpStream) -> ConnGuard {
+    let instance = instance::current();
+
+    match stream.try_clone() {
+        Ok(clone) => {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            instance.drain.connections.lock().insert(id, clone);
+            ConnGuard(instance, Some(id))
+        }
+        Err(_) => ConnGuard(instance, None),
+    }
+}
+
+pub(crate) fn in_flight() -> FlightGuard {
+    let instance = instance::current();
+    instance.drain.in_flight.fetch_add(1, Ordering::AcqRel);
+    FlightGuard(instance)
+}
+
+/// If the server is shutting down, in which case the connections shall not be kept alive.
+#[inline]
+pub(crate) fn is_draining() -> bool {
+    instance::current().drain.draining.load(Ordering::Acquire)
+}
+
+/// Wait for the requests in flight to complete, up to the timeout, then close the connections left
+/// by force. Once no request is in flight, the idle connections are closed for reading, such that
+/// they're done as soon as the responses on the way are written.
+pub(crate) fn drain(timeout: Duration) -> ShutdownStats {
+    let instance = instance::current();
+    let state = &instance.drain;
+    state.draining.store(true, Ordering::Release);
+
+    let deadline = Instant::now() + timeout;
+    let mut stats = ShutdownStats::default();
+
+    loop {
+        if state.connections.lock().is_empty() {
+            break;
+        }
+
+        if state.in_flight.load(Ordering::Acquire) == 0 {
+            shutdown_all(state, Shutdown::Read);
+        }
+
+        if Instant::now() >= deadline {
+            stats.dropped = state.in_flight.load(Ordering::Acquire);
+            stats.closed = shutdown_all(state, Shutdown::Both);
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    state.draining.store(false, Ordering::Release);
+    stats
+}
+
+fn shutdown_all(state: &DrainState, how: Shutdown) -> usize {
+    let connections = state.connections.lock();
+    for stream in connections.values() {
+        // the stream may have been closed by the client already
+        stream.shutdown(how).unwrap_or_default();
+    }
+
+    connections.len()
+}
+
+#[cfg(test)]
+mod drain_test {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn drain_deadline_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let instance = Instance::new();
+        let _scope = instance::enter(&instance);
+
+        let conn = track(&stream);
+        let flight = in_flight();
+
+        // the request never completes, so the connection is closed by force at the deadline
+        let stats = drain(Duration::from_millis(50));
+        assert_eq!(
+            stats,
+            ShutdownStats {
+                closed: 1,
+                dropped: 1
+            }
+        );
+        assert!(!is_draining());
+
+        let mut buf = [0u8; 8];
+        assert_eq!(client.read(&mut buf).unwrap_or_default(), 0);
+
+        drop(flight);
+        drop(conn);
+        assert!(instance.drain.connections.lock().is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! The glob patterns used by the static folder rules, in the similar fashion as `.gitignore`: a
+//! pattern without a `/` matches the file or folder name at any level, e.g. `*.map` or `.git`,
+//! otherwise it matches the path from the static folder root, e.g. `assets/**/*.js`.
+//!
+//! Supported syntax: `*` matches anything but `/`, `**` matches anything including `/`, `?` matches
+//! a single character but `/`, and `[...]` matches a character class. Patterns are case-insensitive,
+//! unless built otherwise.
+
+use crate::regex::Regex;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Glob {
+    regex: Regex,
+    basename: bool,
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Option<Glob> {
+        Glob::build(pattern, false)
+    }
+
+    /// The glob pattern which is case-sensitive if required, e.g. for the route segments.
+    pub(crate) fn build(pattern: &str, case_sensitive: bool) -> Option<Glob> {
+        let pattern = pattern.trim().trim_start_matches('/').trim_end_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let mut source = String::from(if case_sensitive { "^" } else { "(?i)^" });
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+
+                    if chars.peek() == Some(&'/') {
+                        // `**/` also matches zero folders
+                        chars.next();
+                        source.push_str("(?:.*/)?");
+                    } else {
+                        source.push_str(".*");
+                    }
+                }
+                '*' => source.push_str("[^/]*"),
+                '?' => source.push_str("[^/]"),
+                '[' => {
+                    source.push('[');
+
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        source.push('^');
+                    }
+
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some('\\') => source.push_str("\\\\"),
+                            Some(c) => source.push(c),
+                            None => return None,
+                        }
+                    }
+
+                    source.push(']');
+                }
+                _ => source.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+
+        source.push('$');
+
+        Some(Glob {
+            regex: Regex::new(&source).ok()?,
+            basename: !pattern.contains('/'),
+        })
+    }
+
+    /// Match against the `/` separated path relative to the static folder root.
+    pub(crate) fn is_match(&self, path: &str) -> bool {
+        if self.basename {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            return self.regex.is_match(name);
+        }
+
+        self.regex.is_match(path)
+    }
+}
+
+#[cfg(test)]
+mod glob_test {
+    use super::Glob;
+
+    #[test]
+    fn glob_match_test() {
+        let ext = Glob::new("*.TXT").unwrap();
+        assert!(ext.is_match("a.txt"));
+        assert!(ext.is_match("docs/a.txt"));
+        assert!(!ext.is_match("a.txt.bak"));
+
+        let deep = Glob::new("/assets/**/*.js").unwrap();
+        assert!(deep.is_match("assets/app.js"));
+        assert!(deep.is_match("assets/lib/vendor/x.js"));
+        assert!(!deep.is_match("public/assets/app.js"));
+
+        let folder = Glob::new("private/*").unwrap();
+        assert!(folder.is_match("private/key.pem"));
+        assert!(!folder.is_match("private/sub/key.pem"));
+
+        let class = Glob::new("file[0-9].?s").unwrap();
+        assert!(class.is_match("file1.js"));
+        assert!(!class.is_match("filea.js"));
+
+        assert!(Glob::new("[abc").is_none());
+        assert!(Glob::new("/").is_none());
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::VecDeque;
+
+const ENTRY_OVERHEAD: usize = 32;
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) enum HpackError {
+    InvalidIndex,
+    InvalidInteger,
+    InvalidHuffman,
+    InvalidTableSize,
+    Truncated,
+}
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The canonical huffman code table from RFC 7541, Appendix B, as (code, bit length) pairs indexed
+/// by the symbol. The last entry is the EOS symbol.
+#[rustfmt::skip]
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7f_ffd8, 23), (0xfff_ffe2, 28), (0xfff_ffe3, 28),
+    (0xfff_ffe4, 28), (0xfff_ffe5, 28), (0xfff_ffe6, 28), (0xfff_ffe7, 28),
+    (0xfff_ffe8, 28), (0xff_ffea, 24), (0x3fff_fffc, 30), (0xfff_ffe9, 28),
+    (0xfff_ffea, 28), (0x3fff_fffd, 30), (0xfff_ffeb, 28), (0xfff_ffec, 28),
+    (0xfff_ffed, 28), (0xfff_ffee, 28), (0xfff_ffef, 28), (0xfff_fff0, 28),
+    (0xfff_fff1, 28), (0xfff_fff2, 28), (0x3fff_fffe, 30), (0xfff_fff3, 28),
+    (0xfff_fff4, 28), (0xfff_fff5, 28), (0xfff_fff6, 28), (0xfff_fff7, 28),
+    (0xfff_fff8, 28), (0xfff_fff9, 28), (0xfff_fffa, 28), (0xfff_fffb, 28),
+    (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11),
+    (0x3fa, 10), (0x3fb, 10), (0xf9, 8), (0x7fb, 11),
+    (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6),
+    (0x1a, 6), (0x1b, 6), (0x1c, 6), (0x1d, 6),
+    (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10),
+    (0x1ffa, 13), (0x21, 6), (0x5d, 7), (0x5e, 7),
+    (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7),
+    (0x67, 7), (0x68, 7), (0x69, 7), (0x6a, 7),
+    (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7),
+    (0xfc, 8), (0x73, 7), (0xfd, 8), (0x1ffb, 13),
+    (0x7_fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5),
+    (0x24, 6), (0x5, 5), (0x25, 6), (0x26, 6),
+    (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5),
+    (0x2b, 6), (0x76, 7), (0x2c, 6), (0x8, 5),
+    (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15),
+    (0x7fc, 11), (0x3ffd, 14), (0x1ffd, 13), (0xfff_fffc, 28),
+    (0xf_ffe6, 20), (0x3f_ffd2, 22), (0xf_ffe7, 20), (0xf_ffe8, 20),
+    (0x3f_ffd3, 22), (0x3f_ffd4, 22), (0x3f_ffd5, 22), (0x7f_ffd9, 23),
+    (0x3f_ffd6, 22), (0x7f_ffda, 23), (0x7f_ffdb, 23), (0x7f_ffdc, 23),
+    (0x7f_ffdd, 23), (0x7f_ffde, 23), (0xff_ffeb, 24), (0x7f_ffdf, 23),
+    (0xff_ffec, 24), (0xff_ffed, 24), (0x3f_ffd7, 22), (0x7f_ffe0, 23),
+    (0xff_ffee, 24), (0x7f_ffe1, 23), (0x7f_ffe2, 23), (0x7f_ffe3, 23),
+    (0x7f_ffe4, 23), (0x1f_ffdc, 21), (0x3f_ffd8, 22), (0x7f_ffe5, 23),
+    (0x3f_ffd9, 22), (0x7f_ffe6, 23), (0x7f_ffe7, 23), (0xff_ffef, 24),
+    (0x3f_ffda, 22), (0x1f_ffdd, 21), (0xf_ffe9, 20), (0x3f_ffdb, 22),
+    (0x3f_ffdc, 22), (0x7f_ffe8, 23), (0x7f_ffe9, 23), (0x1f_ffde, 21),
+    (0x7f_ffea, 23), (0x3f_ffdd, 22), (0x3f_ffde, 22), (0xff_fff0, 24),
+    (0x1f_ffdf, 21), (0x3f_ffdf, 22), (0x7f_ffeb, 23), (0x7f_ffec, 23),
+    (0x1f_ffe0, 21), (0x1f_ffe1, 21), (0x3f_ffe0, 22), (0x1f_ffe2, 21),
+    (0x7f_ffed, 23), (0x3f_ffe1, 22), (0x7f_ffee, 23), (0x7f_ffef, 23),
+    (0xf_ffea, 20), (0x3f_ffe2, 22), (0x3f_ffe3, 22), (0x3f_ffe4, 22),
+    (0x7f_fff0, 23), (0x3f_ffe5, 22), (0x3f_ffe6, 22), (0x7f_fff1, 23),
+    (0x3ff_ffe0, 26), (0x3ff_ffe1, 26), (0xf_ffeb, 20), (0x7_fff1, 19),
+    (0x3f_ffe7, 22), (0x7f_fff2, 23), (0x3f_ffe8, 22), (0x1ff_ffec, 25),
+    (0x3ff_ffe2, 26), (0x3ff_ffe3, 26), (0x3ff_ffe4, 26), (0x7ff_ffde, 27),
+    (0x7ff_ffdf, 27), (0x3ff_ffe5, 26), (0xff_fff1, 24), (0x1ff_ffed, 25),
+    (0x7_fff2, 19), (0x1f_ffe3, 21), (0x3ff_ffe6, 26), (0x7ff_ffe0, 27),
+    (0x7ff_ffe1, 27), (0x3ff_ffe7, 26), (0x7ff_ffe2, 27), (0xff_fff2, 24),
+    (0x1f_ffe4, 21), (0x1f_ffe5, 21), (0x3ff_ffe8, 26), (0x3ff_ffe9, 26),
+    (0xfff_fffd, 28), (0x7ff_ffe3, 27), (0x7ff_ffe4, 27), (0x7ff_ffe5, 27),
+    (0xf_ffec, 20), (0xff_fff3, 24), (0xf_ffed, 20), (0x1f_ffe6, 21),
+    (0x3f_ffe9, 22), (0x1f_ffe7, 21), (0x1f_ffe8, 21), (0x7f_fff3, 23),
+    (0x3f_ffea, 22), (0x3f_ffeb, 22), (0x1ff_ffee, 25), (0x1ff_ffef, 25),
+    (0xff_fff4, 24), (0xff_fff5, 24), (0x3ff_ffea, 26), (0x7f_fff4, 23),
+    (0x3ff_ffeb, 26), (0x7ff_ffe6, 27), (0x3ff_ffec, 26), (0x3ff_ffed, 26),
+    (0x7ff_ffe7, 27), (0x7ff_ffe8, 27), (0x7ff_ffe9, 27), (0x7ff_ffea, 27),
+    (0x7ff_ffeb, 27), (0xfff_fffe, 28), (0x7ff_ffec, 27), (0x7ff_ffed, 27),
+    (0x7ff_ffee, 27), (0x7ff_ffef, 27), (0x7ff_fff0, 27), (0x3ff_ffee, 26),
+    (0x3fff_ffff, 30),
+];
+
+lazy_static! {
+    /// The huffman decoding tree, where each node holds the next node index for the bit 0 and 1,
+    /// and a leaf is encoded as the negated symbol offset by 1.
+    static ref HUFFMAN_TREE: Vec<[i32; 2]> = build_huffman_tree();
+}
+
+fn build_huffman_tree() -> Vec<[i32; 2]> {
+    let mut tree: Vec<[i32; 2]> = vec![[0, 0]];
+
+    for (sym, &(code, len)) in HUFFMAN_CODES.iter().enumerate() {
+        let mut node = 0usize;
+
+        for shift in (0..len).rev() {
+            let bit = ((code >> shift) & 1) as usize;
+
+            if shift == 0 {
+                tree[node][bit] = -(sym as i32) - 1;
+                break;
+            }
+
+            if tree[node][bit] == 0 {
+                tree.push([0, 0]);
+                tree[node][bit] = (tree.len() - 1) as i32;
+            }
+
+            node = tree[node][bit] as usize;
+        }
+    }
+
+    tree
+}
+
+fn huffman_decode(src: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let tree = &*HUFFMAN_TREE;
+    let mut out = Vec::with_capacity(src.len() * 8 / 5);
+    let mut node = 0usize;
+
+    // bits consumed since the last complete symbol, and if they're all 1s (i.e. a valid padding)
+    let mut pending = 0u8;
+    let mut all_ones = true;
+
+    for byte in src {
+        for shift in (0..8).rev() {
+            let bit = ((byte >> shift) & 1) as usize;
+            let next = tree[node][bit];
+
+            pending += 1;
+            all_ones &= bit == 1;
+
+            if next < 0 {
+                let sym = (-next - 1) as usize;
+                if sym == 256 {
+                    // an EOS symbol in the string is a decoding error
+                    return Err(HpackError::InvalidHuffman);
+                }
+
+                out.push(sym as u8);
+                node = 0;
+                pending = 0;
+                all_ones = true;
+            } else if next == 0 {
+                return Err(HpackError::InvalidHuffman);
+            } else {
+                node = next as usize;
+            }
+        }
+    }
+
+    // padding must be strictly shorter than 8 bits and correspond to the MSBs of the EOS symbol
+    if pending > 7 || !all_ones {
+        return Err(HpackError::InvalidHuffman);
+    }
+
+    Ok(out)
+}
+
+fn decode_integer(src: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+    if *pos >= src.len() {
+        return Err(HpackError::Truncated);
+    }
+
+    let mask = ((1u16 << prefix) - 1) as u8;
+    let mut value = (src[*pos] & mask) as usize;
+    *pos += 1;
+
+    if value < mask as usize {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        if *pos >= src.len() {
+            return Err(HpackError::Truncated);
+        }
+
+        let byte = src[*pos];
+        *pos += 1;
+
+        if shift > 28 {
+            return Err(HpackError::InvalidInteger);
+        }
+
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn encode_integer(value: usize, prefix: u8, flags: u8, dst: &mut Vec<u8>) {
+    let mask = ((1u16 << prefix) - 1) as usize;
+
+    if value < mask {
+        dst.push(flags | value as u8);
+        return;
+    }
+
+    dst.push(flags | mask as u8);
+
+    let mut rest = value - mask;
+    while rest >= 128 {
+        dst.push((rest % 128) as u8 | 0x80);
+        rest /= 128;
+    }
+
+    dst.push(rest as u8);
+}
+
+fn decode_string(src: &[u8], pos: &mut usize) -> Result<String, HpackError> {
+    if *pos >= src.len() {
+        return Err(HpackError::Truncated);
+    }
+
+    let is_huffman = src[*pos] & 0x80 == 0x80;
+    let len = decode_integer(src, pos, 7)?;
+
+    if *pos + len > src.len() {
+        return Err(HpackError::Truncated);
+    }
+
+    let raw = &src[*pos..*pos + len];
+    *pos += len;
+
+    let bytes = if is_huffman {
+        huffman_decode(raw)?
+    } else {
+        raw.to_vec()
+    };
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn encode_string(value: &str, dst: &mut Vec<u8>) {
+    encode_integer(value.len(), 7, 0, dst);
+    dst.extend_from_slice(value.as_bytes());
+}
+
+/// The HPACK decoder, which shall live as long as the connection does, since the dynamic table is
+/// shared by all header blocks received on the connection.
+pub(crate) struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    size_limit: usize,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+            size_limit: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    pub(crate) fn decode(&mut self, src: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut headers = Vec::new();
+        let mut pos = 0;
+
+        while pos < src.len() {
+            let byte = src[pos];
+
+            if byte & 0x80 == 0x80 {
+                // indexed header field
+                let index = decode_integer(src, &mut pos, 7)?;
+                headers.push(self.entry(index)?);
+            } else if byte & 0xc0 == 0x40 {
+                // literal header field with incremental indexing
+                let (name, value) = self.decode_literal(src, &mut pos, 6)?;
+                self.insert(name.clone(), value.clone());
+                headers.push((name, value));
+            } else if byte & 0xe0 == 0x20 {
+                // dynamic table size update
+                let size = decode_integer(src, &mut pos, 5)?;
+                if size > self.size_limit {
+                    return Err(HpackError::InvalidTableSize);
+                }
+
+                self.max_size = size;
+                self.evict(0);
+            } else {
+                // literal header field without indexing, or never indexed
+                headers.push(self.decode_literal(src, &mut pos, 4)?);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn decode_literal(
+        &self,
+        src: &[u8],
+        pos: &mut usize,
+        prefix: u8,
+    ) -> Result<(String, String), HpackError> {
+        let index = decode_integer(src, pos, prefix)?;
+
+        let name = if index == 0 {
+            decode_string(src, pos)?
+        } else {
+            self.entry(index)?.0
+        };
+
+        Ok((name, decode_string(src, pos)?))
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), HpackError> {
+        if index == 0 {
+            return Err(HpackError::InvalidIndex);
+        }
+
+        if index <= STATIC_TABLE.len() {
+            let (name, value) = STATIC_TABLE[index - 1];
+            return Ok((name.to_owned(), value.to_owned()));
+        }
+
+        match self.table.get(index - STATIC_TABLE.len() - 1) {
+            Some(entry) => Ok(entry.clone()),
+            None => Err(HpackError::InvalidIndex),
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+
+        // an entry larger than the table empties the table, and won't be stored
+        self.evict(size);
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front((name, value));
+        }
+    }
+
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            match self.table.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
+
+/// The HPACK encoder. We never add entries to the peer's dynamic table, such that the encoder is
+/// stateless: fields are either indexed from the static table, or sent as literals without indexing.
+pub(crate) struct Encoder;
+
+impl Encoder {
+    pub(crate) fn encode(headers: &[(String, String)]) -> Vec<u8> {
+        let mut dst = Vec::with_capacity(headers.len() * 32);
+
+        for (name, value) in headers.iter() {
+            let mut name_index = 0;
+            let mut field_index = 0;
+
+            for (idx, &(s_name, s_value)) in STATIC_TABLE.iter().enumerate() {
+                if s_name != name {
+                    continue;
+                }
+
+                if s_value == value {
+                    field_index = idx + 1;
+                    break;
+                }
+
+                if name_index == 0 {
+                    name_index = idx + 1;
+                }
+            }
+
+            if field_index > 0 {
+                // the entire field is in the static table
+                encode_integer(field_index, 7, 0x80, &mut dst);
+                continue;
+            }
+
+            if name_index > 0 {
+                encode_integer(name_index, 4, 0, &mut dst);
+            } else {
+                dst.push(0);
+                encode_string(name, &mut dst);
+            }
+
+            encode_string(value, &mut dst);
+        }
+
+        dst
+    }
+}
+
+#[cfg(test)]
+mod hpack_test {
+    use super::{huffman_decode, Decoder, Encoder, HUFFMAN_CODES};
+
+    fn hex(src: &str) -> Vec<u8> {
+        let src: String = src.chars().filter(|c| !c.is_whitespace()).collect();
+        (0..src.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&src[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn pairs(src: &[(&str, &str)]) -> Vec<(String, String)> {
+        src.iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn huffman_table_is_canonical() {
+        let mut symbols: Vec<usize> = (0..HUFFMAN_CODES.len()).collect();
+        symbols.sort_by_key(|&sym| (HUFFMAN_CODES[sym].1, sym));
+
+        let mut code = 0u32;
+        let mut len = HUFFMAN_CODES[symbols[0]].1;
+
+        for sym in symbols {
+            let (actual, bits) = HUFFMAN_CODES[sym];
+            code <<= bits - len;
+            len = bits;
+
+            assert_eq!(actual, code, "Unexpected huffman code for symbol: {}", sym);
+            code += 1;
+        }
+    }
+
+    #[test]
+    fn huffman_strings() {
+        let cases = [
+            ("f1e3 c2e5 f23a 6ba0 ab90 f4ff", "www.example.com"),
+            ("a8eb 1064 9cbf", "no-cache"),
+            ("25a8 49e9 5ba9 7d7f", "custom-key"),
+            ("25a8 49e9 5bb8 e8b4 bf", "custom-value"),
+        ];
+
+        for (encoded, plain) in cases.iter() {
+            assert_eq!(huffman_decode(&hex(encoded)).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn decode_requests_with_huffman() {
+        // RFC 7541, Appendix C.4
+        let mut decoder = Decoder::new();
+
+        let first = decoder
+            .decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+            .unwrap();
+        assert_eq!(
+            first,
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+
+        let second = decoder
+            .decode(&hex("8286 84be 5886 a8eb 1064 9cbf"))
+            .unwrap();
+        assert_eq!(
+            second,
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+
+        let third = decoder
+            .decode(&hex(
+                "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+            ))
+            .unwrap();
+        assert_eq!(
+            third,
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ])
+        );
+    }
+
+    #[test]
+    fn encoder_round_trip() {
+        let headers = pairs(&[
+            (":status", "200"),
+            (":status", "302"),
+            ("content-type", "text/html"),
+            ("x-powered-by", "rusty-express"),
+        ]);
+
+        let encoded = Encoder::encode(&headers);
+        assert_eq!(encoded[0], 0x88);
+        assert_eq!(Decoder::new().decode(&encoded).unwrap(), headers);
+    }
+}
@@ -0,0 +1,372 @@
+//! Cap the connections served at the same time, in total and per client IP, such that a burst of
+//! connections, e.g. the idle ones, can't take over all the workers, see
+//! `ServerConfig::set_max_connections`.
+
+use std::net::{IpAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core::instance::{self, Instance};
+use crate::hashbrown::HashMap;
+use crate::parking_lot::{Condvar, Mutex};
+
+/// What to do with a new connection when the server is at its connection limits, which is decided
+/// by the worker taking the connection, rather than the listener accepting it. The TLS
+/// connections are reset instead of being responded with a `503`, since no handshake is made for
+/// them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Hold the connection till a slot is freed, up to the timeout, before responding with a
+    /// `503 Service Unavailable`. The worker is held by the connection while waiting. A client at
+    /// its per-IP limit is never queued, which would keep the others waiting on it, but responded
+    /// with the `503` right away.
+    Queue(Duration),
+    /// Respond with a `503 Service Unavailable` right away, which is the default.
+    #[default]
+    Reject,
+    /// Reset the connection without a response, which costs the least.
+    Reset,
+}
+
+/// The statistics of the connections of a server, which can be obtained via
+/// `HttpServer::connection_stats`, or `AsyncController::connection_stats` while it's running.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionStats {
+    /// The number of connections currently served.
+    pub active: usize,
+    /// The number of connections accepted and served, including the ones queued first.
+    pub accepted: u64,
+    /// The number of connections held in the queue till a slot is freed.
+    pub queued: u64,
+    /// The number of connections turned down with a `503`, including the ones timed out in queue.
+    pub rejected: u64,
+    /// The number of connections reset.
+    pub reset: u64,
+}
+
+/// The connection slots of a server instance.
+pub(crate) struct LimitState {
+    slots: Mutex<Slots>,
+    freed: Condvar,
+    accepted: AtomicU64,
+    queued: AtomicU64,
+    rejected: AtomicU64,
+    reset: AtomicU64,
+}
+
+#[derive(Default)]
+struct Slots {
+    active: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl LimitState {
+    pub(crate) fn new() -> Self {
+        LimitState {
+            slots: Mutex::new(Slots::default()),
+            freed: Condvar::new(),
+            accepted: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            reset: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        let active = self.slots.lock().active;
+
+        ConnectionStats {
+            active,
+            accepted: self.accepted.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            reset: self.reset.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The limits on the connections, where 0 means no limit.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Limits {
+    pub(crate) max: usize,
+    pub(crate) max_per_ip: usize,
+    pub(crate) policy: OverflowPolicy,
+}
+
+/// Hold the slot of the connection till the guard is dropped, i.e. when the connection is done.
+pub(crate) struct SlotGuard(Arc<Instance>, Option<IpAddr>);
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let state = &self.0.limits;
+        let mut slots = state.slots.lock();
+        slots.active = slots.active.saturating_sub(1);
+
+        if let Some(ip) = self.1 {
+            let gone = match slots.per_ip.get_mut(&ip) {
+                Some(count) => {
+                    *count -= 1;
+                    *count == 0
+                }
+                None => false,
+            };
+
+            if gone {
+                slots.per_ip.remove(&ip);
+            }
+        }
+
+        drop(slots);
+        state.freed.notify_one();
+    }
+}
+
+/// How the connection over the limits shall be closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Overflow {
+    Reject,
+    Reset,
+}
+
+/// Take a slot for the connection, or tell how to close it if the server is at its limits, in
+/// which case the connection has been counted as rejected or reset already.
+pub(crate) fn admit(stream: &TcpStream, limits: Limits) -> Result<SlotGuard, Overflow> {
+    let instance = instance::current();
+    let state = &instance.limits;
+
+    // the per-IP limit is skipped if the peer is unknown, e.g. it's gone already
+    let ip = match limits.max_per_ip {
+        0 => None,
+        _ => stream.peer_addr().ok().map(|addr| addr.ip()),
+    };
+
+    let mut slots = state.slots.lock();
+    let mut deadline = None;
+
+    let fits = loop {
+        let ip_full = ip.is_some_and(|ip| {
+            slots
+                .per_ip
+                .get(&ip)
+                .is_some_and(|count| *count >= limits.max_per_ip)
+        });
+
+        if ip_full {
+            break false;
+        }
+
+        if limits.max == 0 || slots.active < limits.max {
+            break true;
+        }
+
+        let timeout = match limits.policy {
+            OverflowPolicy::Queue(timeout) => timeout,
+            _ => break false,
+        };
+
+        let until = *deadline.get_or_insert_with(|| {
+            state.queued.fetch_add(1, Ordering::Relaxed);
+            Instant::now() + timeout
+        });
+
+        if state.freed.wait_until(&mut slots, until).timed_out() {
+            // a slot may be freed right at the deadline
+            break slots.active < limits.max;
+        }
+    };
+
+    if !fits {
+        drop(slots);
+
+        return Err(match limits.policy {
+            OverflowPolicy::Reset => {
+                state.reset.fetch_add(1, Ordering::Relaxed);
+                Overflow::Reset
+            }
+            _ => {
+                state.rejected.fetch_add(1, Ordering::Relaxed);
+                Overflow::Reject
+            }
+        });
+    }
+
+    slots.active += 1;
+    if let Some(ip) = ip {
+        *slots.per_ip.entry(ip).or_insert(0) += 1;
+    }
+
+    drop(slots);
+    state.accepted.fetch_add(1, Ordering::Relaxed);
+
+    Ok(SlotGuard(instance, ip))
+}
+
+/// Close the connection with a TCP reset, by lingering for no time. Where it's not supported, the
+/// connection is closed as usual.
+pub(crate) fn reset(stream: TcpStream) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let linger = libc::linger {
+            l_onoff: 1,
+            l_linger: 0,
+        };
+
+        unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_LINGER,
+                &linger as *const libc::linger as *const libc::c_void,
+                std::mem::size_of::<libc::linger>() as libc::socklen_t,
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    stream
+        .shutdown(std::net::Shutdown::Both)
+        .unwrap_or_default();
+
+    drop(stream);
+}
+
+#[cfg(test)]
+mod limits_test {
+    use super::*;
+    use crate::core::server::HttpServer;
+    use crate::core::states::{AsyncController, ControlMessage};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn connect(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (client, stream)
+    }
+
+    #[test]
+    fn admit_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let instance = Instance::new();
+        let _scope = instance::enter(&instance);
+
+        let limits = Limits {
+            max: 2,
+            max_per_ip: 1,
+            policy: OverflowPolicy::Queue(Duration::from_millis(500)),
+        };
+
+        let (_c1, first) = connect(&listener);
+        let (_c2, second) = connect(&listener);
+
+        let slot = admit(&first, limits).unwrap();
+
+        // the client is at its per-IP limit, so it's not queued
+        assert_eq!(admit(&second, limits).err(), Some(Overflow::Reject));
+
+        // queued till the first connection is done
+        let limits = Limits {
+            max: 1,
+            max_per_ip: 0,
+            policy: OverflowPolicy::Queue(Duration::from_secs(5)),
+        };
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(slot);
+        });
+
+        let slot = admit(&second, limits).unwrap();
+        release.join().unwrap();
+
+        let limits = Limits {
+            max: 1,
+            max_per_ip: 0,
+            policy: OverflowPolicy::Reset,
+        };
+
+        assert_eq!(admit(&first, limits).err(), Some(Overflow::Reset));
+        drop(slot);
+
+        let stats = instance.limits.stats();
+        assert_eq!(stats.active, 0);
+        assert_eq!(
+            (stats.accepted, stats.queued, stats.rejected, stats.reset),
+            (2, 1, 1, 1)
+        );
+    }
+
+    fn terminate_later(courier: AsyncController) {
+        thread::sleep(Duration::from_millis(500));
+        courier.send(ControlMessage::Terminate).unwrap_or_default();
+    }
+
+    fn connect_to(port: u16) -> TcpStream {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+                return stream;
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        panic!("The server on port {} is not up", port);
+    }
+
+    #[test]
+    fn servers_test() {
+        let listeners: Vec<TcpListener> = (0..2)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().port())
+            .collect();
+        drop(listeners);
+
+        let servers: Vec<_> = ports
+            .iter()
+            .map(|port| {
+                let port = *port;
+                thread::spawn(move || {
+                    let mut server = HttpServer::new();
+                    server.config().set_max_connections(1);
+                    server.listen_and_serve(port, Some(terminate_later));
+                    server
+                })
+            })
+            .collect();
+
+        // the first server is at its limit with an idle connection, and the next one is rejected
+        let idle = connect_to(ports[0]);
+        thread::sleep(Duration::from_millis(100));
+
+        let mut rejected = connect_to(ports[0]);
+        let mut resp = String::new();
+        rejected.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 503"));
+
+        // the other server is not affected
+        let mut other = connect_to(ports[1]);
+        other
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut resp = String::new();
+        other.read_to_string(&mut resp).unwrap_or_default();
+        assert!(resp.starts_with("HTTP/1.1 404"));
+        drop(idle);
+
+        let stats: Vec<ConnectionStats> = servers
+            .into_iter()
+            .map(|handle| handle.join().unwrap().connection_stats())
+            .collect();
+
+        assert_eq!((stats[0].accepted, stats[0].rejected), (1, 1));
+        assert_eq!((stats[1].accepted, stats[1].rejected), (1, 0));
+        assert!(stats.iter().all(|s| s.active == 0));
+    }
+}
@@ -0,0 +1,151 @@
+//! Rendering the listing of a static folder, either as a html page or as a json array.
+
+use std::cmp::Ordering;
+
+use crate::support::common::percent_encode;
+
+#[derive(PartialEq, Eq)]
+pub(crate) struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+}
+
+impl Entry {
+    pub(crate) fn new(name: String, is_dir: bool, size: u64) -> Self {
+        Entry { name, is_dir, size }
+    }
+}
+
+impl Ord for Entry {
+    /// Folders go first, then the files, and each group is sorted by the names.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .is_dir
+            .cmp(&self.is_dir)
+            .then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub(crate) fn html(uri: &str, entries: &[Entry]) -> String {
+    let base = uri.trim_end_matches('/');
+    let title = escape_html(&[base, "/"].join(""));
+
+    let mut page = format!(
+        "<!DOCTYPE html>\r\n<html><head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\
+         <body><h1>Index of {0}</h1><ul>",
+        title
+    );
+
+    if !base.is_empty() {
+        let parent = match base.rfind('/') {
+            Some(pos) if pos > 0 => &base[..pos],
+            _ => "",
+        };
+
+        page.push_str(&format!(
+            "<li><a href=\"{}/\">../</a></li>",
+            escape_html(&percent_encode(parent, true))
+        ));
+    }
+
+    for entry in entries.iter() {
+        let suffix = if entry.is_dir { "/" } else { "" };
+
+        page.push_str(&format!(
+            "<li><a href=\"{}/{}{}\">{}{}</a></li>",
+            escape_html(&percent_encode(base, true)),
+            escape_html(&percent_encode(&entry.name, false)),
+            suffix,
+            escape_html(&entry.name),
+            suffix
+        ));
+    }
+
+    page.push_str("</ul></body></html>");
+    page
+}
+
+pub(crate) fn json(entries: &[Entry]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{{\"name\":\"{}\",\"type\":\"{}\",\"size\":{}}}",
+                escape_json(&entry.name),
+                if entry.is_dir { "dir" } else { "file" },
+                if entry.is_dir { 0 } else { entry.size }
+            )
+        })
+        .collect();
+
+    ["[", &items.join(","), "]"].join("")
+}
+
+fn escape_html(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+
+    for c in raw.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn escape_json(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+
+    for c in raw.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod listing_test {
+    use super::*;
+
+    #[test]
+    fn render_test() {
+        let mut entries = vec![
+            Entry::new(String::from("b.txt"), false, 5),
+            Entry::new(String::from("a <b>.txt"), false, 7),
+            Entry::new(String::from("sub"), true, 4096),
+        ];
+        entries.sort();
+
+        assert_eq!(
+            json(&entries),
+            "[{\"name\":\"sub\",\"type\":\"dir\",\"size\":0},\
+             {\"name\":\"a <b>.txt\",\"type\":\"file\",\"size\":7},\
+             {\"name\":\"b.txt\",\"type\":\"file\",\"size\":5}]"
+        );
+
+        let page = html("/docs", &entries);
+        assert!(page.contains("<a href=\"/\">../</a>"));
+        assert!(page.contains("<a href=\"/docs/sub/\">sub/</a>"));
+        assert!(page.contains("<a href=\"/docs/a%20%3Cb%3E.txt\">a &lt;b&gt;.txt</a>"));
+    }
+}
@@ -0,0 +1,205 @@
+//! The built-in MIME types, the `mime.types` parser and the content sniffing for files without an
+//! extension. The runtime mappings are kept with the connection metadata, see
+//! `ServerConfig::set_mime_type`.
+
+/// The default MIME type if we can't figure out a better one.
+pub(crate) const OCTET_STREAM: &str = "application/octet-stream";
+
+/// The built-in MIME type of the file extension, which shall be in lower case.
+pub(crate) fn builtin(ext: &str) -> Option<&'static str> {
+    let mime = match ext {
+        "7z" => "application/x-7z-compressed",
+        "aac" => "audio/aac",
+        "abw" => "application/x-abiword",
+        "arc" | "bin" => "application/octet-stream",
+        "avi" => "video/x-msvideo",
+        "avif" => "image/avif",
+        "azw" => "application/vnd.amazon.ebook",
+        "bmp" => "image/bmp",
+        "bz" => "application/x-bzip",
+        "bz2" => "application/x-bzip2",
+        "csh" => "application/x-csh",
+        "css" | "scss" | "sass" | "less" => "text/css",
+        "csv" => "text/csv",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "eot" => "application/vnd.ms-fontobject",
+        "epub" => "application/epub+zip",
+        "gif" => "image/gif",
+        "gz" => "application/gzip",
+        "htm" | "html" => "text/html",
+        "ico" => "image/x-icon",
+        "ics" => "text/calendar",
+        "jar" => "application/java-archive",
+        "jpeg" | "jpg" => "image/jpeg",
+        "js" | "jsx" | "mjs" => "application/javascript",
+        "json" | "map" => "application/json",
+        "md" => "text/markdown",
+        "mid" | "midi" => "audio/midi",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "mpeg" => "video/mpeg",
+        "mpkg" => "application/vnd.apple.installer+xml",
+        "odp" => "application/vnd.oasis.opendocument.presentation",
+        "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "oga" | "ogg" => "audio/ogg",
+        "ogv" => "video/ogg",
+        "ogx" => "application/ogg",
+        "otf" => "font/otf",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "rar" => "application/x-rar-compressed",
+        "rtf" => "application/rtf",
+        "sh" => "application/x-sh",
+        "svg" => "image/svg+xml",
+        "swf" => "application/x-shockwave-flash",
+        "tar" => "application/x-tar",
+        "tif" | "tiff" => "image/tiff",
+        "ts" => "application/typescript",
+        "ttf" => "font/ttf",
+        "txt" => "text/plain",
+        "vsd" => "application/vnd.visio",
+        "wasm" => "application/wasm",
+        "wav" => "audio/wav",
+        "weba" => "audio/webm",
+        "webm" => "video/webm",
+        "webmanifest" => "application/manifest+json",
+        "webp" => "image/webp",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "xhtml" => "application/xhtml+xml",
+        "xml" => "application/xml",
+        "xul" => "application/vnd.mozilla.xul+xml",
+        "zip" => "application/zip",
+        _ => return None,
+    };
+
+    Some(mime)
+}
+
+/// If the charset parameter shall be added to the MIME type, i.e. the textual types.
+pub(crate) fn is_text(mime: &str) -> bool {
+    if mime.contains(';') {
+        // the parameters are given already
+        return false;
+    }
+
+    mime.starts_with("text/")
+        || mime == "application/javascript"
+        || mime == "application/xml"
+        || mime == "application/xhtml+xml"
+        || mime == "image/svg+xml"
+}
+
+/// Parse the content of a `mime.types` file, where each line is a MIME type followed by its file
+/// extensions, e.g. `text/html  html htm`, and `#` starts a comment.
+pub(crate) fn parse(content: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    for line in content.lines() {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+
+        let mut parts = line.split_whitespace();
+        let mime = match parts.next() {
+            Some(m) if m.contains('/') => m,
+            _ => continue,
+        };
+
+        for ext in parts {
+            let ext = ext.trim_start_matches('.').trim_end_matches(';');
+            if !ext.is_empty() {
+                result.push((ext.to_lowercase(), mime.to_owned()));
+            }
+        }
+    }
+
+    result
+}
+
+/// Guess the MIME type from the leading bytes of the file content.
+pub(crate) fn sniff(head: &[u8]) -> &'static str {
+    const SIGNATURES: [(&[u8], &str); 8] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x00asm", "application/wasm"),
+    ];
+
+    for (sig, mime) in SIGNATURES.iter() {
+        if head.starts_with(sig) {
+            return mime;
+        }
+    }
+
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    let text = match std::str::from_utf8(head) {
+        Ok(t) => t,
+        // the last character may be cut in half by the read boundary
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+        Err(_) => return OCTET_STREAM,
+    };
+
+    if text
+        .chars()
+        .any(|c| c.is_control() && !c.is_ascii_whitespace())
+    {
+        return OCTET_STREAM;
+    }
+
+    let lead = text.trim_start().to_ascii_lowercase();
+    for tag in ["<!doctype html", "<html", "<head", "<body"].iter() {
+        if lead.starts_with(tag) {
+            return "text/html";
+        }
+    }
+
+    if lead.starts_with("<?xml") {
+        return "application/xml";
+    }
+
+    "text/plain"
+}
+
+#[cfg(test)]
+mod mime_test {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let content = "# comment\n\
+                       text/html\t\thtml htm\n\
+                       application/x-custom  cst;  # trailing\n\
+                       bogus line\n";
+
+        assert_eq!(
+            parse(content),
+            vec![
+                (String::from("html"), String::from("text/html")),
+                (String::from("htm"), String::from("text/html")),
+                (String::from("cst"), String::from("application/x-custom")),
+            ]
+        );
+    }
+
+    #[test]
+    fn sniff_test() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00"), "image/png");
+        assert_eq!(sniff(b"  <!DOCTYPE html><html>"), "text/html");
+        assert_eq!(sniff(b"plain old text\n"), "text/plain");
+        assert_eq!(sniff(b"\x00\x01\x02\x03"), OCTET_STREAM);
+        assert_eq!(sniff("caf\u{e9}".as_bytes()[..4].as_ref()), "text/plain");
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "http2")]
+pub(crate) mod hpack;
 mod scheduler;
 mod trie;
 
@@ -8,10 +10,20 @@ pub mod session;
 
 pub mod locks;
 
+pub(crate) mod cache;
 pub(crate) mod common;
+pub(crate) mod constraint;
 pub(crate) mod debug;
+pub(crate) mod drain;
+pub(crate) mod glob;
+pub(crate) mod limits;
+pub(crate) mod listing;
+pub(crate) mod mime;
+pub(crate) mod negotiate;
+#[cfg(target_os = "linux")]
+pub(crate) mod signals;
 pub(crate) mod shared_pool {
-    pub(crate) use crate::support::scheduler::{close, initialize_with, run};
+    pub(crate) use crate::support::scheduler::{close, initialize_with, run, Pool};
 }
 
 pub(crate) use self::scheduler::{TaskType, ThreadPool, TimeoutPolicy};
@@ -0,0 +1,211 @@
+//! The content negotiation helpers, which will pick the best offer based on the client's preference
+//! from the `Accept`-family request header fields. Each entry in the header field is a range with an
+//! optional quality value, e.g. `text/html;q=0.8`, and for each offer we use the quality value of
+//! the most specific range matching it. The offer with the highest quality value wins, and if tied,
+//! the one listed first by the server wins.
+
+/// Matching a header range against an offer, returning the specificity of the match: the higher
+/// value means the more specific, and `None` means not matching at all.
+type Matcher = fn(&str, &str) -> Option<u8>;
+
+struct Range<'a> {
+    value: &'a str,
+    quality: f32,
+}
+
+/// Pick the index of the best offer based on the header field. If the header field is missing or
+/// empty, all offers are acceptable and the first offer wins.
+pub(crate) fn negotiate(header: Option<&str>, offers: &[&str], matcher: Matcher) -> Option<usize> {
+    if offers.is_empty() {
+        return None;
+    }
+
+    let ranges = match header {
+        Some(raw) if !raw.trim().is_empty() => parse_ranges(raw),
+        _ => return Some(0),
+    };
+
+    let mut best: Option<(usize, f32)> = None;
+
+    for (index, offer) in offers.iter().enumerate() {
+        let quality = match quality_of(&ranges, offer, matcher) {
+            Some(q) if q > 0.0 => q,
+            _ => continue,
+        };
+
+        match best {
+            Some((_, q)) if q >= quality => {}
+            _ => best = Some((index, quality)),
+        }
+    }
+
+    best.map(|(index, _)| index)
+}
+
+/// The quality value of the offer, or `None` if no range in the header field covers it.
+pub(crate) fn quality(header: &str, offer: &str, matcher: Matcher) -> Option<f32> {
+    quality_of(&parse_ranges(header), offer, matcher)
+}
+
+/// Matching media types, e.g. `text/html` against `text/html`, `text/*` or `*/*`.
+pub(crate) fn media_type(range: &str, offer: &str) -> Option<u8> {
+    let offer = offer.split(';').next().unwrap_or("").trim();
+
+    if range == "*/*" || range == "*" {
+        return Some(0);
+    }
+
+    let (r_type, r_sub) = split_media(range)?;
+    let (o_type, o_sub) = split_media(offer)?;
+
+    if !r_type.eq_ignore_ascii_case(o_type) {
+        return None;
+    }
+
+    if r_sub == "*" {
+        Some(1)
+    } else if r_sub.eq_ignore_ascii_case(o_sub) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Matching language tags, e.g. `en-US` against `en-US`, `en` or `*`.
+pub(crate) fn language(range: &str, offer: &str) -> Option<u8> {
+    if range == "*" {
+        return Some(0);
+    }
+
+    if range.eq_ignore_ascii_case(offer) {
+        return Some(3);
+    }
+
+    if is_prefix_tag(range, offer) {
+        // the range `en` covers the offer `en-US`
+        return Some(2);
+    }
+
+    if is_prefix_tag(offer, range) {
+        // the range `en-US` is a close enough match to the offer `en`
+        return Some(1);
+    }
+
+    None
+}
+
+/// Matching tokens for charsets and encodings, e.g. `utf-8` against `UTF-8` or `*`.
+pub(crate) fn token(range: &str, offer: &str) -> Option<u8> {
+    if range == "*" {
+        Some(0)
+    } else if range.eq_ignore_ascii_case(offer) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+fn parse_ranges(raw: &str) -> Vec<Range<'_>> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+
+            let value = parts.next()?.trim();
+            if value.is_empty() {
+                return None;
+            }
+
+            let mut quality = 1.0;
+            for param in parts {
+                let param = param.trim();
+                if param.starts_with("q=") || param.starts_with("Q=") {
+                    quality = param[2..]
+                        .trim()
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|q| !q.is_nan())
+                        .unwrap_or(0.0);
+                }
+            }
+
+            Some(Range {
+                value,
+                quality: quality.clamp(0.0, 1.0),
+            })
+        })
+        .collect()
+}
+
+fn quality_of(ranges: &[Range], offer: &str, matcher: Matcher) -> Option<f32> {
+    let mut best: Option<(u8, f32)> = None;
+
+    for range in ranges.iter() {
+        if let Some(spec) = matcher(range.value, offer) {
+            match best {
+                Some((s, _)) if s >= spec => {}
+                _ => best = Some((spec, range.quality)),
+            }
+        }
+    }
+
+    best.map(|(_, q)| q)
+}
+
+fn split_media(media: &str) -> Option<(&str, &str)> {
+    let mut parts = media.splitn(2, '/');
+
+    match (parts.next(), parts.next()) {
+        (Some(t), Some(s)) if !t.is_empty() && !s.is_empty() => Some((t.trim(), s.trim())),
+        _ => None,
+    }
+}
+
+fn is_prefix_tag(prefix: &str, tag: &str) -> bool {
+    tag.len() > prefix.len()
+        && tag.as_bytes()[prefix.len()] == b'-'
+        && tag[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+#[cfg(test)]
+mod negotiate_test {
+    use super::*;
+
+    #[test]
+    fn media_type_test() {
+        let offers = ["application/json", "text/html"];
+
+        assert_eq!(negotiate(None, &offers, media_type), Some(0));
+        assert_eq!(negotiate(Some("*/*"), &offers, media_type), Some(0));
+        assert_eq!(negotiate(Some("text/html"), &offers, media_type), Some(1));
+        assert_eq!(
+            negotiate(
+                Some("text/html;q=0.9, application/json;q=0.8"),
+                &offers,
+                media_type
+            ),
+            Some(1)
+        );
+        assert_eq!(
+            negotiate(Some("text/*;q=0.5, */*;q=0.1"), &offers, media_type),
+            Some(1)
+        );
+        assert_eq!(
+            negotiate(Some("*/*, application/json;q=0"), &offers, media_type),
+            Some(1)
+        );
+        assert_eq!(negotiate(Some("image/png"), &offers, media_type), None);
+    }
+
+    #[test]
+    fn language_test() {
+        let offers = ["en", "fr-CA"];
+
+        assert_eq!(negotiate(Some("fr"), &offers, language), Some(1));
+        assert_eq!(
+            negotiate(Some("en-US, fr;q=0.5"), &offers, language),
+            Some(0)
+        );
+        assert_eq!(negotiate(Some("de, *;q=0.1"), &offers, language), Some(0));
+        assert_eq!(negotiate(Some("de"), &offers, language), None);
+    }
+}
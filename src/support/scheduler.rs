@@ -5,8 +5,9 @@ use std::thread;
 use std::time::{Duration, SystemTime};
 
 use crate::channel::{self, Receiver, RecvTimeoutError, SendTimeoutError, Sender};
+use crate::core::instance;
 use crate::hashbrown::HashSet;
-use crate::parking_lot::{Mutex, Once, OnceState};
+use crate::parking_lot::Mutex;
 use crate::support::debug::{self, InfoLevel};
 
 const CHAN_SIZE: usize = 512;
@@ -16,7 +17,6 @@ const RETRY_LIMIT: u8 = 64;
 const TIMEOUT: Duration = Duration::from_millis(200);
 const YIELD_DURATION: Duration = Duration::from_millis(128);
 
-static IS_CLOSING: AtomicBool = AtomicBool::new(false);
 static SOFT_POOL_CAP: AtomicUsize = AtomicUsize::new(POOL_CAP);
 
 trait FnBox {
@@ -54,6 +54,7 @@ pub struct ThreadPool {
     ),
     grave: Arc<Mutex<HashSet<usize>>>,
     timeout_policy: TimeoutPolicy,
+    is_closing: Arc<AtomicBool>,
 }
 
 impl ThreadPool {
@@ -65,10 +66,16 @@ impl ThreadPool {
         };
 
         let (sender, receiver) = channel::bounded(CHAN_SIZE);
+        let is_closing = Arc::new(AtomicBool::new(false));
 
         let mut workers = Vec::with_capacity(pool_size);
         (0..pool_size).for_each(|id| {
-            workers.push(Worker::launch(id, receiver.clone(), None));
+            workers.push(Worker::launch(
+                id,
+                receiver.clone(),
+                None,
+                Arc::clone(&is_closing),
+            ));
         });
 
         ThreadPool {
@@ -79,6 +86,7 @@ impl ThreadPool {
             pressure_status: (None, None),
             grave: Arc::new(Mutex::new(HashSet::new())),
             timeout_policy: TimeoutPolicy::Drop,
+            is_closing,
         }
     }
 
@@ -207,6 +215,7 @@ impl ThreadPool {
                     start + id,
                     self.receiver.clone(),
                     Some(self.grave.clone()),
+                    Arc::clone(&self.is_closing),
                 ));
             });
         }
@@ -233,13 +242,14 @@ impl Worker {
         id: usize,
         work_queue: Receiver<Message>,
         grave: Option<Arc<Mutex<HashSet<usize>>>>,
+        is_closing: Arc<AtomicBool>,
     ) -> Worker {
         let thread = thread::spawn(move || {
             let mut idle_counter = 0;
             let mut message: Result<Message, RecvTimeoutError>;
 
             loop {
-                if IS_CLOSING.load(Ordering::Relaxed) {
+                if is_closing.load(Ordering::Relaxed) {
                     return;
                 }
 
@@ -257,7 +267,7 @@ impl Worker {
                             }
                         }
                         Message::Terminate => {
-                            IS_CLOSING.store(true, Ordering::Release);
+                            is_closing.store(true, Ordering::Release);
                             return;
                         }
                     }
@@ -295,13 +305,25 @@ impl Drop for Worker {
     }
 }
 
-struct Pool {
+/// The worker pools shared by the connections of a server instance.
+pub(crate) struct Pool {
     req_workers: ThreadPool,
     resp_workers: ThreadPool,
     parser_workers: ThreadPool,
     stream_workers: ThreadPool,
 }
 
+impl Pool {
+    fn select(&mut self, task: &TaskType) -> &mut ThreadPool {
+        match task {
+            TaskType::Request => &mut self.req_workers,
+            TaskType::Response => &mut self.resp_workers,
+            TaskType::Parser => &mut self.parser_workers,
+            TaskType::StreamLoader => &mut self.stream_workers,
+        }
+    }
+}
+
 pub enum TaskType {
     Request,
     Response,
@@ -309,75 +331,109 @@ pub enum TaskType {
     StreamLoader,
 }
 
-static ONCE: Once = Once::new();
-static mut POOL: Option<Pool> = None;
-
 pub(crate) fn initialize_with(sizes: Vec<usize>) {
-    assert_eq!(
-        ONCE.state(),
-        OnceState::New,
-        ">>> Only 1 instance of the server is allowed per process ... <<<"
-    );
-
-    ONCE.call_once(|| {
-        let pool_sizes: Vec<usize> = sizes
-            .iter()
-            .map(|val| match val {
-                0 => 1,
-                _ => *val,
-            })
-            .collect();
-
-        let (worker_size, parser_size) = match pool_sizes.len() {
-            1 => (pool_sizes[0], pool_sizes[0]),
-            2 => (pool_sizes[0], pool_sizes[1]),
-            _ => panic!("Requiring vec sizes of 2 for each, or 1 for all"),
-        };
+    let instance = instance::current();
+    let mut workers = instance.workers.lock();
 
-        let mut pool = Pool {
-            req_workers: ThreadPool::new(worker_size),
-            resp_workers: ThreadPool::new(worker_size),
-            parser_workers: ThreadPool::new(parser_size),
-            stream_workers: ThreadPool::new(parser_size),
-        };
+    if workers.is_some() {
+        debug::print(
+            "The shared pool of the server has been initialized already",
+            InfoLevel::Warning,
+        );
 
-        pool.resp_workers
-            .toggle_auto_expansion(true, Some(4 * worker_size));
+        return;
+    }
 
-        // Put it in the heap so it can outlive this call
-        unsafe {
-            POOL.replace(pool);
-        }
-    });
+    let pool_sizes: Vec<usize> = sizes
+        .iter()
+        .map(|val| match val {
+            0 => 1,
+            _ => *val,
+        })
+        .collect();
+
+    let (worker_size, parser_size) = match pool_sizes.len() {
+        1 => (pool_sizes[0], pool_sizes[0]),
+        2 => (pool_sizes[0], pool_sizes[1]),
+        _ => panic!("Requiring vec sizes of 2 for each, or 1 for all"),
+    };
+
+    let mut pool = Pool {
+        req_workers: ThreadPool::new(worker_size),
+        resp_workers: ThreadPool::new(worker_size),
+        parser_workers: ThreadPool::new(parser_size),
+        stream_workers: ThreadPool::new(parser_size),
+    };
+
+    pool.resp_workers
+        .toggle_auto_expansion(true, Some(4 * worker_size));
+
+    workers.replace(pool);
 }
 
 pub(crate) fn run<F>(f: F, task: TaskType)
 where
     F: FnOnce() + Send + 'static,
 {
-    unsafe {
-        if let Some(ref mut pool) = POOL {
-            // if pool has been created
-            match task {
-                TaskType::Request => pool.req_workers.execute(f),
-                TaskType::Response => pool.resp_workers.execute(f),
-                TaskType::Parser => pool.parser_workers.execute(f),
-                TaskType::StreamLoader => pool.stream_workers.execute(f),
-            };
-
+    let instance = instance::current();
+
+    // the work is done for the same server instance
+    let bound = Arc::clone(&instance);
+    let f = move || {
+        let _scope = instance::enter(&bound);
+        f();
+    };
+
+    // only the sender is taken from the pool, such that the other connections are not held up by
+    // the lock while the workers are busy and the job is retried.
+    let sender = match *instance.workers.lock() {
+        Some(ref mut pool) => pool.select(&task).sender.clone(),
+        None => {
+            // otherwise, spawn to a new thread for the work;
+            thread::spawn(f);
             return;
         }
+    };
+
+    let mut message = Message::NewJob(Box::new(f));
+    let mut retry = 0;
 
-        // otherwise, spawn to a new thread for the work;
-        thread::spawn(f);
+    while retry < RETRY_LIMIT {
+        match sender.send_timeout(message, Duration::from_millis(1)) {
+            Ok(()) => return,
+            Err(SendTimeoutError::Timeout(msg)) => {
+                debug::print(
+                    "Unable to distribute the job: execution timed out, all workers are busy for too long",
+                    InfoLevel::Warning
+                );
+
+                // slow expansion, where the lock is only held for adding the workers
+                if retry % RETRY_LIMIT / 2 == 0 {
+                    if let Some(ref mut pool) = *instance.workers.lock() {
+                        pool.select(&task).expand();
+                    }
+                }
+
+                message = msg;
+                retry += 1;
+            }
+            Err(SendTimeoutError::Disconnected(_)) => {
+                debug::print(
+                    "Unable to distribute the job: workers have been dropped",
+                    InfoLevel::Error,
+                );
+
+                return;
+            }
+        }
     }
 }
 
 pub(crate) fn close() {
-    unsafe {
-        if let Some(mut pool) = POOL.take() {
-            pool.req_workers.close();
-            pool.resp_workers.close();
-        }
+    let pool = instance::current().workers.lock().take();
+
+    if let Some(mut pool) = pool {
+        pool.req_workers.close();
+        pool.resp_workers.close();
     }
 }
@@ -287,7 +287,7 @@ impl SessionExchangeConfig for ExchangeConfig {
     }
 
     fn auto_clean_is_running() -> bool {
-        AUTO_CLEAN.load(atomic::Ordering::Release)
+        AUTO_CLEAN.load(atomic::Ordering::Acquire)
     }
 }
 
@@ -0,0 +1,203 @@
+//! Translate the Unix signals into the control messages of the server, see
+//! `HttpServer::handle_signals`. The signal handler only writes the signal number into a pipe,
+//! which is async-signal-safe, and the messages are sent from a watcher thread reading the pipe.
+
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::channel::TrySendError;
+use crate::core::states::{AsyncController, ControlMessage, SignalHook};
+use crate::support::debug::{self, InfoLevel};
+
+const SIGNALS: [libc::c_int; 4] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGUSR1];
+
+// give up on the message if it's not picked up within about a second
+const DELIVERY_RETRIES: usize = 100;
+
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    let fd = WRITE_FD.load(Ordering::Acquire);
+    if fd < 0 {
+        return;
+    }
+
+    let byte = sig as u8;
+    unsafe {
+        libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+/// Start watching the signals, and the messages are sent via the courier of the server.
+pub(crate) fn install(courier: AsyncController, on_usr1: Option<SignalHook>) {
+    let mut fds: [libc::c_int; 2] = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        debug::print(
+            "Failed to create the pipe for the signal handler, the signals are not handled",
+            InfoLevel::Warning,
+        );
+
+        return;
+    }
+
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    let previous = WRITE_FD.swap(write_fd, Ordering::AcqRel);
+    if previous >= 0 {
+        // the watcher of the previous server will see the end of its pipe and quit
+        unsafe { libc::close(previous) };
+    }
+
+    thread::spawn(move || {
+        watch(read_fd, courier, on_usr1);
+        unsafe { libc::close(read_fd) };
+    });
+
+    for sig in SIGNALS.iter() {
+        set_handler(
+            *sig,
+            on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Restore the default actions of the signals, and stop the watcher.
+pub(crate) fn uninstall() {
+    for sig in SIGNALS.iter() {
+        set_handler(*sig, libc::SIG_DFL);
+    }
+
+    let fd = WRITE_FD.swap(-1, Ordering::AcqRel);
+    if fd >= 0 {
+        unsafe { libc::close(fd) };
+    }
+}
+
+fn watch(read_fd: libc::c_int, courier: AsyncController, on_usr1: Option<SignalHook>) {
+    let mut byte = 0u8;
+
+    loop {
+        let size = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+
+        if size < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+
+        // the write end is closed, we're done
+        if size <= 0 {
+            return;
+        }
+
+        let delivered = match libc::c_int::from(byte) {
+            libc::SIGTERM | libc::SIGINT => {
+                // the server is draining now, and if the signal comes again, don't wait any longer
+                set_handler(libc::SIGTERM, libc::SIG_DFL);
+                set_handler(libc::SIGINT, libc::SIG_DFL);
+
+                deliver(&courier, ControlMessage::Terminate)
+            }
+            libc::SIGHUP => match courier.try_send(ControlMessage::HotReloadConfig) {
+                // a message is pending, e.g. the reload of the last signal, so the reload is
+                // coalesced into it, and the listener is woken to pick it up.
+                Err(TrySendError::Full(_)) => {
+                    courier.wake();
+                    true
+                }
+                result => result.is_ok(),
+            },
+            libc::SIGUSR1 => {
+                if let Some(hook) = on_usr1 {
+                    hook();
+                }
+
+                true
+            }
+            _ => true,
+        };
+
+        if !delivered {
+            debug::print(
+                &format!("Failed to deliver the control message for signal {}", byte),
+                InfoLevel::Warning,
+            );
+        }
+    }
+}
+
+/// Deliver the message which can't be coalesced, and keep waking the listener till the pending
+/// message is picked up, such that the watcher is never blocked on the courier.
+fn deliver(courier: &AsyncController, message: ControlMessage) -> bool {
+    let mut message = message;
+
+    for _ in 0..DELIVERY_RETRIES {
+        match courier.try_send(message) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(msg)) => {
+                courier.wake();
+                message = msg;
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+
+    false
+}
+
+fn set_handler(sig: libc::c_int, handler: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handler;
+
+        // restart the interrupted system calls, e.g. the `accept` of the listener
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        if libc::sigaction(sig, &action, ptr::null_mut()) != 0 {
+            debug::print(
+                &format!("Failed to set the handler of signal {}", sig),
+                InfoLevel::Warning,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod signals_test {
+    use super::*;
+    use crate::channel;
+    use crate::core::server::HttpServer;
+    use std::net::TcpListener;
+
+    fn raise_signals(_: AsyncController) {
+        // one at a time, otherwise the pending signals of the same kind are merged
+        for sig in [libc::SIGHUP, libc::SIGHUP, libc::SIGTERM].iter() {
+            unsafe { libc::kill(libc::getpid(), *sig) };
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn idle_shutdown_test() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let (tx, rx) = channel::bounded(1);
+
+        thread::spawn(move || {
+            let mut server = HttpServer::new();
+            server.handle_signals(None);
+
+            // no connection is made to the server other than the wake-ups of the signals
+            server.listen_and_serve(port, Some(raise_signals));
+            tx.send(()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_secs(10)).is_ok());
+    }
+}
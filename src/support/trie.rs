@@ -1,12 +1,16 @@
-use crate::core::router::RouteHandler;
+use crate::core::router::{ParamConstraint, RouteHandler};
 use crate::hashbrown::HashMap;
 use crate::regex::Regex;
+use crate::support::glob::Glob;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct Field {
     name: String,
     is_param: bool,
     validation: Option<Regex>,
+    constraint: Option<(String, ParamConstraint)>,
+    pattern: Option<Glob>,
+    catch_all: bool,
 }
 
 impl Field {
@@ -15,6 +19,101 @@ impl Field {
             name,
             is_param,
             validation,
+            constraint: None,
+            pattern: None,
+            catch_all: false,
+        }
+    }
+
+    /// The param with the typed constraint, e.g. `:id<u64>`, where the type name is kept to tell the
+    /// routes apart.
+    pub(crate) fn with_constraint(mut self, constraint: Option<(String, ParamConstraint)>) -> Self {
+        self.constraint = constraint;
+        self
+    }
+
+    /// The segment matching a glob pattern, e.g. `*.json` or `v[0-9]`.
+    pub(crate) fn pattern(name: String, pattern: Glob) -> Self {
+        Field {
+            name,
+            is_param: false,
+            validation: None,
+            constraint: None,
+            pattern: Some(pattern),
+            catch_all: false,
+        }
+    }
+
+    /// The catch-all segment `*name`, which matches the rest of the uri, and its value is saved as
+    /// the param `name` if not empty.
+    pub(crate) fn catch_all(name: String) -> Self {
+        Field {
+            name,
+            is_param: true,
+            validation: None,
+            constraint: None,
+            pattern: None,
+            catch_all: true,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub(crate) fn is_param(&self) -> bool {
+        self.is_param
+    }
+
+    #[inline]
+    pub(crate) fn is_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn is_catch_all(&self) -> bool {
+        self.catch_all
+    }
+
+    /// If the value passes the regex validation and the typed constraint of the param, if any.
+    pub(crate) fn accepts(&self, value: &str) -> bool {
+        if let Some(ref reg) = self.validation {
+            if !reg.is_match(value) {
+                return false;
+            }
+        }
+
+        match self.constraint {
+            Some((_, check)) => check(value),
+            None => true,
+        }
+    }
+
+    fn same_constraint(&self, other: &Field) -> bool {
+        match (&self.constraint, &other.constraint) {
+            (Some((one, _)), Some((two, _))) => one == two,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// If the 2 segments match the same uri segments, i.e. they're equal except for the param names.
+    fn same_shape(&self, other: &Field) -> bool {
+        if self.is_param != other.is_param
+            || self.catch_all != other.catch_all
+            || self.pattern.is_some() != other.pattern.is_some()
+            || (!self.is_param && self.name != other.name)
+            || !self.same_constraint(other)
+        {
+            return false;
+        }
+
+        match (&self.validation, &other.validation) {
+            (Some(reg_one), Some(reg_two)) => reg_one.as_str() == reg_two.as_str(),
+            (None, None) => true,
+            _ => false,
         }
     }
 }
@@ -23,7 +122,10 @@ impl PartialEq for Field {
     fn eq(&self, other: &Field) -> bool {
         if self.name != other.name
             || self.is_param != other.is_param
+            || self.catch_all != other.catch_all
+            || self.pattern.is_some() != other.pattern.is_some()
             || self.validation.is_some() != other.validation.is_some()
+            || !self.same_constraint(other)
         {
             return false;
         }
@@ -38,13 +140,16 @@ impl PartialEq for Field {
     }
 }
 
+#[derive(Clone)]
 struct Node {
     field: Field,
     handler: RouteHandler,
     //    callback: Option<Callback>,
     //    location: Option<PathBuf>,
     named_children: HashMap<String, Node>,
+    pattern_children: Vec<Node>,
     params_children: Vec<Node>,
+    catch_all: Option<Box<Node>>,
 }
 
 impl Node {
@@ -55,7 +160,9 @@ impl Node {
             //            callback,
             //            location,
             named_children: HashMap::new(),
+            pattern_children: Vec::new(),
             params_children: Vec::new(),
+            catch_all: None,
         }
     }
 
@@ -67,35 +174,185 @@ impl Node {
             None => return,
         };
 
-        // if already has this child, keep calling insert recursively. Only do this when not
-        // a params, otherwise, always create a new branch
-        if !head.is_param {
-            if let Some(child) = self.named_children.get_mut(&head.name) {
-                if segments.is_empty() {
-                    // done, update the node
-                    if child.handler.is_some() {
-                        panic!("Key collision!");
-                    }
-
-                    child.handler = handler;
-                } else {
-                    // recursive insert to the child
-                    child.insert(segments, handler);
+        // the catch-all segment is always the last one, and there can be only one per node
+        if head.catch_all {
+            if self.catch_all.is_some() {
+                panic!("Key collision!");
+            }
+
+            self.catch_all = Some(Box::new(Node::new(head, handler)));
+            return;
+        }
+
+        // if already has this child, keep calling insert recursively, otherwise create a new branch
+        let child = if head.pattern.is_some() {
+            self.pattern_children.iter_mut().find(|c| c.field == head)
+        } else if head.is_param {
+            self.params_children.iter_mut().find(|c| c.field == head)
+        } else {
+            self.named_children.get_mut(&head.name)
+        };
+
+        if let Some(child) = child {
+            if segments.is_empty() {
+                // done, update the node
+                if child.handler.is_some() {
+                    panic!("Key collision!");
                 }
 
-                return;
+                child.handler = handler;
+            } else {
+                // recursive insert to the child
+                child.insert(segments, handler);
             }
 
+            return;
+        }
+
+        if head.pattern.is_some() {
+            self.pattern_children
+                .push(Node::build_new_child(head, segments, handler));
+        } else if head.is_param {
+            self.params_children
+                .push(Node::build_new_child(head, segments, handler));
+        } else {
             self.named_children.insert(
                 head.name.clone(),
                 Node::build_new_child(head, segments, handler),
             );
+        }
+    }
 
-            return;
+    /// Merge the handler into the node of the exact same segments, including the param names.
+    fn merge(&mut self, segments: &[Field], handler: RouteHandler) -> bool {
+        let (head, rest) = match segments.split_last() {
+            Some(parts) => parts,
+            None => return self.handler.merge(handler),
+        };
+
+        let child = if head.catch_all {
+            self.catch_all.as_mut().map(|c| c.as_mut())
+        } else if head.pattern.is_some() {
+            self.pattern_children.iter_mut().find(|c| c.field == *head)
+        } else if head.is_param {
+            self.params_children.iter_mut().find(|c| c.field == *head)
+        } else {
+            self.named_children.get_mut(&head.name)
+        };
+
+        match child {
+            Some(c) if c.field == *head => c.merge(rest, handler),
+            _ => false,
         }
+    }
 
-        self.params_children
-            .push(Node::build_new_child(head, segments, handler));
+    /// If a route of the same shape has been added, i.e. one that would match the same uri, which
+    /// only differs in the param names.
+    fn contains(&self, segments: &[Field]) -> bool {
+        let (head, rest) = match segments.split_last() {
+            Some(parts) => parts,
+            None => return self.handler.is_some(),
+        };
+
+        let found = |child: &Node| {
+            if rest.is_empty() {
+                child.handler.is_some()
+            } else {
+                child.contains(rest)
+            }
+        };
+
+        if head.catch_all {
+            return self.catch_all.is_some();
+        }
+
+        if head.pattern.is_some() {
+            return self
+                .pattern_children
+                .iter()
+                .any(|c| c.field.same_shape(head) && found(c));
+        }
+
+        if head.is_param {
+            return self
+                .params_children
+                .iter()
+                .any(|c| c.field.same_shape(head) && found(c));
+        }
+
+        self.named_children.get(&head.name).is_some_and(found)
+    }
+
+    /// Remove the route of the exact same segments, and prune the branches left without any route.
+    fn remove(&mut self, segments: &[Field]) -> bool {
+        let (head, rest) = match segments.split_last() {
+            Some(parts) => parts,
+            None => return false,
+        };
+
+        if head.catch_all {
+            return match self.catch_all {
+                Some(ref c) if c.field == *head => self.catch_all.take().is_some(),
+                _ => false,
+            };
+        }
+
+        let detach = |child: &mut Node| {
+            if !rest.is_empty() {
+                return child.remove(rest);
+            }
+
+            if child.handler.is_none() {
+                return false;
+            }
+
+            child.handler = RouteHandler::default();
+            true
+        };
+
+        if head.pattern.is_some() || head.is_param {
+            let children = if head.is_param {
+                &mut self.params_children
+            } else {
+                &mut self.pattern_children
+            };
+
+            let pos = match children.iter().position(|c| c.field == *head) {
+                Some(pos) => pos,
+                None => return false,
+            };
+
+            let removed = detach(&mut children[pos]);
+            if children[pos].is_bare() {
+                children.remove(pos);
+            }
+
+            return removed;
+        }
+
+        let removed = match self.named_children.get_mut(&head.name) {
+            Some(child) => detach(child),
+            None => return false,
+        };
+
+        if self
+            .named_children
+            .get(&head.name)
+            .is_some_and(|c| c.is_bare())
+        {
+            self.named_children.remove(&head.name);
+        }
+
+        removed
+    }
+
+    /// If the node leads to no route at all.
+    fn is_bare(&self) -> bool {
+        self.handler.is_none()
+            && self.named_children.is_empty()
+            && self.pattern_children.is_empty()
+            && self.params_children.is_empty()
+            && self.catch_all.is_none()
     }
 
     fn build_new_child(field: Field, segments: Vec<Field>, handler: RouteHandler) -> Node {
@@ -114,6 +371,7 @@ impl Node {
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct RouteTrie {
     root: Node,
 }
@@ -130,12 +388,32 @@ impl RouteTrie {
 
     #[inline]
     pub(crate) fn is_empty(&self) -> bool {
-        self.root.named_children.is_empty() && self.root.params_children.is_empty()
+        self.root.named_children.is_empty()
+            && self.root.pattern_children.is_empty()
+            && self.root.params_children.is_empty()
+            && self.root.catch_all.is_none()
     }
 
-    #[inline]
-    pub(crate) fn add(&mut self, segments: Vec<Field>, handler: RouteHandler) {
+    /// Add the route to the trie, or return `false` if a route of the same shape exists already,
+    /// in which case the trie is not changed.
+    pub(crate) fn add(&mut self, segments: Vec<Field>, handler: RouteHandler) -> bool {
+        if segments.is_empty() {
+            return false;
+        }
+
+        if self.root.contains(&segments) {
+            // only the guarded routes can share the same path, see `RouteHandler::merge`
+            return self.root.merge(&segments, handler);
+        }
+
         self.root.insert(segments, handler);
+        true
+    }
+
+    /// Remove the route of the exact same segments, including the param names, and return `false`
+    /// if there's no such route.
+    pub(crate) fn remove(&mut self, segments: &[Field]) -> bool {
+        self.root.remove(segments)
     }
 
     pub(crate) fn find(
@@ -146,6 +424,9 @@ impl RouteTrie {
         RouteTrie::recursive_find(&route_head.root, segments, params)
     }
 
+    /// Find the handler in the order of precedence: the static segment, then the glob pattern
+    /// segments, then the params, then the catch-all. If a branch can't lead to a handler, we will
+    /// backtrack and try the next one in this order.
     fn recursive_find(
         root: &Node,
         segments: &[String],
@@ -156,14 +437,30 @@ impl RouteTrie {
         }
 
         let head = &segments[0];
-        let is_tail = segments.len() <= 1;
+        let rest = &segments[1..];
 
         if let Some(child) = root.named_children.get(head) {
-            if is_tail {
-                return child.handler.clone();
+            let result = RouteTrie::descend(child, rest, params);
+            if result.is_some() {
+                return result;
+            }
+        }
+
+        // the empty segment is the trailing slash, which only matches the route ending with one
+        if head.is_empty() {
+            return RouteHandler::default();
+        }
+
+        for pattern_node in root.pattern_children.iter() {
+            match pattern_node.field.pattern {
+                Some(ref pattern) if pattern.is_match(head) => {}
+                _ => continue,
             }
 
-            return RouteTrie::recursive_find(&child, &segments[1..], params);
+            let result = RouteTrie::descend(pattern_node, rest, params);
+            if result.is_some() {
+                return result;
+            }
         }
 
         for param_node in root.params_children.iter() {
@@ -171,19 +468,11 @@ impl RouteTrie {
                 continue;
             }
 
-            if let Some(ref reg) = param_node.field.validation {
-                if !reg.is_match(head) {
-                    continue;
-                }
+            if !param_node.field.accepts(head) {
+                continue;
             }
 
-            let result = if is_tail {
-                //                RouteHandler::new(param_node.callback, param_node.location.clone())
-                param_node.handler.clone()
-            } else {
-                RouteTrie::recursive_find(param_node, &segments[1..], params)
-            };
-
+            let result = RouteTrie::descend(param_node, rest, params);
             if result.is_some() {
                 params
                     .entry(param_node.field.name.clone())
@@ -192,6 +481,116 @@ impl RouteTrie {
             }
         }
 
+        if let Some(ref catch_all) = root.catch_all {
+            // nor does the catch-all take the trailing slash
+            if segments.last().is_some_and(String::is_empty) {
+                return RouteHandler::default();
+            }
+
+            if !catch_all.field.name.is_empty() {
+                params
+                    .entry(catch_all.field.name.clone())
+                    .or_insert_with(|| segments.join("/"));
+            }
+
+            return catch_all.handler.clone();
+        }
+
         RouteHandler::default()
     }
+
+    fn descend(node: &Node, rest: &[String], params: &mut HashMap<String, String>) -> RouteHandler {
+        if rest.is_empty() {
+            return node.handler.clone();
+        }
+
+        RouteTrie::recursive_find(node, rest, params)
+    }
+}
+
+#[cfg(test)]
+mod trie_test {
+    use super::{Field, RouteTrie};
+    use crate::core::router::RouteHandler;
+    use crate::hashbrown::HashMap;
+    use crate::regex::Regex;
+    use crate::support::glob::Glob;
+    use std::path::PathBuf;
+
+    fn handler(tag: &str) -> RouteHandler {
+        RouteHandler::new(None, Some(PathBuf::from(tag)))
+    }
+
+    fn find(trie: &RouteTrie, uri: &str) -> (Option<PathBuf>, HashMap<String, String>) {
+        let segments: Vec<String> = uri.trim_matches('/').split('/').map(String::from).collect();
+        let mut params = HashMap::new();
+        let result = RouteTrie::find(trie, &segments, &mut params);
+
+        (result.path().cloned(), params)
+    }
+
+    #[test]
+    fn precedence_test() {
+        let mut trie = RouteTrie::initialize();
+        let field = |name: &str| Field::new(String::from(name), false, None);
+        let param = |name: &str| Field::new(String::from(name), true, None);
+
+        // fields are in the reversed order
+        trie.add(vec![field("me"), field("users")], handler("static"));
+        trie.add(
+            vec![
+                Field::pattern(String::from("*.json"), Glob::new("*.json").unwrap()),
+                field("users"),
+            ],
+            handler("pattern"),
+        );
+        trie.add(
+            vec![field("posts"), param("id"), field("users")],
+            handler("param"),
+        );
+        trie.add(
+            vec![Field::catch_all(String::from("rest")), field("users")],
+            handler("catch_all"),
+        );
+
+        assert_eq!(find(&trie, "/users/me").0, Some(PathBuf::from("static")));
+        assert_eq!(
+            find(&trie, "/users/me.json").0,
+            Some(PathBuf::from("pattern"))
+        );
+
+        let (result, params) = find(&trie, "/users/42/posts");
+        assert_eq!(result, Some(PathBuf::from("param")));
+        assert_eq!(params.get("id"), Some(&String::from("42")));
+
+        // the param branch can't match, so backtrack to the catch-all
+        let (result, params) = find(&trie, "/users/42/likes/7");
+        assert_eq!(result, Some(PathBuf::from("catch_all")));
+        assert_eq!(params.get("rest"), Some(&String::from("42/likes/7")));
+        assert!(params.get("id").is_none());
+
+        assert_eq!(find(&trie, "/users").0, None);
+    }
+
+    #[test]
+    fn conflict_test() {
+        let mut trie = RouteTrie::initialize();
+        let field = |name: &str| Field::new(String::from(name), false, None);
+        let param = |name: &str| Field::new(String::from(name), true, None);
+
+        assert!(trie.add(vec![field("posts"), param("id")], handler("one")));
+        assert!(trie.add(vec![field("likes"), param("id")], handler("two")));
+
+        // only differs in the param name
+        assert!(!trie.add(vec![field("posts"), param("uid")], handler("three")));
+        assert!(!trie.add(vec![field("likes"), param("id")], handler("four")));
+        assert_eq!(find(&trie, "/42/posts").0, Some(PathBuf::from("one")));
+
+        // the validation makes it a different route
+        let digits = Field::new(String::from("id"), true, Some(Regex::new(r"\d+").unwrap()));
+        assert!(trie.add(vec![field("posts"), digits], handler("five")));
+
+        assert!(trie.add(vec![Field::catch_all(String::from("rest"))], handler("six")));
+        assert!(!trie.add(vec![Field::catch_all(String::new())], handler("seven")));
+    }
 }